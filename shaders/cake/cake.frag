@@ -15,6 +15,12 @@ layout(push_constant) uniform PushConstants {
     int end_time;
     int screen_width;
     int screen_height;
+    int velocity_mode;
+    int note_style;
+    int border_color;
+    float border_scale;
+    float shading;
+    int scroll_direction;
 } consts;
 
 layout(set = 0, binding = 0) readonly buffer BufferArray
@@ -22,7 +28,7 @@ layout(set = 0, binding = 0) readonly buffer BufferArray
     ivec4 BinTree[];
 } buffers[256];
 
-const float pi = 3.1415926535897;
+#include <note_style.glsl>
 
 ivec4 getNoteAt(int time) {
     int nextIndex = buffers[buffer_index].BinTree[0].x;
@@ -68,11 +74,13 @@ void main()
         frag_color = vec3(((note.z >> 16) & 0xFF) / 255.0, ((note.z >> 8) & 0xFF) / 255.0, (note.z & 0xFF) / 255.0);
     }
 
-    vec3 color = frag_color;
-
-    // Adjust color
-
-    color *= (1.0 + cos(pi * 0.5 * v_uv.x)) * 0.5;
+    float velocity = mix(0.2, 1.0, float(note.w) / 127.0);
+    float alpha = 1.0;
+    if (consts.velocity_mode == 1) {
+        frag_color *= velocity;
+    } else if (consts.velocity_mode == 2) {
+        alpha = velocity;
+    }
 
     // Check borders
 
@@ -88,13 +96,18 @@ void main()
 
     float min_x_dist = min(note_left_dist, note_right_dist) * consts.screen_width;
     float min_y_dist = min(note_top_dist, note_bottom_dist) * consts.screen_height;
-    float min_dist = min(min_x_dist, min_y_dist);
 
-    if(min_dist < border_width) {
-        color = frag_color * 0.2;
-    }
+    vec3 color = style_note(
+        frag_color,
+        v_uv.x,
+        vec2(min_x_dist, min_y_dist),
+        float(border_width) * consts.border_scale,
+        int(consts.note_style),
+        consts.border_color,
+        consts.shading
+    );
 
     // Square for SRGB
     color *= color;
-    fsout_Color = vec4(color, 1);
+    fsout_Color = vec4(color, alpha);
 }
@@ -0,0 +1,32 @@
+#version 450
+
+layout(location = 0) in vec4 frag_color;
+// X goes across the keys, y goes away from the keyboard
+layout(location = 1) in vec2 frag_tex_coord;
+layout(location = 2) flat in uint frag_kind;
+
+layout(location = 0) out vec4 out_color;
+
+const uint KIND_GLOW = 0;
+const uint KIND_BEAM = 1;
+const uint KIND_PARTICLE = 2;
+
+void main() {
+    vec2 uv = frag_tex_coord;
+    float across = abs(uv.x * 2.0 - 1.0);
+    float strength;
+
+    if (frag_kind == KIND_GLOW) {
+        // Half of a soft circle, centered on the edge of the keyboard
+        strength = max(0.0, 1.0 - length(vec2(across, uv.y)));
+        strength *= strength;
+    } else if (frag_kind == KIND_BEAM) {
+        float fade = 1.0 - uv.y;
+        strength = sqrt(1.0 - across) * fade * fade;
+    } else {
+        strength = max(0.0, 1.0 - length(uv * 2.0 - 1.0));
+        strength *= strength;
+    }
+
+    out_color = vec4(frag_color.rgb, frag_color.a * strength);
+}
@@ -15,6 +15,12 @@ layout(push_constant) uniform PushConstants {
     int end_time;
     int screen_width;
     int screen_height;
+    int velocity_mode;
+    int note_style;
+    int border_color;
+    float border_scale;
+    float shading;
+    int scroll_direction;
 } consts;
 
 layout(set = 0, binding = 0) readonly buffer BufferData
@@ -22,7 +28,7 @@ layout(set = 0, binding = 0) readonly buffer BufferData
     int BinTree[];
 };
 
-const float pi = 3.1415926535897;
+#include <note_style.glsl>
 
 ivec4 getNoteAt(int time) {
     int nextIndex = tree_offset + BinTree[tree_offset];
@@ -48,7 +54,8 @@ ivec4 getNoteAt(int time) {
     int end = BinTree[nextIndex + 1];
     int color = BinTree[nextIndex + 2];
 
-    return ivec4(start, end, color, 0);
+    // The velocity is stored in the upper 8 bits of the color
+    return ivec4(start, end, color, (color >> 24) & 0xFF);
 }
 
 float ticks_to_screen_y(int ticks) {
@@ -70,11 +77,13 @@ void main()
         frag_color = vec3(((note.z >> 16) & 0xFF) / 255.0, ((note.z >> 8) & 0xFF) / 255.0, (note.z & 0xFF) / 255.0);
     }
 
-    vec3 color = frag_color;
-
-    // Adjust color
-
-    color *= (1.0 + cos(pi * 0.5 * v_uv.x)) * 0.5;
+    float velocity = mix(0.2, 1.0, float(note.w) / 127.0);
+    float alpha = 1.0;
+    if (consts.velocity_mode == 1) {
+        frag_color *= velocity;
+    } else if (consts.velocity_mode == 2) {
+        alpha = velocity;
+    }
 
     // Check borders
 
@@ -90,13 +99,18 @@ void main()
 
     float min_x_dist = min(note_left_dist, note_right_dist) * consts.screen_width;
     float min_y_dist = min(note_top_dist, note_bottom_dist) * consts.screen_height;
-    float min_dist = min(min_x_dist, min_y_dist);
 
-    if(min_dist < border_width) {
-        color = frag_color * 0.2;
-    }
+    vec3 color = style_note(
+        frag_color,
+        v_uv.x,
+        vec2(min_x_dist, min_y_dist),
+        float(border_width) * consts.border_scale,
+        int(consts.note_style),
+        consts.border_color,
+        consts.shading
+    );
 
     // Square for SRGB
     color *= color;
-    fsout_Color = vec4(color, 1);
+    fsout_Color = vec4(color, alpha);
 }
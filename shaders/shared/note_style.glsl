@@ -0,0 +1,57 @@
+const float pi = 3.1415926535897;
+
+const int STYLE_FLAT = 0;
+const int STYLE_GRADIENT = 1;
+const int STYLE_ROUNDED = 2;
+const int STYLE_OUTLINED = 3;
+const int STYLE_GLOW = 4;
+
+// Colors a pixel of a note with the selected note style. The distances to the
+// closest vertical and horizontal edges of the note are in pixels, and a negative
+// border color keeps the border in the color of the note.
+vec3 style_note(
+    vec3 note_color,
+    float uv_x,
+    vec2 edge_dist,
+    float border,
+    int style,
+    int border_rgb,
+    float shading
+) {
+    vec3 color = note_color;
+    vec3 border_color = note_color * 0.2;
+
+    if (style == STYLE_GRADIENT || style == STYLE_ROUNDED) {
+        color *= mix(1.0, (1.0 + cos(pi * 0.5 * uv_x)) * 0.5, shading);
+    } else if (style == STYLE_OUTLINED) {
+        color *= 1.0 - 0.8 * shading;
+        border_color = note_color;
+    } else if (style == STYLE_GLOW) {
+        float center = 1.0 - abs(uv_x * 2.0 - 1.0);
+        color = mix(color, vec3(1.0), center * center * 0.6 * shading);
+        border_color = note_color * mix(1.0, 0.2, shading);
+    }
+
+    if (border_rgb >= 0) {
+        border_color = vec3(
+            ((border_rgb >> 16) & 0xFF) / 255.0,
+            ((border_rgb >> 8) & 0xFF) / 255.0,
+            (border_rgb & 0xFF) / 255.0
+        );
+    }
+
+    float dist = min(edge_dist.x, edge_dist.y);
+    if (style == STYLE_ROUNDED) {
+        float radius = border * 2.0 + 2.0;
+        float corner_dist = length(max(vec2(radius) - edge_dist, 0.0));
+        if (corner_dist > radius) {
+            discard;
+        }
+        dist = min(dist, radius - corner_dist);
+    }
+
+    if (dist < border) {
+        color = border_color;
+    }
+    return color;
+}
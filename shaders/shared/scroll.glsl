@@ -0,0 +1,11 @@
+// Moves a position of the downward layout to the scroll direction. The upward
+// layout is mirrored vertically, and the horizontal one has the keys along the
+// height and the time along the width.
+vec4 scroll_position(vec2 pos, int direction) {
+    if (direction == 1) {
+        pos.y = -pos.y;
+    } else if (direction == 2) {
+        pos = vec2(-pos.y, -pos.x);
+    }
+    return vec4(pos, 0, 1);
+}
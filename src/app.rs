@@ -117,6 +117,9 @@ impl ApplicationHandler for WasabiApplication {
                     renderer.resize(None);
                 }
                 WindowEvent::CloseRequested => {
+                    renderer
+                        .gui_window()
+                        .save_file_profile(&self.settings, &self.state);
                     event_loop.exit();
                 }
                 WindowEvent::DroppedFile(ref path) => {
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use super::WasabiAudioPlayer;
+
+const CHANNEL_KEYS: usize = 256;
+
+/// Forwards the events of one MIDI layer into the shared player of the group, and
+/// keeps track of the notes it holds so they can be released without resetting the
+/// notes of the other layers.
+pub struct LayerPlayer {
+    parent: Arc<WasabiAudioPlayer>,
+    /// Number of playing notes of each key of each channel
+    held: Vec<u32>,
+}
+
+impl LayerPlayer {
+    pub fn new(parent: Arc<WasabiAudioPlayer>) -> Self {
+        Self {
+            parent,
+            held: vec![0; 16 * CHANNEL_KEYS],
+        }
+    }
+
+    pub fn parent(&self) -> &Arc<WasabiAudioPlayer> {
+        &self.parent
+    }
+
+    pub fn push_events(&mut self, data: impl Iterator<Item = u32>) {
+        let held = &mut self.held;
+        self.parent.push_events(data.inspect(|&event| {
+            let index = (event & 0x0F) as usize * CHANNEL_KEYS + ((event >> 8) & 0xFF) as usize;
+            let velocity = (event >> 16) & 0xFF;
+            match event & 0xF0 {
+                0x90 if velocity > 0 => held[index] += 1,
+                0x80 | 0x90 => held[index] = held[index].saturating_sub(1),
+                _ => {}
+            }
+        }));
+    }
+
+    /// Sends a note off for every note of this layer which is still playing
+    pub fn release_notes(&mut self) {
+        let note_offs = self.held.iter().enumerate().flat_map(|(index, &count)| {
+            let channel = (index / CHANNEL_KEYS) as u32;
+            let key = (index % CHANNEL_KEYS) as u32;
+            std::iter::repeat_n(0x80 | channel | (key << 8), count as usize)
+        });
+        self.parent.push_events(note_offs);
+        self.held.fill(0);
+    }
+}
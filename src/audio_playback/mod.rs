@@ -1,10 +1,17 @@
-use std::sync::{Arc, RwLock};
+use std::sync::{
+    atomic::{AtomicBool, AtomicI32, Ordering},
+    Arc, RwLock,
+};
 
 use crate::{
     gui::window::{GuiMessageSystem, LoadingStatus},
+    midi::transpose_audio_event,
     settings::{Synth, SynthSettings, WasabiSoundfont},
 };
 
+mod layer;
+pub use layer::*;
+
 mod xsynth;
 pub use xsynth::*;
 
@@ -24,37 +31,93 @@ enum MidiAudioPlayer {
     Kdmapi(KdmapiPlayer),
     #[cfg(all(supported_os, not(target_os = "freebsd")))]
     MidiDevice(MidiDevicePlayer),
+    /// Forwards the events into the shared player of a group of layered MIDIs
+    Layer(LayerPlayer),
     None,
 }
 
-pub struct WasabiAudioPlayer(RwLock<MidiAudioPlayer>);
+pub struct WasabiAudioPlayer {
+    player: RwLock<MidiAudioPlayer>,
+    /// Semitones added to the keys of all the outgoing note events
+    transpose: AtomicI32,
+    muted: AtomicBool,
+}
 
 impl WasabiAudioPlayer {
     pub fn empty() -> Arc<Self> {
-        Arc::new(Self(RwLock::new(MidiAudioPlayer::None)))
+        Arc::new(Self {
+            player: RwLock::new(MidiAudioPlayer::None),
+            transpose: AtomicI32::new(0),
+            muted: AtomicBool::new(false),
+        })
+    }
+
+    /// A player for a single MIDI layer, which plays through `parent` but can be
+    /// muted on its own
+    pub fn new_layer(parent: &Arc<WasabiAudioPlayer>) -> Arc<Self> {
+        Arc::new(Self {
+            player: RwLock::new(MidiAudioPlayer::Layer(LayerPlayer::new(parent.clone()))),
+            transpose: AtomicI32::new(0),
+            muted: AtomicBool::new(false),
+        })
     }
 
     pub fn voice_count(&self) -> Option<u64> {
-        match &*self.0.read().unwrap() {
+        match &*self.player.read().unwrap() {
             MidiAudioPlayer::XSynth(player) => Some(player.voice_count()),
             MidiAudioPlayer::Kdmapi(player) => player.voice_count(),
+            MidiAudioPlayer::Layer(layer) => layer.parent().voice_count(),
             _ => None,
         }
     }
 
+    /// Sets the live transpose of the outgoing notes. The synth is reset when it
+    /// changes, as the note offs of the playing notes would go to different keys.
+    pub fn set_transpose(&self, semitones: i32) {
+        if self.transpose.swap(semitones, Ordering::Relaxed) != semitones {
+            self.reset();
+        }
+    }
+
+    /// Mutes or unmutes the outgoing events. The playing notes are released when
+    /// muting, so they don't hang without their note offs. A layer only releases its
+    /// own notes, the other synths are reset.
+    pub fn set_muted(&self, muted: bool) {
+        if self.muted.swap(muted, Ordering::Relaxed) != muted && muted {
+            let mut player = self.player.write().unwrap();
+            if let MidiAudioPlayer::Layer(layer) = &mut *player {
+                layer.release_notes();
+            } else {
+                drop(player);
+                self.reset();
+            }
+        }
+    }
+
     pub fn push_events(&self, data: impl Iterator<Item = u32>) {
-        match &mut *self.0.write().unwrap() {
+        // Checked while holding the player, so no notes of a layer are sent after
+        // it released them for muting
+        let mut player = self.player.write().unwrap();
+        if self.muted.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let transpose = self.transpose.load(Ordering::Relaxed);
+        let data = data.filter_map(|e| transpose_audio_event(e, transpose));
+
+        match &mut *player {
             MidiAudioPlayer::XSynth(player) => player.push_events(data),
             #[cfg(supported_os)]
             MidiAudioPlayer::Kdmapi(player) => player.push_events(data),
             #[cfg(all(supported_os, not(target_os = "freebsd")))]
             MidiAudioPlayer::MidiDevice(player) => player.push_events(data),
+            MidiAudioPlayer::Layer(layer) => layer.push_events(data),
             _ => {}
         }
     }
 
     pub fn configure(&self, settings: &SynthSettings) {
-        match &mut *self.0.write().unwrap() {
+        match &mut *self.player.write().unwrap() {
             MidiAudioPlayer::XSynth(player) => player.configure(&settings.xsynth),
             #[cfg(supported_os)]
             MidiAudioPlayer::Kdmapi(player) => player.configure(&settings.kdmapi),
@@ -68,7 +131,7 @@ impl WasabiAudioPlayer {
         loading_status: Arc<LoadingStatus>,
         errors: Arc<GuiMessageSystem>,
     ) {
-        match &mut *self.0.write().unwrap() {
+        match &mut *self.player.write().unwrap() {
             MidiAudioPlayer::XSynth(player) => {
                 player.set_soundfonts(soundfonts, loading_status, errors)
             }
@@ -78,13 +141,16 @@ impl WasabiAudioPlayer {
         }
     }
 
+    /// Cuts the playing notes. A layer only releases its own notes.
     pub fn reset(&self) {
-        match &mut *self.0.write().unwrap() {
+        match &mut *self.player.write().unwrap() {
             MidiAudioPlayer::XSynth(player) => player.reset(),
             #[cfg(supported_os)]
             MidiAudioPlayer::Kdmapi(player) => player.reset(),
             #[cfg(all(supported_os, not(target_os = "freebsd")))]
             MidiAudioPlayer::MidiDevice(player) => player.reset(),
+            // The other layers keep playing through the shared player
+            MidiAudioPlayer::Layer(layer) => layer.release_notes(),
             _ => {}
         }
     }
@@ -96,7 +162,7 @@ impl WasabiAudioPlayer {
         errors: Arc<GuiMessageSystem>,
     ) {
         // First drop the previous synth to avoid any loading errors
-        *self.0.write().unwrap() = MidiAudioPlayer::None;
+        *self.player.write().unwrap() = MidiAudioPlayer::None;
 
         // Create the new synth object based on the settings
         let synth = match settings.synth {
@@ -123,7 +189,7 @@ impl WasabiAudioPlayer {
         };
 
         // Apply the synth to the struct
-        *self.0.write().unwrap() = synth;
+        *self.player.write().unwrap() = synth;
 
         // Configure the synth and load the soundfont list
         self.configure(settings);
@@ -1,15 +1,21 @@
+pub mod background;
 pub mod fps;
+mod grid;
 mod keyboard;
 pub mod keyboard_layout;
+mod layers;
 pub mod scene;
 pub mod stats;
 
 mod about;
 mod errors;
+pub mod export;
 mod loading;
+pub mod palette_editor;
 mod playback_panel;
 mod render;
 pub mod render_state;
+mod seek_bar;
 mod settings;
 mod shortcuts;
 pub use errors::*;
@@ -27,29 +33,50 @@ use time::Duration;
 use tokio::sync::{oneshot, oneshot::Receiver};
 
 use crate::{
+    audio_playback::WasabiAudioPlayer,
     gui::{
-        window::{keyboard::GuiKeyboard, scene::GuiRenderScene},
+        window::{
+            background::GuiBackground,
+            grid::draw_beat_grid,
+            keyboard::GuiKeyboard,
+            keyboard_layout::{KeyboardParams, KeyboardView},
+            scene::GuiRenderScene,
+        },
         GuiRenderer, GuiState,
     },
     midi::{
-        CakeMIDIFile, InRamMIDIFile, LiveLoadMIDIFile, MIDIFileBase, MIDIFileUnion, PieMIDIFile,
+        choose_parsing, CakeMIDIFile, InRamMIDIFile, LiveLoadMIDIFile, LoadProgress, MIDIFileBase,
+        MIDIFileGroup, MIDIFileUnion, MIDILayer, MidiParser, PieMIDIFile,
     },
-    settings::{MidiParsing, WasabiSettings},
+    renderer::devices::GpuInfo,
+    settings::{FileProfile, FileProfiles, MidiSettings, ScrollDirection, WasabiSettings},
     state::WasabiState,
     utils::NOTE_SPEED_RANGE,
+    video_render::screenshot::{take_screenshot, ScreenshotConfig},
 };
 
 pub struct GuiWasabiWindow {
-    render_scene: GuiRenderScene,
+    /// One scene for each layer of the MIDI group
+    render_scenes: Vec<GuiRenderScene>,
     keyboard_layout: keyboard_layout::KeyboardLayout,
+    /// The parameters the keyboard layout was built with
+    keyboard_params: KeyboardParams,
     keyboard: GuiKeyboard,
-    midi_file: Option<MIDIFileUnion>,
+    background: GuiBackground,
+    midi_file: Option<MIDIFileGroup>,
+    /// Path of the last MIDI passed to the loader
+    midi_path: Option<PathBuf>,
     fps: fps::Fps,
     nps: stats::NpsCounter,
 
     settings_win: SettingsWindow,
     midi_picker: Option<Receiver<PathBuf>>,
-    midi_loader: Option<Receiver<MIDIFileUnion>>,
+    midi_loader: Option<Receiver<MIDILayer>>,
+    /// Whether the picked or loaded MIDI is added to the group as a new layer
+    loading_layer: bool,
+    profiles: FileProfiles,
+    /// The GPU drawing the window
+    gpu: GpuInfo,
 }
 
 impl GuiWasabiWindow {
@@ -67,6 +94,9 @@ impl GuiWasabiWindow {
         settings_win
             .load_midi_devices(settings)
             .unwrap_or_else(|e| state.errors.warning(e.to_string()));
+        settings_win
+            .load_gpus(renderer.device.instance())
+            .unwrap_or_else(|e| state.errors.warning(e.to_string()));
 
         state.synth.switch(
             &settings.synth,
@@ -74,17 +104,30 @@ impl GuiWasabiWindow {
             state.errors.clone(),
         );
 
+        let profiles = FileProfiles::load().unwrap_or_else(|e| {
+            state.errors.warning(e.to_string());
+            FileProfiles::default()
+        });
+
+        let keyboard_params = KeyboardParams::from(&settings.scene.keyboard);
+
         GuiWasabiWindow {
-            render_scene: GuiRenderScene::new(renderer),
-            keyboard_layout: keyboard_layout::KeyboardLayout::new(&Default::default()),
+            render_scenes: vec![GuiRenderScene::new(renderer)],
+            keyboard_layout: keyboard_layout::KeyboardLayout::new(&keyboard_params),
+            keyboard_params,
             keyboard: GuiKeyboard::new(),
+            background: GuiBackground::new(),
             midi_file: None,
+            midi_path: None,
             fps: fps::Fps::new(),
             nps: Default::default(),
 
             settings_win,
             midi_picker: None,
             midi_loader: None,
+            loading_layer: false,
+            profiles,
+            gpu: GpuInfo::from_device(renderer.device.physical_device()),
         }
     }
 
@@ -160,16 +203,27 @@ impl GuiWasabiWindow {
         if let Some(recv) = self.midi_picker.as_mut() {
             if let Ok(midi) = recv.try_recv() {
                 state.last_midi_location = midi.clone();
-                self.load_midi(midi, settings, state);
+                if self.loading_layer {
+                    self.add_midi_layer(midi, settings, state);
+                } else {
+                    self.load_midi(midi, settings, state);
+                }
                 self.midi_picker = None;
             }
         }
 
-        // Check for MIDIs parsed by the MIDI loader and play
+        // Check for MIDIs parsed by the MIDI loader and play, or add them as a layer
         if let Some(recv) = self.midi_loader.as_mut() {
-            if let Ok(mut midi) = recv.try_recv() {
-                midi.timer_mut().play();
-                self.midi_file = Some(midi);
+            if let Ok(layer) = recv.try_recv() {
+                match self.midi_file.as_mut() {
+                    Some(midi) if self.loading_layer => midi.add_layer(layer),
+                    _ => {
+                        let mut midi = MIDIFileGroup::new(layer, settings.midi.start_delay);
+                        self.restore_file_playback(&mut midi, settings);
+                        midi.timer_mut().play();
+                        self.midi_file = Some(midi);
+                    }
+                }
                 self.midi_loader = None;
             }
         }
@@ -177,12 +231,17 @@ impl GuiWasabiWindow {
         // If something is loading, pause playback and hide all windows
         if state.loading_status.is_loading() {
             if let Some(midi) = self.midi_file.as_mut() {
-                midi.timer_mut().pause();
+                if !midi.timer().is_paused() {
+                    midi.timer_mut().pause();
+                }
             }
             state.loading_status.show(&ctx);
             state.show_about = false;
             state.show_settings = false;
             state.show_shortcuts = false;
+            state.show_export = false;
+            state.show_layers = false;
+            state.show_palette_editor = false;
         }
 
         // Render windows
@@ -191,13 +250,25 @@ impl GuiWasabiWindow {
         }
 
         if state.show_about {
-            self.show_about(&ctx, state);
+            self.show_about(&ctx, settings, state);
         }
 
         if state.show_shortcuts {
             self.show_shortcuts(&ctx, state);
         }
 
+        if state.show_export {
+            self.show_export(&ctx, settings, state);
+        }
+
+        if state.show_layers {
+            self.show_layers(&ctx, state);
+        }
+
+        if state.show_palette_editor {
+            self.show_palette_editor(&ctx, settings, state);
+        }
+
         // Show render window (with priority when rendering)
         if state.show_render || state.render_state.is_rendering {
             self.show_render(&ctx, settings, state);
@@ -209,6 +280,7 @@ impl GuiWasabiWindow {
         }
 
         // Set global keyboard shortcuts
+        let mut screenshot = false;
         ctx.input(|events| {
             for event in &events.events {
                 if let egui::Event::Key {
@@ -232,9 +304,23 @@ impl GuiWasabiWindow {
                     if key == &egui::Key::Insert {
                         state.synth.reset();
                     }
+                    if *pressed && key == &egui::Key::F12 {
+                        screenshot = true;
+                    }
+                    if *pressed && matches!(key, egui::Key::PageUp | egui::Key::PageDown) {
+                        let step = if modifiers.shift { 12 } else { 1 };
+                        let step = if key == &egui::Key::PageUp {
+                            step
+                        } else {
+                            -step
+                        };
+                        settings.scene.live_transpose =
+                            (settings.scene.live_transpose + step).clamp(-127, 127);
+                    }
                 }
             }
         });
+        state.synth.set_transpose(settings.scene.live_transpose);
 
         // Render the panel
         let panel_height = self.show_playback_panel(&ctx, settings, state);
@@ -243,30 +329,75 @@ impl GuiWasabiWindow {
         // We must render notes before keyboard because the notes
         // renderer tells us the key colors
         let available = ctx.available_rect();
-        let height = available.height();
-        let keyboard_height =
-            (11.6 / settings.scene.key_range.len() as f32 * available.width()).min(height / 2.0);
-        let notes_height = height - keyboard_height;
-
-        let key_view = self.keyboard_layout.get_view_for_keys(
-            *settings.scene.key_range.start() as usize,
-            *settings.scene.key_range.end() as usize,
+        let key_range = settings
+            .scene
+            .visible_key_range(self.midi_file.as_ref().and_then(|midi| midi.key_range()));
+        let direction = settings.scene.scroll_direction;
+        let [keys_length, time_length] = direction.orient([available.width(), available.height()]);
+        let keyboard_size =
+            (11.6 / key_range.len() as f32 * keys_length * settings.scene.keyboard.height)
+                .min(time_length / 2.0);
+        let (notes_rect, keyboard_rect) = match direction {
+            ScrollDirection::Down => {
+                available.split_top_bottom_at_y(available.bottom() - keyboard_size)
+            }
+            ScrollDirection::Up => {
+                let (keyboard, notes) =
+                    available.split_top_bottom_at_y(available.top() + keyboard_size);
+                (notes, keyboard)
+            }
+            ScrollDirection::Horizontal => {
+                let (keyboard, notes) =
+                    available.split_left_right_at_x(available.left() + keyboard_size);
+                (notes, keyboard)
+            }
+        };
+
+        let keyboard_params = KeyboardParams::from(&settings.scene.keyboard);
+        if keyboard_params != self.keyboard_params {
+            self.keyboard_layout = keyboard_layout::KeyboardLayout::new(&keyboard_params);
+            self.keyboard_params = keyboard_params;
+        }
+
+        // The live transpose moves the notes and the keyboard sideways together, so a
+        // note stays over the key it is written at while it is heard transposed
+        let key_view = self.keyboard_layout.get_view_for_shifted_keys(
+            *key_range.start() as usize,
+            *key_range.end() as usize,
+            -settings.scene.live_transpose,
         );
 
+        if screenshot {
+            Self::take_screenshot(
+                &ctx,
+                gui_state.renderer,
+                self.midi_file.as_mut(),
+                &key_view,
+                &mut self.nps,
+                settings,
+                state,
+            );
+        }
+
         let no_frame = Frame::default()
             .inner_margin(egui::Margin::same(0))
             .fill(settings.scene.bg_color);
 
+        if let Err(e) = self.background.update(&ctx, &settings.scene.background) {
+            state.errors.error(&e);
+        }
+
         let mut stats = stats::GuiMidiStats::empty();
 
         let mut render_result_data: Option<scene::RenderResultData> = None;
 
-        // Render the notes
-        egui::TopBottomPanel::top("Note panel")
-            .height_range(notes_height..=notes_height)
+        // Render the notes and the keyboard
+        egui::CentralPanel::default()
             .frame(no_frame)
-            .show_separator_line(false)
             .show(&ctx, |ui| {
+                self.background
+                    .draw(ui, notes_rect, &settings.scene.background);
+
                 if let Some(midi_file) = self.midi_file.as_mut() {
                     // Set playback keyboard shortcuts
                     ui.input(|events| {
@@ -314,25 +445,44 @@ impl GuiWasabiWindow {
                         midi_file.timer_mut().pause();
                     }
 
-                    let result = self.render_scene.draw(
-                        gui_state,
-                        ui,
-                        &key_view,
-                        midi_file,
-                        settings.scene.note_speed,
-                    );
-                    stats.set_rendered_note_count(result.notes_rendered);
-                    stats.set_polyphony(result.polyphony);
-                    render_result_data = Some(result);
+                    if settings.scene.grid.enabled {
+                        if let Some(grid) = midi_file.beat_grid() {
+                            draw_beat_grid(ui, notes_rect, grid, current, &settings.scene);
+                        }
+                    }
+
+                    // The layers are drawn on top of each other, the first one at the bottom
+                    let rect = notes_rect;
+                    let layer_count = midi_file.layers().len();
+                    while self.render_scenes.len() < layer_count {
+                        self.render_scenes
+                            .push(GuiRenderScene::new(gui_state.renderer));
+                    }
+
+                    let layers = midi_file.layers_mut().iter_mut();
+                    for (layer, scene) in layers.zip(self.render_scenes.iter_mut()) {
+                        let result = scene.draw(
+                            gui_state,
+                            ui,
+                            rect,
+                            &key_view,
+                            &mut layer.file,
+                            settings.scene.note_speed,
+                            &settings.scene,
+                        );
+                        render_result_data = Some(match render_result_data.take() {
+                            Some(below) => below.merge_under(result),
+                            None => result,
+                        });
+                    }
+                    ui.allocate_rect(rect, egui::Sense::hover());
+
+                    if let Some(result) = render_result_data.as_ref() {
+                        stats.set_rendered_note_count(result.notes_rendered);
+                        stats.set_polyphony(result.polyphony);
+                    }
                 }
-            });
 
-        // Render the keyboard
-        egui::TopBottomPanel::top("Keyboard panel")
-            .height_range(keyboard_height..=keyboard_height)
-            .frame(no_frame)
-            .show_separator_line(false)
-            .show(&ctx, |ui| {
                 let colors = if let Some(data) = render_result_data {
                     data.key_colors
                 } else {
@@ -340,7 +490,7 @@ impl GuiWasabiWindow {
                 };
 
                 self.keyboard
-                    .draw(ui, &key_view, &colors, &settings.scene.bar_color);
+                    .draw(ui, keyboard_rect, &key_view, &colors, &settings.scene);
             });
 
         // Render the stats
@@ -353,7 +503,9 @@ impl GuiWasabiWindow {
             } else {
                 0.0
             };
-            let pos = egui::Pos2::new(pad, panel_height + pad);
+            // Keep the stats over the notes instead of the keyboard
+            let offset = notes_rect.min - available.min;
+            let pos = egui::Pos2::new(pad, panel_height + pad) + offset;
             self.draw_stats(&ctx, pos, stats, settings, false);
         }
 
@@ -364,6 +516,15 @@ impl GuiWasabiWindow {
     }
 
     pub fn open_midi_dialog(&mut self, state: &mut WasabiState) {
+        self.pick_midi(state, false);
+    }
+
+    /// Opens the file picker for a MIDI which is added to the current one as a layer
+    pub fn open_layer_dialog(&mut self, state: &mut WasabiState) {
+        self.pick_midi(state, true);
+    }
+
+    fn pick_midi(&mut self, state: &mut WasabiState, as_layer: bool) {
         // Do not open if something is loading already
         if state.loading_status.is_loading() {
             return;
@@ -371,6 +532,7 @@ impl GuiWasabiWindow {
 
         let (tx, rx) = oneshot::channel();
         self.midi_picker = Some(rx);
+        self.loading_layer = as_layer;
         let last_location = state.last_midi_location.clone();
 
         // Open the file picker in a thread so the main UI thread does not freeze
@@ -388,26 +550,149 @@ impl GuiWasabiWindow {
         });
     }
 
+    /// Renders the current frame of the loaded layers, as they are shown in the window,
+    /// and saves it as a PNG in the background. The frame is rendered at the window
+    /// size or at the resolution of the settings.
+    fn take_screenshot(
+        ctx: &egui::Context,
+        renderer: &GuiRenderer,
+        midi_file: Option<&mut MIDIFileGroup>,
+        key_view: &KeyboardView,
+        nps: &mut stats::NpsCounter,
+        settings: &WasabiSettings,
+        state: &WasabiState,
+    ) {
+        let Some(midi) = midi_file else {
+            state.errors.warning("Open a MIDI to take a screenshot");
+            return;
+        };
+
+        let resolution = settings.gui.screenshot.resolution.unwrap_or_else(|| {
+            let size = ctx.screen_rect().size() * ctx.pixels_per_point();
+            [size.x.round() as u32, size.y.round() as u32]
+        });
+
+        // The same NPS as the statistics of the window
+        let file_stats = midi.stats();
+        let nps = file_stats.nps.unwrap_or_else(|| {
+            nps.tick(file_stats.passed_notes.unwrap_or(0) as i64);
+            nps.read() as u64
+        });
+
+        let config = ScreenshotConfig::new(&midi.layers()[0].path, resolution, settings.clone());
+        take_screenshot(renderer, midi, key_view, nps, config, state.errors.clone());
+    }
+
     pub fn load_midi(
         &mut self,
         midi_path: PathBuf,
         settings: &mut WasabiSettings,
         state: &WasabiState,
     ) {
+        self.save_file_profile(settings, state);
+
         // Unload current MIDI to free resources while loading the new one
         if let Some(mut midi_file) = self.midi_file.take() {
             midi_file.timer_mut().pause();
         }
 
+        match self.profiles.get(&midi_path) {
+            Some(profile) if settings.midi.remember_files => profile.apply(settings),
+            _ => {
+                FileProfile::restore_defaults(settings);
+                settings.midi.color_seed = settings.midi.remember_files.then(rand::random);
+            }
+        }
+
+        let mut midi_settings = settings.midi.clone();
+        midi_settings.hidden_velocity = settings.scene.velocity.min_velocity;
+        self.midi_path = Some(midi_path.clone());
+        self.loading_layer = false;
+        self.spawn_midi_loader(midi_path, midi_settings, state);
+    }
+
+    /// Loads a MIDI and adds it to the current group as a layer, with the colors
+    /// chosen for the new layers
+    pub fn add_midi_layer(
+        &mut self,
+        midi_path: PathBuf,
+        settings: &mut WasabiSettings,
+        state: &WasabiState,
+    ) {
+        if self.midi_file.is_none() {
+            self.load_midi(midi_path, settings, state);
+            return;
+        }
+
+        let mut midi_settings = settings.midi.clone();
+        midi_settings.colors = state.layer_colors;
+        midi_settings.color_seed = None;
+        midi_settings.hidden_velocity = settings.scene.velocity.min_velocity;
+        self.loading_layer = true;
+        self.spawn_midi_loader(midi_path, midi_settings, state);
+    }
+
+    /// Remembers the settings and the playback position of the current MIDI,
+    /// when enabled in the settings
+    pub fn save_file_profile(&mut self, settings: &WasabiSettings, state: &WasabiState) {
+        if !settings.midi.remember_files {
+            return;
+        }
+        let (Some(path), Some(midi)) = (self.midi_path.as_ref(), self.midi_file.as_ref()) else {
+            return;
+        };
+
+        let mut profile = FileProfile::from_settings(settings);
+        profile.muted = midi
+            .layers()
+            .iter()
+            .any(|layer| layer.path == *path && layer.is_muted());
+        profile.position = midi.timer().get_time().as_seconds_f64().max(0.0);
+
+        self.profiles.insert(path, profile);
+        self.profiles
+            .save()
+            .unwrap_or_else(|e| state.errors.error(&e));
+    }
+
+    /// Seeks to the remembered position of a newly loaded MIDI and mutes it if it was
+    fn restore_file_playback(&self, midi: &mut MIDIFileGroup, settings: &WasabiSettings) {
+        if !settings.midi.remember_files {
+            return;
+        }
+        let Some(profile) = self.midi_path.as_ref().and_then(|p| self.profiles.get(p)) else {
+            return;
+        };
+
+        let length = midi.midi_length().unwrap_or(0.0);
+        if profile.position > 0.0 && profile.position < length {
+            midi.timer_mut()
+                .seek(Duration::seconds_f64(profile.position));
+        }
+        if let Some(layer) = midi.layers_mut().first_mut() {
+            layer.set_muted(profile.muted);
+        }
+    }
+
+    fn spawn_midi_loader(
+        &mut self,
+        midi_path: PathBuf,
+        settings: MidiSettings,
+        state: &WasabiState,
+    ) {
         let filename = midi_path.file_name().unwrap_or_default().to_os_string();
 
-        state.loading_status.create(
+        let progress = LoadProgress::new();
+        state.loading_status.create_with_progress(
             loading::LoadingType::Midi,
             format!("Parsing {:?}", filename),
+            progress.clone(),
         );
 
-        let synth = state.synth.clone();
-        let settings = settings.midi.clone();
+        // Each layer plays through its own player, so it can be muted on its own
+        let synth = WasabiAudioPlayer::new_layer(&state.synth);
+        let layer_player = synth.clone();
+        let layer_path = midi_path.clone();
         let loading_status = state.loading_status.clone();
         let errors = state.errors.clone();
 
@@ -418,43 +703,69 @@ impl GuiWasabiWindow {
         // via crossbeam
         thread::spawn(move || {
             if let Some(midi_path) = midi_path.to_str() {
-                match settings.parsing {
-                    MidiParsing::Ram => {
-                        match InRamMIDIFile::load_from_file(midi_path, synth, &settings) {
+                let parser = match MidiParser::from_setting(settings.parsing) {
+                    Some(parser) => parser,
+                    None => match choose_parsing(Path::new(midi_path), settings.parallel_parsing) {
+                        Ok(auto) => {
+                            loading_status
+                                .update_message(format!("Parsing {:?}\n{}", filename, auto.reason));
+                            auto.parser
+                        }
+                        Err(e) => {
+                            errors.error(&e);
+                            loading_status.clear();
+                            return;
+                        }
+                    },
+                };
+
+                match parser {
+                    MidiParser::Ram => {
+                        match InRamMIDIFile::load_from_file(midi_path, synth, &settings, progress) {
                             Ok(midi) => {
                                 let midi_file = MIDIFileUnion::InRam(midi);
-                                tx.send(midi_file).ok();
+                                tx.send(MIDILayer::new(midi_file, layer_path, layer_player))
+                                    .ok();
                             }
+                            Err(WasabiError::MidiLoadCancelled) => {}
                             Err(e) => errors.error(&e),
                         }
                         loading_status.clear();
                     }
-                    MidiParsing::Live => {
-                        match LiveLoadMIDIFile::load_from_file(midi_path, synth, &settings) {
+                    MidiParser::Live => {
+                        match LiveLoadMIDIFile::load_from_file(
+                            midi_path, synth, &settings, progress,
+                        ) {
                             Ok(midi) => {
                                 let midi_file = MIDIFileUnion::Live(midi);
-                                tx.send(midi_file).ok();
+                                tx.send(MIDILayer::new(midi_file, layer_path, layer_player))
+                                    .ok();
                             }
+                            Err(WasabiError::MidiLoadCancelled) => {}
                             Err(e) => errors.error(&e),
                         }
                         loading_status.clear();
                     }
-                    MidiParsing::Cake => {
-                        match CakeMIDIFile::load_from_file(midi_path, synth, &settings) {
+                    MidiParser::Cake => {
+                        match CakeMIDIFile::load_from_file(midi_path, synth, &settings, progress) {
                             Ok(midi) => {
                                 let midi_file = MIDIFileUnion::Cake(midi);
-                                tx.send(midi_file).ok();
+                                tx.send(MIDILayer::new(midi_file, layer_path, layer_player))
+                                    .ok();
                             }
+                            Err(WasabiError::MidiLoadCancelled) => {}
                             Err(e) => errors.error(&e),
                         }
                         loading_status.clear();
                     }
-                    MidiParsing::Pie => {
-                        match PieMIDIFile::load_from_file(midi_path, synth, &settings) {
+                    MidiParser::Pie => {
+                        match PieMIDIFile::load_from_file(midi_path, synth, &settings, progress) {
                             Ok(midi) => {
                                 let midi_file = MIDIFileUnion::Pie(midi);
-                                tx.send(midi_file).ok();
+                                tx.send(MIDILayer::new(midi_file, layer_path, layer_player))
+                                    .ok();
                             }
+                            Err(WasabiError::MidiLoadCancelled) => {}
                             Err(e) => errors.error(&e),
                         }
                         loading_status.clear();
@@ -1,12 +1,17 @@
-use crate::{state::WasabiState, utils};
+use crate::{renderer::devices::select_gpu, settings::WasabiSettings, state::WasabiState, utils};
 use std::env::consts::{ARCH, OS};
 
 use super::{GuiWasabiWindow, WasabiError};
 
 impl GuiWasabiWindow {
-    pub fn show_about(&mut self, ctx: &egui::Context, state: &mut WasabiState) {
+    pub fn show_about(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &WasabiSettings,
+        state: &mut WasabiState,
+    ) {
         let frame = utils::create_window_frame(ctx);
-        let size = [600.0, 460.0];
+        let size = [600.0, 500.0];
 
         let mut updcheck = false;
 
@@ -79,6 +84,20 @@ impl GuiWasabiWindow {
                         ui.label("Architecture:");
                         ui.label(ARCH.to_string());
                         ui.end_row();
+
+                        ui.label("Graphics Device:");
+                        ui.label(self.gpu.label());
+                        ui.end_row();
+
+                        // The export picks its GPU the same way when it starts
+                        let export_gpu = select_gpu(
+                            self.settings_win.gpus(),
+                            settings.gui.export_device.as_ref(),
+                            |gpu| (*gpu).clone(),
+                        );
+                        ui.label("Video Export Device:");
+                        ui.label(export_gpu.map_or("None".to_owned(), |gpu| gpu.label()));
+                        ui.end_row();
                     });
 
                 ui.add_space(20.0);
@@ -0,0 +1,90 @@
+use std::path::{Path, PathBuf};
+
+use egui::{pos2, vec2, Color32, ColorImage, Context, Rect, TextureHandle, TextureOptions, Ui};
+use image::{ImageReader, RgbaImage};
+
+use crate::settings::{BackgroundFit, BackgroundSettings};
+
+use super::WasabiError;
+
+/// Decodes a background image file
+pub fn load_background_image(path: &Path) -> Result<RgbaImage, WasabiError> {
+    let image = ImageReader::open(path)
+        .map_err(|e| WasabiError::BackgroundError(e.to_string()))?
+        .with_guessed_format()
+        .map_err(|e| WasabiError::BackgroundError(e.to_string()))?
+        .decode()
+        .map_err(|e| WasabiError::BackgroundError(e.to_string()))?;
+    Ok(image.to_rgba8())
+}
+
+/// The multiplier of the background colors for the dim setting
+pub fn dim_factor(settings: &BackgroundSettings) -> f32 {
+    1.0 - settings.dim.clamp(0.0, 1.0)
+}
+
+/// The background image drawn behind the notes of the GUI
+pub struct GuiBackground {
+    /// The image the texture was loaded from. It is also kept when loading
+    /// failed, so the error is only reported once.
+    path: Option<PathBuf>,
+    texture: Option<TextureHandle>,
+}
+
+impl GuiBackground {
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            texture: None,
+        }
+    }
+
+    /// Loads the image of the settings if it changed since the last call
+    pub fn update(
+        &mut self,
+        ctx: &Context,
+        settings: &BackgroundSettings,
+    ) -> Result<(), WasabiError> {
+        if self.path == settings.image {
+            return Ok(());
+        }
+
+        self.path = settings.image.clone();
+        self.texture = None;
+
+        if let Some(path) = &self.path {
+            let image = load_background_image(path)?;
+            let size = [image.width() as usize, image.height() as usize];
+            let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+            let options = TextureOptions {
+                wrap_mode: egui::TextureWrapMode::Repeat,
+                ..TextureOptions::LINEAR
+            };
+            self.texture = Some(ctx.load_texture("background", image, options));
+        }
+
+        Ok(())
+    }
+
+    pub fn draw(&self, ui: &Ui, rect: Rect, settings: &BackgroundSettings) {
+        let Some(texture) = &self.texture else {
+            return;
+        };
+
+        let image_size = texture.size_vec2();
+        let (dest, uv) = match settings.fit {
+            BackgroundFit::Tile => {
+                let repeats = rect.size() / image_size;
+                (rect, Rect::from_min_max(pos2(0.0, 0.0), repeats.to_pos2()))
+            }
+            fit => {
+                let [w, h] = fit.scaled_size(image_size.into(), rect.size().into());
+                let dest = Rect::from_center_size(rect.center(), vec2(w, h));
+                (dest, Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)))
+            }
+        };
+
+        let tint = Color32::from_gray((dim_factor(settings) * 255.0) as u8);
+        ui.painter_at(rect).image(texture.id(), dest, uv, tint);
+    }
+}
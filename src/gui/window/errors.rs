@@ -13,6 +13,8 @@ use crate::utils;
 #[derive(Debug)]
 pub enum WasabiError {
     MidiLoadError(MIDILoadError),
+    MidiLoadCancelled,
+    MidiExportError(String),
     SoundFontLoadError(LoadSfError),
     #[cfg(supported_os)]
     SynthError(String),
@@ -20,6 +22,9 @@ pub enum WasabiError {
     SettingsError(String),
     UpdaterError(String),
     PaletteError(String),
+    BackgroundError(String),
+    GraphicsError(String),
+    ScreenshotError(String),
     Other(String),
 }
 
@@ -33,13 +38,18 @@ impl fmt::Display for WasabiError {
                 }
                 MIDILoadError::FileTooBig => write!(f, "MIDI Load Error: File Too Big"),
             },
+            WasabiError::MidiLoadCancelled => write!(f, "MIDI Load Error: Cancelled by user"),
+            WasabiError::MidiExportError(e) => write!(f, "MIDI Export Error: {e}"),
             WasabiError::SoundFontLoadError(e) => write!(f, "Error Parsing SoundFont: {e}"),
             #[cfg(supported_os)]
             WasabiError::SynthError(e) => write!(f, "Synth Error: {e}"),
             WasabiError::FilesystemError(e) => write!(f, "Filesystem Error: {e}"),
             WasabiError::SettingsError(e) => write!(f, "Settings Error: {e}"),
             WasabiError::UpdaterError(e) => write!(f, "Update Error: {e}"),
-            WasabiError::PaletteError(e) => write!(f, "Palette Load Error: {e}"),
+            WasabiError::PaletteError(e) => write!(f, "Palette Error: {e}"),
+            WasabiError::BackgroundError(e) => write!(f, "Background Load Error: {e}"),
+            WasabiError::GraphicsError(e) => write!(f, "Graphics Error: {e}"),
+            WasabiError::ScreenshotError(e) => write!(f, "Screenshot Error: {e}"),
             WasabiError::Other(e) => write!(f, "Unknown Error: {e}"),
         }
     }
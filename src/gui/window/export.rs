@@ -0,0 +1,320 @@
+use std::{path::Path, thread};
+
+use crate::{
+    midi::{
+        export_midi, read_track_count, LoadProgress, MIDIFileBase, MIDIFileGroup, MidiExportOptions,
+    },
+    settings::{NoteFilterSettings, WasabiSettings},
+    state::WasabiState,
+    utils::{self, convert_seconds_to_time_string},
+};
+
+use super::{loading::LoadingType, GuiWasabiWindow, WasabiError};
+
+/// The options of the MIDI export window
+#[derive(Default)]
+pub struct MidiExportState {
+    /// Index of the exported layer
+    pub layer: usize,
+    /// Whether each track of the exported layer is exported
+    pub tracks: Vec<bool>,
+    pub transpose: i32,
+    pub apply_filters: bool,
+    pub trim: bool,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl GuiWasabiWindow {
+    /// Opens the export window with the transposition and the length of the
+    /// current playback
+    pub fn open_export(&mut self, settings: &WasabiSettings, state: &mut WasabiState) {
+        let length = self
+            .midi_file
+            .as_ref()
+            .and_then(|midi| midi.midi_length())
+            .unwrap_or(0.0);
+
+        // Muted layers aren't heard, so they aren't exported either
+        let layer = self
+            .midi_file
+            .as_ref()
+            .and_then(|midi| midi.layers().iter().position(|layer| !layer.is_muted()))
+            .unwrap_or(0);
+
+        state.export_state = MidiExportState {
+            layer,
+            tracks: self
+                .midi_file
+                .as_ref()
+                .map_or(Vec::new(), |midi| Self::read_export_tracks(midi, layer)),
+            transpose: (settings.midi.transpose + settings.scene.live_transpose).clamp(-127, 127),
+            apply_filters: settings.midi.filters.is_active(),
+            trim: false,
+            start: 0.0,
+            end: length,
+        };
+        state.show_export = true;
+    }
+
+    /// Every track of the layer is exported by default
+    fn read_export_tracks(midi: &MIDIFileGroup, layer: usize) -> Vec<bool> {
+        let track_count = midi
+            .layers()
+            .get(layer)
+            .and_then(|layer| read_track_count(&layer.path).ok().flatten())
+            .unwrap_or(0);
+        vec![true; track_count as usize]
+    }
+
+    pub fn show_export(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &WasabiSettings,
+        state: &mut WasabiState,
+    ) {
+        let frame = utils::create_window_frame(ctx);
+        let length = self
+            .midi_file
+            .as_ref()
+            .and_then(|midi| midi.midi_length())
+            .unwrap_or(0.0);
+
+        let mut open = state.show_export;
+        let mut export = false;
+
+        egui::Window::new("Export MIDI")
+            .resizable(false)
+            .collapsible(false)
+            .title_bar(true)
+            .enabled(true)
+            .frame(frame)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let export_state = &mut state.export_state;
+
+                egui::Grid::new("export_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                The layer to export. Each layer is exported on its \
+                                own, since the layers can have different tempos.\n\
+                                Muted layers can't be exported.",
+                            );
+                            ui.label("Layer: ");
+                        });
+                        let layers = self
+                            .midi_file
+                            .as_ref()
+                            .map_or(&[][..], |midi| midi.layers());
+                        let selected = layers
+                            .get(export_state.layer)
+                            .map_or("(None loaded)".into(), |layer| layer.name());
+                        let previous = export_state.layer;
+                        egui::ComboBox::from_id_salt("export_layer_select")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for (i, layer) in layers.iter().enumerate() {
+                                    ui.add_enabled_ui(!layer.is_muted(), |ui| {
+                                        ui.selectable_value(
+                                            &mut export_state.layer,
+                                            i,
+                                            layer.name(),
+                                        );
+                                    });
+                                }
+                            });
+                        if export_state.layer != previous {
+                            if let Some(midi) = self.midi_file.as_ref() {
+                                export_state.tracks =
+                                    Self::read_export_tracks(midi, export_state.layer);
+                            }
+                        }
+                        ui.end_row();
+
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                The tracks whose notes and events are exported.\n\
+                                The tempo changes of the other tracks are kept, \
+                                so the timing of the MIDI doesn't change.",
+                            );
+                            ui.label("Tracks: ");
+                        });
+                        ui.vertical(|ui| {
+                            ui.horizontal(|ui| {
+                                if ui.button("All").clicked() {
+                                    export_state.tracks.fill(true);
+                                }
+                                if ui.button("None").clicked() {
+                                    export_state.tracks.fill(false);
+                                }
+                            });
+                            let tracks = &mut export_state.tracks;
+                            let row_height = ui.spacing().interact_size.y;
+                            egui::ScrollArea::vertical()
+                                .id_salt("export_tracks")
+                                .max_height(150.0)
+                                .show_rows(ui, row_height, tracks.len(), |ui, range| {
+                                    for i in range {
+                                        ui.checkbox(&mut tracks[i], format!("Track {}", i + 1));
+                                    }
+                                });
+                        });
+                        ui.end_row();
+
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                The total transposition of the exported notes, \
+                                including the one applied while loading.\n\
+                                Notes moved below key 0 or above key 127 are removed.",
+                            );
+                            ui.label("Transpose: ");
+                        });
+                        ui.add(
+                            egui::DragValue::new(&mut export_state.transpose)
+                                .speed(0.2)
+                                .range(-127..=127)
+                                .suffix(" semitones"),
+                        );
+                        ui.end_row();
+
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                Removes the notes matched by the note filters \
+                                of the MIDI settings.",
+                            );
+                            ui.label("Apply Note Filters: ");
+                        });
+                        ui.checkbox(&mut export_state.apply_filters, "");
+                        ui.end_row();
+
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                Only exports the notes starting between the start \
+                                and the end time. The exported MIDI begins at the \
+                                start time.",
+                            );
+                            ui.label("Trim: ");
+                        });
+                        ui.checkbox(&mut export_state.trim, "");
+                        ui.end_row();
+
+                        ui.add_enabled_ui(export_state.trim, |ui| ui.label("Start: "));
+                        ui.add_enabled_ui(export_state.trim, |ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut export_state.start)
+                                    .speed(0.1)
+                                    .range(0.0..=export_state.end)
+                                    .custom_formatter(|t, _| convert_seconds_to_time_string(t)),
+                            );
+                        });
+                        ui.end_row();
+
+                        ui.add_enabled_ui(export_state.trim, |ui| ui.label("End: "));
+                        ui.add_enabled_ui(export_state.trim, |ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut export_state.end)
+                                    .speed(0.1)
+                                    .range(export_state.start..=length.max(export_state.start))
+                                    .custom_formatter(|t, _| convert_seconds_to_time_string(t)),
+                            );
+                        });
+                        ui.end_row();
+                    });
+
+                ui.add_space(8.0);
+                ui.vertical_centered(|ui| {
+                    let layer = self
+                        .midi_file
+                        .as_ref()
+                        .and_then(|midi| midi.layers().get(state.export_state.layer));
+                    let can_export = layer.is_some_and(|layer| !layer.is_muted())
+                        && state.export_state.tracks.contains(&true);
+                    if ui
+                        .add_enabled(can_export, egui::Button::new("Export..."))
+                        .clicked()
+                    {
+                        export = true;
+                    }
+                });
+            });
+
+        if export {
+            self.start_export(settings, state);
+            open = false;
+        }
+        state.show_export = open;
+    }
+
+    /// Asks for the output path and exports the selected layer in a thread, displaying
+    /// the progress like the loaders do
+    fn start_export(&mut self, settings: &WasabiSettings, state: &WasabiState) {
+        let export_state = &state.export_state;
+        let Some(layer) = self
+            .midi_file
+            .as_ref()
+            .and_then(|midi| midi.layers().get(export_state.layer))
+        else {
+            return;
+        };
+        let midi_path = layer.path.clone();
+        // The trim range is in the time of the group, which the layer starts later in
+        let offset = layer.offset();
+
+        if state.loading_status.is_loading() {
+            return;
+        }
+
+        let mut midi_settings = settings.midi.clone();
+        midi_settings.transpose = export_state.transpose;
+        if !export_state.apply_filters {
+            midi_settings.filters = NoteFilterSettings::default();
+        }
+        let options = MidiExportOptions {
+            range: export_state.trim.then_some((
+                (export_state.start - offset).max(0.0),
+                (export_state.end - offset).max(0.0),
+            )),
+            tracks: Some(export_state.tracks.clone()),
+        };
+
+        let loading_status = state.loading_status.clone();
+        let errors = state.errors.clone();
+
+        thread::spawn(move || {
+            let stem = midi_path.file_stem().unwrap_or_default().to_string_lossy();
+            let output = rfd::FileDialog::new()
+                .add_filter("mid", &["mid", "MID"])
+                .set_title("Export MIDI as...")
+                .set_directory(midi_path.parent().unwrap_or(Path::new("./")))
+                .set_file_name(format!("{stem} (export).mid"))
+                .save_file();
+
+            let (Some(output), Some(path)) = (output, midi_path.to_str()) else {
+                return;
+            };
+
+            let progress = LoadProgress::new();
+            let filename = output.file_name().unwrap_or_default().to_os_string();
+            loading_status.create_with_progress(
+                LoadingType::MidiExport,
+                format!("Exporting {:?}", filename),
+                progress.clone(),
+            );
+
+            match export_midi(path, &output, &midi_settings, options, progress) {
+                Ok(_) | Err(WasabiError::MidiLoadCancelled) => {}
+                Err(e) => errors.error(&e),
+            }
+            loading_status.clear();
+        });
+    }
+}
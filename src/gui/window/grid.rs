@@ -0,0 +1,50 @@
+use egui::{pos2, vec2, Align2, Color32, FontId, Rect, Stroke, Ui};
+
+use crate::{
+    midi::BeatGrid,
+    settings::{SceneSettings, ScrollDirection},
+};
+
+/// Draws the bar and beat lines visible at `time` into the notes area
+pub fn draw_beat_grid(ui: &Ui, rect: Rect, grid: &BeatGrid, time: f64, settings: &SceneSettings) {
+    let painter = ui.painter_at(rect);
+    let font = FontId::proportional(12.0);
+    let speed = settings.note_speed;
+
+    for line in grid.lines_between(time, time + speed) {
+        // Distance from the keyboard, from 0 to 1
+        let progress = ((line.time - time) / speed) as f32;
+        let color = match line.bar {
+            Some(_) => settings.grid.bar_color,
+            None => settings.grid.beat_color,
+        };
+        let stroke = Stroke::new(1.0, color);
+
+        let (start, end) = match settings.scroll_direction {
+            ScrollDirection::Down => {
+                let y = rect.bottom() - progress * rect.height();
+                (pos2(rect.left(), y), pos2(rect.right(), y))
+            }
+            ScrollDirection::Up => {
+                let y = rect.top() + progress * rect.height();
+                (pos2(rect.left(), y), pos2(rect.right(), y))
+            }
+            ScrollDirection::Horizontal => {
+                let x = rect.left() + progress * rect.width();
+                (pos2(x, rect.top()), pos2(x, rect.bottom()))
+            }
+        };
+        painter.line_segment([start, end], stroke);
+
+        if let Some(bar) = line.bar.filter(|_| settings.grid.bar_numbers) {
+            // Inside the bar that starts at the line
+            let (pos, align) = match settings.scroll_direction {
+                ScrollDirection::Down => (start + vec2(3.0, -2.0), Align2::LEFT_BOTTOM),
+                _ => (start + vec2(3.0, 2.0), Align2::LEFT_TOP),
+            };
+            let [r, g, b, _] = color.to_srgba_unmultiplied();
+            let text_color = Color32::from_rgb(r, g, b);
+            painter.text(pos, align, bar.to_string(), font.clone(), text_color);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use egui::{emath::GuiRounding, Color32, Mesh, Pos2, Rect, Sense, Ui};
+use egui::{emath::GuiRounding, Color32, Mesh, Pos2, Rect, Ui, Vec2};
 
-use crate::midi::MIDIColor;
+use crate::{
+    midi::MIDIColor,
+    settings::{KeyStyle, SceneSettings, ScrollDirection},
+};
 
 use super::keyboard_layout::KeyboardView;
 
@@ -11,14 +14,23 @@ impl GuiKeyboard {
         GuiKeyboard {}
     }
 
+    /// Draws the keyboard over `screen_rect`, facing the notes of the scroll direction
     pub fn draw(
         &mut self,
         ui: &mut Ui,
+        screen_rect: Rect,
         key_view: &KeyboardView,
         colors: &[Option<MIDIColor>],
-        bar_color: &Color32,
+        settings: &SceneSettings,
     ) {
-        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::click());
+        let bar_color = &settings.bar_color;
+        let direction = settings.scroll_direction;
+        let flat = settings.keyboard.key_style == KeyStyle::Flat;
+        // The keyboard is laid out for the downward direction, with the keys along
+        // the x axis, and rotated into place afterwards
+        let size = direction.orient([screen_rect.width(), screen_rect.height()]);
+        let rect = Rect::from_min_size(screen_rect.min, Vec2::new(size[0], size[1]));
+
         let mut mesh = Mesh::default();
         let note_border =
             crate::utils::calculate_border_width(rect.width(), key_view.visible_range.len() as f32);
@@ -30,7 +42,7 @@ impl GuiKeyboard {
         let black_key_overlap = bar / 2.35;
         let top = rect.top() + bar;
         let bottom = rect.bottom();
-        let black_bottom = rect.bottom() - rect.height() * 0.34;
+        let black_bottom = rect.top() + rect.height() * settings.keyboard.black_key_length;
         let map_x = |num: f32| rect.left() + num * rect.width();
         fn map_color(col: MIDIColor) -> Color32 {
             Color32::from_rgb(col.red(), col.green(), col.blue())
@@ -38,7 +50,16 @@ impl GuiKeyboard {
 
         for (i, key) in key_view.iter_visible_keys() {
             if !key.black {
-                if let Some(color) = colors[i].map(map_color) {
+                if flat {
+                    let color = colors[i].map(map_color).unwrap_or(Color32::WHITE);
+                    mesh.add_colored_rect(
+                        Rect::from_min_max(
+                            Pos2::new(map_x(key.left), top),
+                            Pos2::new(map_x(key.right), bottom),
+                        ),
+                        color,
+                    );
+                } else if let Some(color) = colors[i].map(map_color) {
                     // Pressed
                     let darkened = Color32::from_rgb(
                         (color.r() as f32 * 0.6) as u8,
@@ -208,7 +229,18 @@ impl GuiKeyboard {
 
         for (i, key) in key_view.iter_visible_keys() {
             if key.black {
-                if let Some(color) = colors[i].map(map_color) {
+                if flat {
+                    let color = colors[i]
+                        .map(map_color)
+                        .unwrap_or(Color32::from_rgb(20, 20, 20));
+                    mesh.add_colored_rect(
+                        Rect::from_min_max(
+                            Pos2::new(map_x(key.left), top),
+                            Pos2::new(map_x(key.right), black_bottom),
+                        ),
+                        color,
+                    );
+                } else if let Some(color) = colors[i].map(map_color) {
                     // Pressed
                     let darkened = Color32::from_rgb(
                         (color.r() as f32 * 0.76) as u8,
@@ -367,10 +399,28 @@ impl GuiKeyboard {
             }
         }
 
+        for vertex in mesh.vertices.iter_mut() {
+            vertex.pos = orient_point(vertex.pos, rect, screen_rect, direction);
+        }
+
         ui.painter().add(mesh);
     }
 }
 
+/// Moves a point of the downward keyboard in `rect` into `screen_rect`. The upward
+/// keyboard is mirrored vertically, and the horizontal one has the lowest key at the
+/// bottom and the notes on its right.
+fn orient_point(pos: Pos2, rect: Rect, screen_rect: Rect, direction: ScrollDirection) -> Pos2 {
+    match direction {
+        ScrollDirection::Down => pos,
+        ScrollDirection::Up => Pos2::new(pos.x, screen_rect.bottom() - (pos.y - rect.top())),
+        ScrollDirection::Horizontal => Pos2::new(
+            screen_rect.right() - (pos.y - rect.top()),
+            screen_rect.bottom() - (pos.x - rect.left()),
+        ),
+    }
+}
+
 fn add_rect_triangles(mesh: &mut Mesh) {
     let idx = mesh.vertices.len() as u32;
     mesh.add_triangle(idx, idx + 1, idx + 2);
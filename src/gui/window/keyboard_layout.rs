@@ -2,6 +2,8 @@
 
 use std::ops::Range;
 
+use crate::settings::{KeyboardSettings, KeyboardType};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum KeyboardParams {
     SameWidth,
@@ -22,6 +24,19 @@ impl Default for KeyboardParams {
     }
 }
 
+impl From<&KeyboardSettings> for KeyboardParams {
+    fn from(settings: &KeyboardSettings) -> Self {
+        match settings.keyboard_type {
+            KeyboardType::SameWidth => KeyboardParams::SameWidth,
+            KeyboardType::Classic => KeyboardParams::Classic {
+                black_key_2_set_offset: settings.black_key_2_set_offset,
+                black_key_3_set_offset: settings.black_key_3_set_offset,
+                black_key_scale: settings.black_key_scale,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct KeyPosition {
     pub black: bool,
@@ -185,6 +200,40 @@ impl KeyboardLayout {
         }
     }
 
+    /// Like [`Self::get_view_for_keys`], but moved by `shift` keys. The view can reach
+    /// past the first and the last key, that part of the view is left empty.
+    pub fn get_view_for_shifted_keys(
+        &'_ self,
+        first_key: usize,
+        last_key: usize,
+        shift: i32,
+    ) -> KeyboardView<'_> {
+        let first = first_key as i32 + shift;
+        let last = last_key as i32 + shift;
+        if first >= 0 && last <= 255 {
+            return self.get_view_for_keys(first as usize, last as usize);
+        }
+
+        let range = KeyboardRange::new(self.key_bounds(first).0, self.key_bounds(last).1);
+        self.get_view_for_range(range)
+    }
+
+    /// Left and right of a key, extended by whole octaves past the ends of the layout
+    fn key_bounds(&self, key: i32) -> (f32, f32) {
+        let octaves = if key < 0 {
+            key.div_euclid(12)
+        } else if key > 255 {
+            (key - 255 + 11) / 12
+        } else {
+            0
+        };
+        let octave_width = self.keys[12].left - self.keys[0].left;
+        let offset = octaves as f32 * octave_width;
+
+        let key = self.keys[(key - octaves * 12) as usize];
+        (key.left + offset, key.right + offset)
+    }
+
     pub fn get_view_for_range(&'_ self, range: KeyboardRange) -> KeyboardView<'_> {
         let mut left_key = self
             .keys
@@ -0,0 +1,120 @@
+use crate::{midi::MIDIFileBase, settings::Colors, state::WasabiState, utils};
+
+use super::GuiWasabiWindow;
+
+impl GuiWasabiWindow {
+    pub fn show_layers(&mut self, ctx: &egui::Context, state: &mut WasabiState) {
+        let frame = utils::create_window_frame(ctx);
+
+        let mut open = state.show_layers;
+        let mut add_layer = false;
+        let mut remove = None;
+
+        egui::Window::new("MIDI Layers")
+            .resizable(false)
+            .collapsible(false)
+            .title_bar(true)
+            .scroll([false, true])
+            .enabled(true)
+            .frame(frame)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some(midi) = self.midi_file.as_mut() else {
+                    ui.label("No MIDI is loaded.");
+                    return;
+                };
+
+                egui::Grid::new("layers_grid")
+                    .num_columns(4)
+                    .spacing([10.0, 8.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("MIDI");
+                        ui.horizontal(|ui| {
+                            ui.monospace("\u{2139}").on_hover_text(
+                                "\
+                                The time the layer starts at, relative to the \
+                                others.\nLayers parsed with Live can't be moved.",
+                            );
+                            ui.strong("Offset");
+                        });
+                        ui.strong("Mute");
+                        ui.label("");
+                        ui.end_row();
+
+                        let single = midi.layers().len() == 1;
+                        for i in 0..midi.layers().len() {
+                            let layer = &midi.layers()[i];
+                            ui.label(layer.name());
+
+                            let mut offset = layer.offset();
+                            let movable = layer.file.allows_seeking_backward();
+                            let changed = ui
+                                .add_enabled(
+                                    movable,
+                                    egui::DragValue::new(&mut offset)
+                                        .speed(0.01)
+                                        .max_decimals(3)
+                                        .suffix(" s"),
+                                )
+                                .changed();
+                            if changed {
+                                midi.set_offset(i, offset);
+                            }
+
+                            let layer = &mut midi.layers_mut()[i];
+                            let mut muted = layer.is_muted();
+                            if ui.checkbox(&mut muted, "").changed() {
+                                layer.set_muted(muted);
+                            }
+
+                            if ui
+                                .add_enabled(!single, egui::Button::new("Remove"))
+                                .clicked()
+                            {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    ui.label("Colors:");
+                    egui::ComboBox::from_id_salt("layer_colors_select")
+                        .selected_text(state.layer_colors.as_str())
+                        .show_ui(ui, |ui| {
+                            for colors in [
+                                Colors::Rainbow,
+                                Colors::Random,
+                                Colors::White,
+                                Colors::Gradient,
+                                Colors::Palette,
+                            ] {
+                                ui.selectable_value(
+                                    &mut state.layer_colors,
+                                    colors,
+                                    colors.as_str(),
+                                );
+                            }
+                        });
+
+                    if ui.button("Add Layer...").clicked() {
+                        add_layer = true;
+                    }
+                });
+            });
+
+        if let (Some(index), Some(midi)) = (remove, self.midi_file.as_mut()) {
+            midi.remove_layer(index);
+            if index < self.render_scenes.len() {
+                self.render_scenes.remove(index);
+            }
+        }
+
+        if add_layer {
+            self.open_layer_dialog(state);
+        }
+        state.show_layers = open;
+    }
+}
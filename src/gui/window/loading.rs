@@ -1,17 +1,24 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
 use egui::Context;
+use numfmt::{Formatter, Precision};
 
-use crate::utils;
+use crate::{midi::LoadProgress, utils};
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 struct StatusInfoHolder {
     title: String,
     message: String,
+    progress: Option<Arc<LoadProgress>>,
+    started: Instant,
 }
 
 pub enum LoadingType {
     Midi,
+    MidiExport,
     SoundFont,
 }
 
@@ -19,6 +26,7 @@ impl std::fmt::Display for LoadingType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LoadingType::Midi => write!(f, "Loading MIDI..."),
+            LoadingType::MidiExport => write!(f, "Exporting MIDI..."),
             LoadingType::SoundFont => write!(f, "Loading SoundFont..."),
         }
     }
@@ -35,6 +43,24 @@ impl LoadingStatus {
         *self.0.write().unwrap() = Some(StatusInfoHolder {
             title: loading_type.to_string(),
             message,
+            progress: None,
+            started: Instant::now(),
+        });
+    }
+
+    /// Same as [`LoadingStatus::create`], but also displays the progress of the
+    /// loader and allows the user to cancel it.
+    pub fn create_with_progress(
+        &self,
+        loading_type: LoadingType,
+        message: String,
+        progress: Arc<LoadProgress>,
+    ) {
+        *self.0.write().unwrap() = Some(StatusInfoHolder {
+            title: loading_type.to_string(),
+            message,
+            progress: Some(progress),
+            started: Instant::now(),
         });
     }
 
@@ -52,8 +78,45 @@ impl LoadingStatus {
         *self.0.write().unwrap() = None;
     }
 
+    fn show_progress(ui: &mut egui::Ui, progress: &LoadProgress, started: Instant) {
+        let mut f = Formatter::new()
+            .separator(',')
+            .unwrap()
+            .precision(Precision::Decimals(0));
+
+        let fraction = progress.fraction();
+        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+
+        ui.add(
+            egui::ProgressBar::new(fraction)
+                .desired_width(280.0)
+                .show_percentage(),
+        );
+        ui.small(format!(
+            "{:.1} / {:.1} MB read, {} events processed",
+            mb(progress.bytes_read()),
+            mb(progress.bytes_total()),
+            f.fmt2(progress.events_processed()),
+        ));
+
+        let elapsed = started.elapsed().as_secs_f64();
+        let eta = if fraction > 0.01 && fraction < 1.0 {
+            let remaining = elapsed * (1.0 - fraction as f64) / fraction as f64;
+            utils::convert_seconds_to_time_string(remaining)
+        } else {
+            "-".to_string()
+        };
+        ui.small(format!(
+            "Elapsed: {}, Remaining: {}",
+            utils::convert_seconds_to_time_string(elapsed),
+            eta
+        ));
+    }
+
     pub fn show(&self, ctx: &Context) {
-        if let Some(info) = self.0.read().unwrap().as_ref() {
+        let info = self.0.read().unwrap().clone();
+
+        if let Some(info) = info {
             let frame = utils::create_window_frame(ctx);
 
             egui::Window::new(&info.title)
@@ -72,9 +135,30 @@ impl LoadingStatus {
                                 .rotate(rotation, egui::Vec2::splat(0.5))
                                 .fit_to_exact_size([56.0, 56.0].into()),
                         );
-                        ui.label(&info.message);
+                        ui.vertical(|ui| {
+                            ui.label(&info.message);
+                            if let Some(progress) = info.progress.as_ref() {
+                                Self::show_progress(ui, progress, info.started);
+                            }
+                        });
                     });
+
+                    if let Some(progress) = info.progress.as_ref() {
+                        ui.separator();
+                        ui.vertical_centered(|ui| {
+                            if progress.is_cancelled() {
+                                ui.add_enabled(false, egui::Button::new("Cancelling..."));
+                            } else if ui.button("\u{2716} Cancel").clicked() {
+                                progress.cancel();
+                            }
+                        });
+                    }
                 });
+
+            // Keep the progress updating even if there is no input
+            if info.progress.is_some() {
+                ctx.request_repaint();
+            }
         }
     }
 }
@@ -0,0 +1,312 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use egui::Color32;
+use image::{DynamicImage, GenericImageView, ImageReader, Rgb, RgbImage};
+
+use crate::{
+    midi::MIDIColor,
+    settings::WasabiSettings,
+    state::WasabiState,
+    utils::{self, IMAGE_EXTENSIONS},
+};
+
+use super::{GuiWasabiWindow, WasabiError};
+
+const GOLDEN_RATIO_CONJUGATE: f64 = 0.618033988749895;
+
+/// The palette being edited, in the format read by `MIDIColor::new_vec_from_palette`
+pub struct PaletteEditorState {
+    /// One row of channel colors for each track
+    pub rows: Vec<[Color32; 16]>,
+    /// File name of the palette in the palettes directory
+    pub name: String,
+}
+
+impl Default for PaletteEditorState {
+    fn default() -> Self {
+        let mut state = Self {
+            rows: vec![[Color32::WHITE; 16]],
+            name: "palette.png".into(),
+        };
+        state.fill(MIDIColor::new_hue_vec(16).into_iter().map(to_color32));
+        state
+    }
+}
+
+impl PaletteEditorState {
+    fn cell_count(&self) -> usize {
+        self.rows.len() * 16
+    }
+
+    /// Replaces the colors row after row, the order the palettes are read in
+    fn fill(&mut self, colors: impl Iterator<Item = Color32>) {
+        for (cell, color) in self.rows.iter_mut().flatten().zip(colors) {
+            *cell = color;
+        }
+    }
+
+    fn fill_even_hues(&mut self) {
+        let colors = MIDIColor::new_hue_vec(self.cell_count());
+        self.fill(colors.into_iter().map(to_color32));
+    }
+
+    /// Hues which are never close to the ones right before them
+    fn fill_golden_ratio(&mut self) {
+        let colors = (0..self.cell_count()).map(|i| {
+            let hue = (i as f64 * GOLDEN_RATIO_CONJUGATE * 360.0) % 360.0;
+            to_color32(MIDIColor::new_from_hue(hue))
+        });
+        self.fill(colors);
+    }
+
+    /// Uses the most common colors of an image, repeating them if there aren't enough
+    fn fill_from_image(&mut self, path: &Path) -> Result<(), WasabiError> {
+        let image = open_image(path)?.thumbnail(128, 128).to_rgb8();
+        let colors = dominant_colors(&image, self.cell_count());
+        if colors.is_empty() {
+            return Err(WasabiError::PaletteError(format!(
+                "Image has no pixels: {path:?}"
+            )));
+        }
+
+        self.fill(colors.into_iter().cycle());
+        Ok(())
+    }
+
+    fn load(&mut self, path: &Path) -> Result<(), WasabiError> {
+        let image = open_image(path)?;
+        if image.dimensions().0 != 16 || image.dimensions().1 == 0 {
+            return Err(WasabiError::PaletteError(format!(
+                "Palette has invalid dimensions: {path:?}"
+            )));
+        }
+
+        let image = image.to_rgb8();
+        self.rows = image
+            .rows()
+            .map(|row| {
+                let mut colors = [Color32::WHITE; 16];
+                for (color, pixel) in colors.iter_mut().zip(row) {
+                    *color = Color32::from_rgb(pixel.0[0], pixel.0[1], pixel.0[2]);
+                }
+                colors
+            })
+            .collect();
+        self.name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        Ok(())
+    }
+
+    /// The path the palette is saved to, always with a `.png` extension
+    fn path(&self) -> Result<PathBuf, WasabiError> {
+        let name = Path::new(self.name.trim());
+        if name.file_name() != Some(name.as_os_str()) {
+            return Err(WasabiError::PaletteError(format!(
+                "Invalid palette name: {:?}",
+                self.name
+            )));
+        }
+
+        let mut path = WasabiSettings::get_palettes_dir();
+        path.push(name);
+        path.set_extension("png");
+
+        Ok(path)
+    }
+
+    fn save(&self) -> Result<(), WasabiError> {
+        let path = self.path()?;
+
+        let mut image = RgbImage::new(16, self.rows.len() as u32);
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                image.put_pixel(x as u32, y as u32, Rgb([color.r(), color.g(), color.b()]));
+            }
+        }
+
+        image
+            .save(&path)
+            .map_err(|e| WasabiError::PaletteError(format!("Failed to save {path:?}: {e}")))
+    }
+}
+
+fn to_color32(color: MIDIColor) -> Color32 {
+    Color32::from_rgb(color.red(), color.green(), color.blue())
+}
+
+fn open_image(path: &Path) -> Result<DynamicImage, WasabiError> {
+    ImageReader::open(path)
+        .map_err(|e| WasabiError::PaletteError(e.to_string()))?
+        .with_guessed_format()
+        .map_err(|e| WasabiError::PaletteError(e.to_string()))?
+        .decode()
+        .map_err(|e| WasabiError::PaletteError(e.to_string()))
+}
+
+/// The average colors of the most used groups of similar colors, most used first
+fn dominant_colors(image: &RgbImage, count: usize) -> Vec<Color32> {
+    // Pixel count and sum of the channels for each color with the same 4 high bits
+    let mut buckets: HashMap<u16, (u64, [u64; 3])> = HashMap::new();
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.0;
+        let key = ((r as u16 >> 4) << 8) | ((g as u16 >> 4) << 4) | (b as u16 >> 4);
+        let (pixels, sum) = buckets.entry(key).or_default();
+        *pixels += 1;
+        for (sum, c) in sum.iter_mut().zip(pixel.0) {
+            *sum += c as u64;
+        }
+    }
+
+    let mut buckets: Vec<_> = buckets.into_values().collect();
+    buckets.sort_unstable_by_key(|(pixels, _)| Reverse(*pixels));
+
+    buckets
+        .into_iter()
+        .take(count)
+        .map(|(pixels, [r, g, b])| {
+            Color32::from_rgb((r / pixels) as u8, (g / pixels) as u8, (b / pixels) as u8)
+        })
+        .collect()
+}
+
+impl GuiWasabiWindow {
+    pub fn show_palette_editor(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &mut WasabiSettings,
+        state: &mut WasabiState,
+    ) {
+        let frame = utils::create_window_frame(ctx);
+
+        let mut open = state.show_palette_editor;
+        let mut result = Ok(());
+        let mut saved = false;
+
+        egui::Window::new("Palette Editor")
+            .resizable(false)
+            .collapsible(false)
+            .title_bar(true)
+            .enabled(true)
+            .frame(frame)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let editor = &mut state.palette_editor;
+
+                ui.horizontal(|ui| {
+                    ui.label("Generate:");
+                    if ui.button("Evenly Spaced Hues").clicked() {
+                        editor.fill_even_hues();
+                    }
+                    if ui.button("Golden Ratio").clicked() {
+                        editor.fill_golden_ratio();
+                    }
+                    if ui.button("From Image...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Image", IMAGE_EXTENSIONS)
+                            .set_title("Pick an image to take the colors from")
+                            .pick_file()
+                        {
+                            result = editor.fill_from_image(&path);
+                        }
+                    }
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Replaces all the colors of the palette. The image\n\
+                        colors are its most common ones, repeated when\n\
+                        the palette has more colors than the image.\
+                        ",
+                    );
+                });
+                ui.add_space(4.0);
+
+                let mut remove = None;
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("palette_editor_grid")
+                            .num_columns(18)
+                            .spacing([4.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("");
+                                for channel in 1..=16 {
+                                    ui.vertical_centered(|ui| ui.strong(channel.to_string()));
+                                }
+                                ui.label("");
+                                ui.end_row();
+
+                                let single = editor.rows.len() == 1;
+                                for (i, row) in editor.rows.iter_mut().enumerate() {
+                                    ui.label(format!("Track {}", i + 1));
+                                    for color in row.iter_mut() {
+                                        ui.color_edit_button_srgba(color);
+                                    }
+                                    if ui
+                                        .add_enabled(!single, egui::Button::new("\u{2716}"))
+                                        .on_hover_text("Remove")
+                                        .clicked()
+                                    {
+                                        remove = Some(i);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                if let Some(i) = remove {
+                    editor.rows.remove(i);
+                }
+
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Add Track").clicked() {
+                        let last = editor.rows.last().copied().unwrap_or([Color32::WHITE; 16]);
+                        editor.rows.push(last);
+                    }
+                    if ui.button("Open...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Image", IMAGE_EXTENSIONS)
+                            .set_directory(WasabiSettings::get_palettes_dir())
+                            .set_title("Open Palette")
+                            .pick_file()
+                        {
+                            result = editor.load(&path);
+                        }
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("File Name:");
+                    ui.text_edit_singleline(&mut editor.name);
+
+                    let exists = editor.path().is_ok_and(|path| path.exists());
+                    let text = if exists { "Overwrite" } else { "Save" };
+                    if ui
+                        .button(text)
+                        .on_hover_text("Saves the palette into the palettes directory.")
+                        .clicked()
+                    {
+                        result = editor.save();
+                        saved = result.is_ok();
+                    }
+                });
+            });
+
+        if saved {
+            result = self.settings_win.load_palettes(settings);
+        }
+        if let Err(e) = result {
+            state.errors.error(&e);
+        }
+        state.show_palette_editor = open;
+    }
+}
@@ -1,4 +1,4 @@
-use super::GuiWasabiWindow;
+use super::{seek_bar::SeekBar, GuiWasabiWindow};
 
 use time::Duration;
 
@@ -182,16 +182,21 @@ impl GuiWasabiWindow {
                         - SPACE * 3.0;
 
                     ui.label(egui::RichText::new(time_text).font(timeid.clone()));
-                    let mut empty_slider =
-                        || ui.add(egui::Slider::new(&mut 0.0, 0.0..=1.0).show_value(false));
+                    let seek_bar_width = ui.spacing().slider_width;
+                    let mut empty_seek_bar =
+                        || ui.add(SeekBar::new(&mut 0.0, 0.0..=1.0, seek_bar_width));
                     if let Some(midi_file) = self.midi_file.as_mut() {
                         if let Some(length) = midi_file.midi_length() {
                             let mut time = midi_file.timer().get_time().as_seconds_f64();
                             let time_prev = time;
 
                             ui.add(
-                                egui::Slider::new(&mut time, -settings.midi.start_delay..=length)
-                                    .show_value(false),
+                                SeekBar::new(
+                                    &mut time,
+                                    -settings.midi.start_delay..=length,
+                                    seek_bar_width,
+                                )
+                                .timeline(midi_file.note_timeline()),
                             );
                             if (time_prev != time)
                                 && (midi_file.allows_seeking_backward() || time_prev < time)
@@ -199,10 +204,10 @@ impl GuiWasabiWindow {
                                 midi_file.timer_mut().seek(Duration::seconds_f64(time));
                             }
                         } else {
-                            empty_slider();
+                            empty_seek_bar();
                         }
                     } else {
-                        empty_slider();
+                        empty_seek_bar();
                     }
                     ui.label(egui::RichText::new(remaining_text).font(timeid.clone()));
 
@@ -233,12 +238,27 @@ impl GuiWasabiWindow {
                             if ui.button("Settings").clicked() {
                                 state.show_settings = true;
                             }
+                            if ui
+                                .add_enabled(self.midi_file.is_some(), egui::Button::new("Layers"))
+                                .clicked()
+                            {
+                                state.show_layers = true;
+                            }
                             if ui.button("Render").clicked() {
                                 if self.midi_file.take().is_some() {
                                     state.synth.reset();
                                 }
                                 state.show_render = true;
                             }
+                            if ui
+                                .add_enabled(
+                                    self.midi_file.is_some(),
+                                    egui::Button::new("Export MIDI"),
+                                )
+                                .clicked()
+                            {
+                                self.open_export(settings, state);
+                            }
                             if ui.button("Shortcuts").clicked() {
                                 state.show_shortcuts = true;
                             }
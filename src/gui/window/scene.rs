@@ -1,15 +1,28 @@
 mod cake_system;
+pub mod effects_system;
 pub mod note_list_system;
 mod pie_system;
 
-use egui::{Image, Ui};
+use std::sync::Arc;
+
+use egui::{Image, Rect, Ui};
+use vulkano::{
+    image::view::ImageView,
+    pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState},
+};
 
 use crate::{
-    midi::{MIDIColor, MIDIFileUnion},
+    midi::{MIDIColor, MIDIFileBase, MIDIFileUnion},
     scenes::SceneSwapchain,
+    settings::SceneSettings,
 };
 
-use self::{cake_system::CakeRenderer, note_list_system::NoteRenderer, pie_system::PieRenderer};
+use self::{
+    cake_system::CakeRenderer,
+    effects_system::{EffectsRenderer, KeyHit},
+    note_list_system::NoteRenderer,
+    pie_system::PieRenderer,
+};
 
 use super::{keyboard_layout::KeyboardView, GuiRenderer, GuiState};
 
@@ -64,15 +77,63 @@ impl CurrentRenderer {
     }
 }
 
+/// Alpha blending for the note pipelines, so that notes can be made translucent
+/// by the velocity opacity mode.
+fn note_blend_state() -> ColorBlendAttachmentState {
+    ColorBlendAttachmentState {
+        blend: Some(AttachmentBlend::alpha()),
+        ..Default::default()
+    }
+}
+
+/// Additive blending for the key hit effects, so they light up what is under them
+fn effect_blend_state() -> ColorBlendAttachmentState {
+    ColorBlendAttachmentState {
+        blend: Some(AttachmentBlend::additive()),
+        ..Default::default()
+    }
+}
+
 pub struct GuiRenderScene {
     swap_chain: SceneSwapchain,
     draw_system: CurrentRenderer,
+    /// Only created once the key hit effects are enabled
+    effects: Option<EffectsRenderer>,
 }
 
 pub struct RenderResultData {
     pub notes_rendered: u64,
     pub polyphony: Option<u64>,
     pub key_colors: Vec<Option<MIDIColor>>,
+    /// The notes pressing each key, for the key hit effects
+    pub key_hits: Vec<Option<KeyHit>>,
+}
+
+impl RenderResultData {
+    /// Combines the results of two layers, where `top` is drawn over this one
+    pub fn merge_under(self, top: RenderResultData) -> RenderResultData {
+        let polyphony = match (self.polyphony, top.polyphony) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+
+        RenderResultData {
+            notes_rendered: self.notes_rendered + top.notes_rendered,
+            polyphony,
+            key_colors: self
+                .key_colors
+                .into_iter()
+                .zip(top.key_colors)
+                .map(|(below, top)| top.or(below))
+                .collect(),
+            key_hits: self
+                .key_hits
+                .into_iter()
+                .zip(top.key_hits)
+                .map(|(below, top)| top.or(below))
+                .collect(),
+        }
+    }
 }
 
 impl GuiRenderScene {
@@ -80,47 +141,102 @@ impl GuiRenderScene {
         Self {
             swap_chain: SceneSwapchain::new(renderer.device.clone()),
             draw_system: CurrentRenderer::None,
+            effects: None,
         }
     }
 
+    /// Draws the notes over `rect` without allocating it, so that the scenes of
+    /// several layers can be drawn on top of each other
     pub fn draw(
         &mut self,
         state: &mut GuiState,
         ui: &mut Ui,
+        rect: Rect,
         key_view: &KeyboardView,
         midi_file: &mut MIDIFileUnion,
         view_range: f64,
+        settings: &SceneSettings,
     ) -> RenderResultData {
-        let size = ui.available_size();
-        let size = [size.x as u32, size.y as u32];
+        let size = [rect.width() as u32, rect.height() as u32];
 
         let scene_image = self.swap_chain.get_next_image(state, size);
         let frame = scene_image.image.clone();
+        let id = scene_image.id;
+
+        let result = self.draw_into(
+            state.renderer,
+            frame,
+            key_view,
+            midi_file,
+            view_range,
+            settings,
+        );
+
+        Image::new((id, rect.size())).paint_at(ui, rect);
+
+        result
+    }
 
+    /// Draws the notes and the key effects of the layer into `frame`, which is
+    /// cleared first
+    pub fn draw_into(
+        &mut self,
+        renderer: &GuiRenderer,
+        frame: Arc<ImageView>,
+        key_view: &KeyboardView,
+        midi_file: &mut MIDIFileUnion,
+        view_range: f64,
+        settings: &SceneSettings,
+    ) -> RenderResultData {
         let result = match midi_file {
-            MIDIFileUnion::InRam(file) => self
-                .draw_system
-                .get_note_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range, None, None),
-
-            MIDIFileUnion::Live(file) => self
-                .draw_system
-                .get_note_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range, None, None),
-
-            MIDIFileUnion::Cake(file) => self
-                .draw_system
-                .get_cake_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range),
-
-            MIDIFileUnion::Pie(file) => self
-                .draw_system
-                .get_pie_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range, None),
+            MIDIFileUnion::InRam(file) => self.draw_system.get_note_renderer(renderer).draw(
+                key_view,
+                frame.clone(),
+                file,
+                view_range,
+                None,
+                None,
+                settings,
+            ),
+
+            MIDIFileUnion::Live(file) => self.draw_system.get_note_renderer(renderer).draw(
+                key_view,
+                frame.clone(),
+                file,
+                view_range,
+                None,
+                None,
+                settings,
+            ),
+
+            MIDIFileUnion::Cake(file) => self.draw_system.get_cake_renderer(renderer).draw(
+                key_view,
+                frame.clone(),
+                file,
+                view_range,
+                settings,
+            ),
+
+            MIDIFileUnion::Pie(file) => self.draw_system.get_pie_renderer(renderer).draw(
+                key_view,
+                frame.clone(),
+                file,
+                view_range,
+                settings,
+            ),
         };
 
-        let img = Image::new((scene_image.id, [size[0] as f32, size[1] as f32].into()));
-        ui.add(img);
+        if settings.effects.is_enabled() {
+            let effects = self.effects.get_or_insert_with(|| {
+                EffectsRenderer::new(
+                    renderer.device.clone(),
+                    renderer.queue.clone(),
+                    renderer.format,
+                )
+            });
+            let time = midi_file.timer().get_time().as_seconds_f64();
+            effects.draw(frame, key_view, time, &result.key_hits, None, settings);
+        }
 
         result
     }
@@ -18,7 +18,7 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            color_blend::ColorBlendState,
             depth_stencil::{DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
@@ -41,9 +41,10 @@ use crate::{
         GuiRenderer,
     },
     midi::{CakeBlock, CakeMIDIFile, CakeSignature, IntVector4},
+    settings::SceneSettings,
 };
 
-use super::RenderResultData;
+use super::{effects_system::KeyHit, note_blend_state, RenderResultData};
 
 const BUFFER_ARRAY_LEN: u64 = 256;
 
@@ -218,7 +219,7 @@ impl CakeRenderer {
                 multisample_state: Some(MultisampleState::default()),
                 color_blend_state: Some(ColorBlendState::with_attachment_states(
                     subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
+                    note_blend_state(),
                 )),
                 depth_stencil_state: Some(DepthStencilState {
                     depth: Some(DepthState::simple()),
@@ -272,7 +273,9 @@ impl CakeRenderer {
         final_image: Arc<ImageView>,
         midi_file: &mut CakeMIDIFile,
         view_range: f64,
+        settings: &SceneSettings,
     ) -> RenderResultData {
+        let velocity = settings.velocity;
         let img_dims = final_image.image().extent();
         if self.depth_buffer.image().extent() != img_dims {
             self.depth_buffer = ImageView::new_default(
@@ -305,15 +308,23 @@ impl CakeRenderer {
         let screen_start = (midi_time * midi_file.ticks_per_second() as f64) as i32;
         let screen_end = ((midi_time + view_range) * midi_file.ticks_per_second() as f64) as i32;
 
+        let scene_dims = settings.scroll_direction.orient([img_dims[0], img_dims[1]]);
+
         let push_constants = gs::PushConstants {
             start_time: screen_start,
             end_time: screen_end,
-            screen_width: img_dims[0] as i32,
-            screen_height: img_dims[1] as i32,
+            screen_width: scene_dims[0] as i32,
+            screen_height: scene_dims[1] as i32,
+            velocity_mode: velocity.mode as i32,
+            note_style: settings.note_style.style as i32,
+            border_color: settings.note_style.packed_border_color(),
+            border_scale: settings.note_style.border_width,
+            shading: settings.note_style.shading,
+            scroll_direction: settings.scroll_direction as i32,
         };
 
         let border_width = crate::utils::calculate_border_width(
-            final_image.image().extent()[0] as f32,
+            scene_dims[0] as f32,
             key_view.visible_range.len() as f32,
         ) as i32;
 
@@ -445,10 +456,21 @@ impl CakeRenderer {
 
         // Calculate the metadata before awaiting the future
         // to keep this more efficient
-        let colors = midi_file
+        let ticks_per_second = midi_file.ticks_per_second() as f64;
+        let key_hits: Vec<_> = midi_file
             .key_blocks()
             .iter()
-            .map(|block| block.get_note_at(screen_start as u32).map(|n| n.color))
+            .map(|block| {
+                block.get_note_at(screen_start as u32).map(|n| KeyHit {
+                    start: n.start_time as f64 / ticks_per_second,
+                    velocity: n.velocity,
+                    color: n.color,
+                })
+            })
+            .collect();
+        let colors = key_hits
+            .iter()
+            .map(|hit| hit.map(|hit| hit.color))
             .collect();
         let rendered_notes = midi_file
             .key_blocks()
@@ -473,8 +495,9 @@ impl CakeRenderer {
 
         RenderResultData {
             notes_rendered: rendered_notes,
-            polyphony: None,
+            polyphony: Some(midi_file.timeline().polyphony_at(midi_time)),
             key_colors: colors,
+            key_hits,
         }
     }
 }
@@ -489,13 +512,15 @@ mod vs {
 mod gs {
     vulkano_shaders::shader! {
         ty: "geometry",
-        path: "shaders/cake/cake.geom"
+        path: "shaders/cake/cake.geom",
+        include: ["shaders/shared"],
     }
 }
 
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "shaders/cake/cake.frag"
+        path: "shaders/cake/cake.frag",
+        include: ["shaders/shared"],
     }
 }
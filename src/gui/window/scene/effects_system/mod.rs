@@ -0,0 +1,480 @@
+//! Effects drawn over the notes where they hit the keyboard
+//!
+//! The glows and the beams only depend on the notes pressing the keys in the
+//! current frame. The particles are started by the hits seen in the rendered
+//! frames, so a note starting and ending between two frames, or skipped by a
+//! seek, has none. Once started, they only move with the current time and the
+//! time their note started at, so the video export draws them like the GUI does
+//! at the same frame rate.
+
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo},
+        AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassContents,
+    },
+    device::{Device, Queue},
+    format::Format,
+    image::view::ImageView,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::Viewport,
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sync::{self, GpuFuture},
+};
+
+use crate::{
+    gui::window::keyboard_layout::KeyboardView,
+    midi::MIDIColor,
+    settings::{KeyEffectsSettings, SceneSettings},
+};
+
+use super::effect_blend_state;
+
+/// Seconds the particles of a hit stay visible
+const PARTICLE_LIFETIME: f64 = 0.8;
+
+/// Notes of the same key starting closer than this are the same hit
+const HIT_EPSILON: f64 = 0.001;
+
+const MAX_EFFECTS: usize = 1 << 16;
+
+const KIND_GLOW: u32 = 0;
+const KIND_BEAM: u32 = 1;
+const KIND_PARTICLE: u32 = 2;
+
+/// The note pressing a key, as reported by the note renderers
+#[derive(Debug, Clone, Copy)]
+pub struct KeyHit {
+    /// Time the note started at, in seconds
+    pub start: f64,
+    pub velocity: u8,
+    pub color: MIDIColor,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod, Vertex)]
+struct EffectVertex {
+    /// Left, near, right and far edges in the downward layout, in clip space
+    #[format(R32G32B32A32_SFLOAT)]
+    rect: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+    #[format(R32_UINT)]
+    kind: u32,
+}
+
+/// Builds the effects in pixels of the downward layout, where x goes along the keys
+/// and y goes up from the keyboard
+struct EffectBuilder {
+    size: [f32; 2],
+    vertices: Vec<EffectVertex>,
+}
+
+impl EffectBuilder {
+    fn push(&mut self, kind: u32, [left, right]: [f32; 2], [near, far]: [f32; 2], color: [f32; 4]) {
+        if self.vertices.len() >= MAX_EFFECTS {
+            return;
+        }
+
+        let x = |x: f32| x / self.size[0] * 2.0 - 1.0;
+        let y = |y: f32| 1.0 - y / self.size[1] * 2.0;
+        self.vertices.push(EffectVertex {
+            rect: [x(left), y(near), x(right), y(far)],
+            color,
+            kind,
+        });
+    }
+}
+
+fn color_with_alpha(color: MIDIColor, alpha: f32) -> [f32; 4] {
+    [
+        color.red() as f32 / 255.0,
+        color.green() as f32 / 255.0,
+        color.blue() as f32 / 255.0,
+        alpha,
+    ]
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+/// Three numbers from 0 to 1, always the same for a particle of a hit
+fn particle_random(key: usize, start: f64, particle: u32) -> [f32; 3] {
+    // Rounded, so the tiny differences between the reported start times don't matter
+    let start = (start * 1000.0).round() as i64 as u64;
+    let seed = splitmix64(start.rotate_left(8) ^ key as u64);
+    let bits = splitmix64(seed.wrapping_add(particle as u64));
+
+    let part = |n: u32| ((bits >> (n * 21)) & 0x1FFFFF) as f32 / 0x1FFFFF as f32;
+    [part(0), part(1), part(2)]
+}
+
+pub struct EffectsRenderer {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    allocator: Arc<StandardMemoryAllocator>,
+    cb_allocator: Arc<StandardCommandBufferAllocator>,
+    /// Notes which started recently, kept after they end so their particles finish
+    hits: Vec<(usize, KeyHit)>,
+}
+
+impl EffectsRenderer {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, format: Format) -> EffectsRenderer {
+        let allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+        // The effects are drawn over the notes, so the image is kept
+        let render_pass = vulkano::single_pass_renderpass!(device.clone(),
+            attachments: {
+                final_color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                },
+            },
+            pass: {
+                color: [final_color],
+                depth_stencil: {},
+            },
+        )
+        .unwrap();
+
+        let vs = vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .unwrap();
+        let gs = gs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .unwrap();
+
+        let vertex_input_state = EffectVertex::per_vertex().definition(&vs).unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+            PipelineShaderStageCreateInfo::new(gs),
+        ];
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState {
+                    topology: PrimitiveTopology::PointList,
+                    ..Default::default()
+                }),
+                viewport_state: Some(Default::default()),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    effect_blend_state(),
+                )),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap();
+
+        EffectsRenderer {
+            gfx_queue: queue,
+            pipeline,
+            render_pass,
+            allocator,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                device,
+                StandardCommandBufferAllocatorCreateInfo::default(),
+            )
+            .into(),
+            hits: Vec::new(),
+        }
+    }
+
+    /// Records the notes pressing the keys in this frame, and forgets the hits whose
+    /// particles are gone or which are after `time` after seeking backward. Notes
+    /// which ended before this frame are never recorded.
+    fn update_hits(&mut self, time: f64, key_hits: &[Option<KeyHit>]) {
+        let alive =
+            |hit: &KeyHit| hit.start <= time + HIT_EPSILON && time - hit.start < PARTICLE_LIFETIME;
+
+        self.hits.retain(|(_, hit)| alive(hit));
+
+        for (key, hit) in key_hits.iter().enumerate() {
+            let Some(hit) = hit.filter(alive) else {
+                continue;
+            };
+            let known = self
+                .hits
+                .iter()
+                .any(|(k, h)| *k == key && (h.start - hit.start).abs() < HIT_EPSILON);
+            if !known {
+                self.hits.push((key, hit));
+            }
+        }
+    }
+
+    fn build_effects(
+        &self,
+        builder: &mut EffectBuilder,
+        key_view: &KeyboardView,
+        time: f64,
+        key_hits: &[Option<KeyHit>],
+        settings: &KeyEffectsSettings,
+    ) {
+        let [keys_length, time_length] = builder.size;
+        let key_edges = |key: usize| {
+            let note = key_view.note(key);
+            [note.left * keys_length, note.right * keys_length]
+        };
+
+        // Beams first, so the glows and the particles are added over them
+        for (key, hit) in key_hits.iter().enumerate() {
+            let Some(hit) = hit else {
+                continue;
+            };
+            if !key_view.visible_range.contains(&key) {
+                continue;
+            }
+
+            let velocity = hit.velocity as f32 / 127.0;
+            let [left, right] = key_edges(key);
+            let width = right - left;
+            let center = (left + right) / 2.0;
+
+            if settings.light_beams {
+                let height = settings.beam_length * time_length;
+                let color = color_with_alpha(hit.color, 0.25 + 0.35 * velocity);
+                builder.push(KIND_BEAM, [left, right], [0.0, height], color);
+            }
+
+            if settings.key_glow {
+                let radius = (width * 1.5).max(6.0);
+                let height = (width * 3.0).clamp(8.0, time_length * 0.15);
+                let color = color_with_alpha(hit.color, 0.4 + 0.6 * velocity);
+                builder.push(
+                    KIND_GLOW,
+                    [center - radius, center + radius],
+                    [0.0, height],
+                    color,
+                );
+            }
+        }
+
+        if !settings.particles {
+            return;
+        }
+
+        for (key, hit) in self.hits.iter() {
+            if !key_view.visible_range.contains(key) {
+                continue;
+            }
+
+            let age = (time - hit.start).max(0.0) as f32;
+            let life = 1.0 - age / PARTICLE_LIFETIME as f32;
+            let velocity = hit.velocity as f32 / 127.0;
+            let [left, right] = key_edges(*key);
+            let width = right - left;
+            let center = (left + right) / 2.0;
+
+            let count = ((4.0 + 12.0 * velocity) * settings.particle_amount).round() as u32;
+            let gravity = time_length * 0.9;
+            let size = width.max(4.0) * 0.6 * (0.5 + 0.5 * life);
+            let color = color_with_alpha(hit.color, life * (0.5 + 0.5 * velocity));
+
+            for particle in 0..count {
+                let [angle, speed, offset] = particle_random(*key, hit.start, particle);
+                let angle = (angle - 0.5) * 1.2;
+                let speed = time_length * (0.25 + 0.55 * speed) * (0.5 + 0.5 * velocity);
+
+                let x = center + (offset - 0.5) * width + angle.sin() * speed * age;
+                let y = angle.cos() * speed * age - 0.5 * gravity * age * age;
+                if y < 0.0 {
+                    continue;
+                }
+
+                builder.push(
+                    KIND_PARTICLE,
+                    [x - size / 2.0, x + size / 2.0],
+                    [y - size / 2.0, y + size / 2.0],
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Draws the effects of the pressed keys over the notes already in `final_image`
+    pub fn draw(
+        &mut self,
+        final_image: Arc<ImageView>,
+        key_view: &KeyboardView,
+        time: f64,
+        key_hits: &[Option<KeyHit>],
+        viewport: Option<Viewport>,
+        settings: &SceneSettings,
+    ) {
+        self.update_hits(time, key_hits);
+
+        let img_dims = final_image.image().extent();
+        let viewport = viewport.unwrap_or(Viewport {
+            offset: [0.0, 0.0],
+            extent: [img_dims[0] as f32, img_dims[1] as f32],
+            depth_range: 0.0..=1.0,
+        });
+
+        let mut builder = EffectBuilder {
+            size: settings.scroll_direction.orient(viewport.extent),
+            vertices: Vec::new(),
+        };
+        self.build_effects(&mut builder, key_view, time, key_hits, &settings.effects);
+        if builder.vertices.is_empty() {
+            return;
+        }
+
+        let vertex_count = builder.vertices.len() as u32;
+        let vertex_buffer = Buffer::from_iter(
+            self.allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            builder.vertices,
+        )
+        .unwrap();
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.cb_allocator.clone(),
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let push_constants = gs::PushConstants {
+            scroll_direction: settings.scroll_direction as u32,
+        };
+
+        unsafe {
+            command_buffer_builder
+                .bind_pipeline_graphics(self.pipeline.clone())
+                .unwrap()
+                .set_viewport(0, vec![viewport].into())
+                .unwrap()
+                .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+                .unwrap()
+                .bind_vertex_buffers(0, vertex_buffer)
+                .unwrap()
+                .draw(vertex_count, 1, 0, 0)
+                .unwrap();
+        }
+
+        command_buffer_builder
+            .end_render_pass(Default::default())
+            .unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec4 rect;
+layout(location = 1) in vec4 color;
+layout(location = 2) in uint kind;
+
+layout(location = 0) out vec4 v_rect;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) out uint v_kind;
+
+void main() {
+    v_rect = rect;
+    v_color = color;
+    v_kind = kind;
+}"
+    }
+}
+
+mod gs {
+    vulkano_shaders::shader! {
+        ty: "geometry",
+        path: "shaders/effects/effects.geom",
+        include: ["shaders/shared"],
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/effects/effects.frag"
+    }
+}
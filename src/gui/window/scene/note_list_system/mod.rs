@@ -8,12 +8,13 @@ use vulkano::image::view::ImageView;
 use crate::{
     gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
     midi::{DisplacedMIDINote, MIDIColor, MIDIFile, MIDINoteColumnView, MIDINoteViews},
+    settings::SceneSettings,
     utils,
 };
 
 use self::notes_render_pass::{NotePassStatus, NoteRenderPass, NoteVertex};
 
-use super::RenderResultData;
+use super::{effects_system::KeyHit, RenderResultData};
 
 #[derive(Default)]
 struct ColumnReturnData {
@@ -70,7 +71,9 @@ impl NoteRenderer {
         view_range: f64,
         bg_color: Option<[f32; 4]>,
         viewport: Option<vulkano::pipeline::graphics::viewport::Viewport>,
+        settings: &SceneSettings,
     ) -> RenderResultData {
+        let velocity = settings.velocity;
         let note_views = midi_file.get_current_column_views(view_range);
 
         struct ColumnViewInfo<Iter: ExactSizeIterator<Item = DisplacedMIDINote> + Send> {
@@ -79,6 +82,7 @@ impl NoteRenderer {
             key: u8,
             remaining: usize,
             color: Option<MIDIColor>,
+            hit: Option<KeyHit>,
             border_width: f32,
         }
 
@@ -88,8 +92,9 @@ impl NoteRenderer {
 
         let mut columns_view_info = Vec::new();
 
+        let extent = final_image.image().extent();
         let border_width = utils::calculate_border_width(
-            final_image.image().extent()[0] as f32,
+            settings.scroll_direction.orient([extent[0], extent[1]])[0] as f32,
             key_view.visible_range.len() as f32,
         );
 
@@ -104,6 +109,7 @@ impl NoteRenderer {
                     key: i as u8,
                     remaining: length,
                     color: None,
+                    hit: None,
                     border_width,
                 });
                 total_notes += length;
@@ -121,6 +127,7 @@ impl NoteRenderer {
                     key: i as u8,
                     remaining: length,
                     color: None,
+                    hit: None,
                     border_width,
                 });
                 total_notes += length;
@@ -133,6 +140,7 @@ impl NoteRenderer {
         let mut cycle = 0;
 
         let view_range = note_views.range().length() as f32;
+        let time = note_views.range().start;
 
         self.render_pass.draw(
             final_image,
@@ -140,6 +148,7 @@ impl NoteRenderer {
             view_range,
             bg_color,
             viewport,
+            settings,
             |buffer| {
                 let buffer_length = buffer.len() as usize;
 
@@ -182,12 +191,21 @@ impl NoteRenderer {
                                         column.key,
                                         note.color.as_u32(),
                                         column.border_width as u32,
+                                        note.velocity,
                                     );
 
                                     if note.start <= 0.0 && note.start + note.len > 0.0 {
                                         poly += 1;
-                                        if column.color.is_none() {
+                                        // Notes hidden by the velocity filter don't light up keys
+                                        if column.color.is_none()
+                                            && note.velocity >= velocity.min_velocity
+                                        {
                                             column.color = Some(note.color);
+                                            column.hit = Some(KeyHit {
+                                                start: time + note.start as f64,
+                                                velocity: note.velocity,
+                                                color: note.color,
+                                            });
                                         }
                                     }
                                 } else {
@@ -236,6 +254,7 @@ impl NoteRenderer {
                 .iter()
                 .map(|column| column.color)
                 .collect(),
+            key_hits: columns_view_info.iter().map(|column| column.hit).collect(),
         }
     }
 }
@@ -16,7 +16,7 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            color_blend::ColorBlendState,
             depth_stencil::{DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
@@ -34,7 +34,12 @@ use vulkano::{
     sync::{self, future::FenceSignalFuture, GpuFuture},
 };
 
-use crate::gui::{window::keyboard_layout::KeyboardView, GuiRenderer};
+use crate::{
+    gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
+    settings::SceneSettings,
+};
+
+use super::super::note_blend_state;
 
 const NOTE_BUFFER_SIZE: u64 = 25000000;
 
@@ -47,14 +52,17 @@ pub struct NoteVertex {
     pub key_color: u32,
     #[format(R32_UINT)]
     pub border_width: u32,
+    #[format(R32_UINT)]
+    pub velocity: u32,
 }
 
 impl NoteVertex {
-    pub fn new(start: f32, len: f32, key: u8, color: u32, border_width: u32) -> Self {
+    pub fn new(start: f32, len: f32, key: u8, color: u32, border_width: u32, velocity: u8) -> Self {
         Self {
             start_length: [start, len],
             key_color: key as u32 | (color << 8),
             border_width,
+            velocity: velocity as u32,
         }
     }
 }
@@ -254,7 +262,7 @@ impl NoteRenderPass {
             multisample_state: Some(MultisampleState::default()),
             color_blend_state: Some(ColorBlendState::with_attachment_states(
                 subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
+                note_blend_state(),
             )),
             depth_stencil_state: Some(DepthStencilState {
                 depth: Some(DepthState::simple()),
@@ -422,7 +430,7 @@ impl NoteRenderPass {
             multisample_state: Some(MultisampleState::default()),
             color_blend_state: Some(ColorBlendState::with_attachment_states(
                 subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
+                note_blend_state(),
             )),
             depth_stencil_state: Some(DepthStencilState {
                 depth: Some(DepthState::simple()),
@@ -464,6 +472,7 @@ impl NoteRenderPass {
         view_range: f32,
         bg_color: Option<[f32; 4]>,
         viewport: Option<Viewport>,
+        settings: &SceneSettings,
         mut fill_buffer: impl FnMut(&Subbuffer<[NoteVertex]>) -> NotePassStatus,
     ) {
         let img_dims = final_image.image().extent();
@@ -573,10 +582,22 @@ impl NoteRenderPass {
                 )
                 .unwrap();
 
+            let view_dims = viewport
+                .as_ref()
+                .map_or([img_dims[0] as f32, img_dims[1] as f32], |vp| vp.extent);
+            let view_dims = settings.scroll_direction.orient(view_dims);
+
             let push_constants = gs::PushConstants {
                 height_time: view_range,
-                win_width: img_dims[0] as f32,
-                win_height: img_dims[1] as f32,
+                win_width: view_dims[0],
+                win_height: view_dims[1],
+                velocity_mode: settings.velocity.mode as u32,
+                min_velocity: settings.velocity.min_velocity as u32,
+                note_style: settings.note_style.style as u32,
+                border_color: settings.note_style.packed_border_color(),
+                border_scale: settings.note_style.border_width,
+                shading: settings.note_style.shading,
+                scroll_direction: settings.scroll_direction as u32,
             };
 
             unsafe {
@@ -648,6 +669,7 @@ mod gs {
     vulkano_shaders::shader! {
         ty: "geometry",
         path: "shaders/notes/notes.geom",
+        include: ["shaders/shared"],
     }
 }
 
@@ -659,15 +681,18 @@ mod vs {
 layout(location = 0) in vec2 start_length;
 layout(location = 1) in uint key_color;
 layout(location = 2) in uint border_width;
+layout(location = 3) in uint velocity;
 
 layout(location = 0) out vec2 v_start_length;
 layout(location = 1) out uint v_key_color;
 layout(location = 2) out uint v_border_width;
+layout(location = 3) out uint v_velocity;
 
 void main() {
     v_start_length = start_length;
     v_key_color = key_color;
     v_border_width = border_width;
+    v_velocity = velocity;
 }"
     }
 }
@@ -675,6 +700,7 @@ void main() {
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "shaders/notes/notes.frag"
+        path: "shaders/notes/notes.frag",
+        include: ["shaders/shared"],
     }
 }
@@ -18,7 +18,7 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            color_blend::ColorBlendState,
             depth_stencil::{DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
@@ -38,9 +38,10 @@ use vulkano::{
 use crate::{
     gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
     midi::{PieMIDIFile, PieSignature},
+    settings::SceneSettings,
 };
 
-use super::RenderResultData;
+use super::{effects_system::KeyHit, note_blend_state, RenderResultData};
 
 #[derive(Default, Debug, Copy, Clone, Zeroable, Pod, Vertex)]
 #[repr(C)]
@@ -170,7 +171,7 @@ impl PieRenderer {
                 multisample_state: Some(MultisampleState::default()),
                 color_blend_state: Some(ColorBlendState::with_attachment_states(
                     subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
+                    note_blend_state(),
                 )),
                 depth_stencil_state: Some(DepthStencilState {
                     depth: Some(DepthState::simple()),
@@ -209,7 +210,9 @@ impl PieRenderer {
         final_image: Arc<ImageView>,
         midi_file: &mut PieMIDIFile,
         view_range: f64,
+        settings: &SceneSettings,
     ) -> RenderResultData {
+        let velocity = settings.velocity;
         let img_dims = final_image.image().extent();
         if self.depth_buffer.image().extent() != img_dims {
             self.depth_buffer = ImageView::new_default(
@@ -307,15 +310,23 @@ impl PieRenderer {
         let screen_start = (midi_time * midi_file.ticks_per_second() as f64) as i32;
         let screen_end = ((midi_time + view_range) * midi_file.ticks_per_second() as f64) as i32;
 
+        let scene_dims = settings.scroll_direction.orient([img_dims[0], img_dims[1]]);
+
         let push_constants = gs::PushConstants {
             start_time: screen_start,
             end_time: screen_end,
-            screen_width: img_dims[0] as i32,
-            screen_height: img_dims[1] as i32,
+            screen_width: scene_dims[0] as i32,
+            screen_height: scene_dims[1] as i32,
+            velocity_mode: velocity.mode as i32,
+            note_style: settings.note_style.style as i32,
+            border_color: settings.note_style.packed_border_color(),
+            border_scale: settings.note_style.border_width,
+            shading: settings.note_style.shading,
+            scroll_direction: settings.scroll_direction as i32,
         };
 
         let border_width = crate::utils::calculate_border_width(
-            final_image.image().extent()[0] as f32,
+            scene_dims[0] as f32,
             key_view.visible_range.len() as f32,
         ) as i32;
 
@@ -467,8 +478,19 @@ impl PieRenderer {
         // Calculate the metadata before awaiting the future
         // to keep this more efficient
         let flat_blocks = midi_file.flat_blocks();
-        let colors = (0..flat_blocks.len())
-            .map(|key| flat_blocks.get_note_at(key, screen_start).map(|n| n.color))
+        let ticks_per_second = midi_file.ticks_per_second() as f64;
+        let key_hits: Vec<_> = (0..flat_blocks.len())
+            .map(|key| {
+                flat_blocks.get_note_at(key, screen_start).map(|n| KeyHit {
+                    start: n.start_time as f64 / ticks_per_second,
+                    velocity: n.velocity,
+                    color: n.color,
+                })
+            })
+            .collect();
+        let colors = key_hits
+            .iter()
+            .map(|hit| hit.map(|hit| hit.color))
             .collect();
         let rendered_notes = (0..flat_blocks.len())
             .map(|key| {
@@ -491,8 +513,9 @@ impl PieRenderer {
 
         RenderResultData {
             notes_rendered: rendered_notes,
-            polyphony: None,
+            polyphony: Some(midi_file.timeline().polyphony_at(midi_time)),
             key_colors: colors,
+            key_hits,
         }
     }
 }
@@ -507,13 +530,15 @@ mod vs {
 mod gs {
     vulkano_shaders::shader! {
         ty: "geometry",
-        path: "shaders/pie/pie.geom"
+        path: "shaders/pie/pie.geom",
+        include: ["shaders/shared"],
     }
 }
 
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "shaders/pie/pie.frag"
+        path: "shaders/pie/pie.frag",
+        include: ["shaders/shared"],
     }
 }
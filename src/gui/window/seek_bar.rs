@@ -0,0 +1,147 @@
+use std::ops::RangeInclusive;
+
+use egui::{Mesh, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2, Widget};
+use numfmt::{Formatter, Precision};
+
+use crate::{midi::NoteTimeline, utils::convert_seconds_to_time_string};
+
+const SEEK_BAR_HEIGHT: f32 = 22.0;
+
+/// A seek bar which shows the note density of the whole MIDI as a histogram
+/// behind the playhead. Clicking or dragging it changes the time.
+pub struct SeekBar<'a> {
+    time: &'a mut f64,
+    range: RangeInclusive<f64>,
+    timeline: Option<&'a NoteTimeline>,
+    width: f32,
+}
+
+impl<'a> SeekBar<'a> {
+    pub fn new(time: &'a mut f64, range: RangeInclusive<f64>, width: f32) -> Self {
+        Self {
+            time,
+            range,
+            timeline: None,
+            width,
+        }
+    }
+
+    pub fn timeline(mut self, timeline: Option<&'a NoteTimeline>) -> Self {
+        self.timeline = timeline;
+        self
+    }
+
+    fn time_at(&self, rect: Rect, x: f32) -> f64 {
+        let (start, end) = (*self.range.start(), *self.range.end());
+        let t = ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) as f64;
+        start + (end - start) * t
+    }
+
+    fn x_at(&self, rect: Rect, time: f64) -> f32 {
+        let (start, end) = (*self.range.start(), *self.range.end());
+        if end <= start {
+            return rect.left();
+        }
+        let t = ((time - start) / (end - start)).clamp(0.0, 1.0) as f32;
+        rect.left() + rect.width() * t
+    }
+
+    fn draw_histogram(&self, ui: &Ui, rect: Rect, played_x: f32) {
+        let Some(timeline) = self.timeline else {
+            return;
+        };
+
+        let bins = rect.width().max(1.0) as usize;
+        let density = timeline.density_histogram(self.range.clone(), bins);
+        let max = density.iter().cloned().fold(0.0, f64::max);
+        if max <= 0.0 {
+            return;
+        }
+
+        let visuals = ui.visuals();
+        let played = visuals.selection.bg_fill;
+        let unplayed = visuals.widgets.inactive.fg_stroke.color.gamma_multiply(0.5);
+
+        let bin_width = rect.width() / bins as f32;
+        let mut mesh = Mesh::default();
+        for (i, nps) in density.into_iter().enumerate() {
+            // Square root scaling keeps the quieter sections visible next to dense ones
+            let height = ((nps / max).sqrt() as f32 * rect.height()).max(1.0);
+            let left = rect.left() + bin_width * i as f32;
+            let color = if left < played_x { played } else { unplayed };
+
+            mesh.add_colored_rect(
+                Rect::from_min_max(
+                    Pos2::new(left, rect.bottom() - height),
+                    Pos2::new(left + bin_width, rect.bottom()),
+                ),
+                color,
+            );
+        }
+        ui.painter().add(mesh);
+    }
+}
+
+impl Widget for SeekBar<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, mut response) = ui.allocate_exact_size(
+            Vec2::new(self.width, SEEK_BAR_HEIGHT),
+            Sense::click_and_drag(),
+        );
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            let time = self.time_at(rect, pos.x);
+            if time != *self.time {
+                *self.time = time;
+                response.mark_changed();
+            }
+        }
+
+        if ui.is_rect_visible(rect) {
+            let visuals = ui.visuals();
+            let rounding = 4.0;
+
+            ui.painter()
+                .rect_filled(rect, rounding, visuals.extreme_bg_color);
+
+            let played_x = self.x_at(rect, *self.time);
+            if self.timeline.is_some() {
+                self.draw_histogram(ui, rect, played_x);
+            } else {
+                // Without a timeline, display the played part like a regular slider
+                let played = Rect::from_min_max(rect.min, Pos2::new(played_x, rect.max.y));
+                ui.painter()
+                    .rect_filled(played, rounding, visuals.selection.bg_fill);
+            }
+
+            ui.painter().vline(
+                played_x,
+                rect.y_range(),
+                Stroke::new(2.0, visuals.strong_text_color()),
+            );
+
+            if let Some(pos) = response.hover_pos() {
+                ui.painter().vline(
+                    pos.x,
+                    rect.y_range(),
+                    Stroke::new(1.0, visuals.weak_text_color()),
+                );
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let time = self.time_at(rect, pos.x);
+            let mut text = convert_seconds_to_time_string(time);
+            if let Some(timeline) = self.timeline {
+                let mut f = Formatter::new()
+                    .separator(',')
+                    .unwrap()
+                    .precision(Precision::Decimals(0));
+                text += &format!("\nNPS: {}", f.fmt2(timeline.nps_at(time)));
+            }
+            response = response.on_hover_text_at_pointer(text);
+        }
+
+        response
+    }
+}
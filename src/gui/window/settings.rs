@@ -1,8 +1,11 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use soundfonts::EguiSFList;
 
+use vulkano::instance::Instance;
+
 use crate::{
+    renderer::devices::{list_gpus, GpuInfo},
     settings::{Colors, Synth, WasabiSettings},
     state::{SettingsTab, WasabiState},
     utils,
@@ -35,6 +38,7 @@ pub struct SettingsWindow {
     palettes: Vec<FilePalette>,
     #[cfg(all(supported_os, not(target_os = "freebsd")))]
     midi_devices: Vec<MidiDevice>,
+    gpus: Vec<GpuInfo>,
     sf_list: EguiSFList,
 }
 
@@ -49,6 +53,7 @@ impl SettingsWindow {
             palettes: Vec::new(),
             #[cfg(all(supported_os, not(target_os = "freebsd")))]
             midi_devices: Vec::new(),
+            gpus: Vec::new(),
             sf_list,
         }
     }
@@ -190,6 +195,15 @@ impl SettingsWindow {
         Ok(())
     }
 
+    pub fn load_gpus(&mut self, instance: &Arc<Instance>) -> Result<(), WasabiError> {
+        self.gpus = list_gpus(instance)?;
+        Ok(())
+    }
+
+    pub fn gpus(&self) -> &[GpuInfo] {
+        &self.gpus
+    }
+
     #[cfg(any(not(supported_os), target_os = "freebsd"))]
     pub fn load_midi_devices(&mut self, _settings: &mut WasabiSettings) -> Result<(), WasabiError> {
         Ok(())
@@ -1,7 +1,7 @@
 use egui_extras::{Column, TableBuilder};
 
 use crate::{
-    settings::{Colors, MidiParsing, WasabiSettings},
+    settings::{ColorBy, Colors, MidiParsing, NoteOffMatching, PaletteOrder, WasabiSettings},
     state::WasabiState,
 };
 
@@ -12,7 +12,7 @@ impl SettingsWindow {
         &mut self,
         ui: &mut egui::Ui,
         settings: &mut WasabiSettings,
-        state: &WasabiState,
+        state: &mut WasabiState,
         width: f32,
     ) {
         ui.vertical_centered(|ui| {
@@ -30,6 +30,9 @@ impl SettingsWindow {
                     ui.label("MIDI Parsing Algorithm:");
                     ui.monospace("\u{2139}").on_hover_text(
                         "\
+                    - Auto\n\
+                  \0    Picks one of the algorithms below for each MIDI,\n\
+                  \0    based on its size and the free memory.\n\
                     - Pie\n\
                   \0    Modified version of Cake.\n\
                   \0    Flattened tree structure, uses a linear memory layout.\n\
@@ -38,8 +41,7 @@ impl SettingsWindow {
                     - Cake\n\
                   \0    The most efficient loading and displaying algorithm.\n\
                   \0    The notes will be stored in binary trees and will be\n\
-                  \0    displayed dynamically. This mode does not support\n\
-                  \0    polyphony statistics.\n\
+                  \0    displayed dynamically.\n\
                     - Standard (RAM)\n\
                   \0    The MIDI will be loaded in the RAM and all the notes\n\
                   \0    will be rendered normally by the GPU.\n\
@@ -52,6 +54,11 @@ impl SettingsWindow {
                 egui::ComboBox::from_id_salt("midi_parsing_select")
                     .selected_text(settings.midi.parsing.as_str())
                     .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut settings.midi.parsing,
+                            MidiParsing::Auto,
+                            MidiParsing::Auto.as_str(),
+                        );
                         ui.selectable_value(
                             &mut settings.midi.parsing,
                             MidiParsing::Cake,
@@ -75,6 +82,71 @@ impl SettingsWindow {
                     });
                 ui.end_row();
 
+                ui.horizontal(|ui| {
+                    ui.label("Parallel Parsing:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Parses all the tracks of the MIDI at the same time\n\
+                    and builds the notes of each key in parallel.\n\
+                    Loads much faster on CPUs with many cores, but\n\
+                    needs more RAM while loading.\n\
+                    Only used by the Cake and Pie algorithms.\
+                    ",
+                    );
+                });
+                ui.add_enabled(
+                    matches!(
+                        settings.midi.parsing,
+                        MidiParsing::Cake | MidiParsing::Pie | MidiParsing::Auto
+                    ),
+                    egui::Checkbox::without_text(&mut settings.midi.parallel_parsing),
+                );
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Note Off Matching:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Which note is ended by a note off event when the\n\
+                    same key is pressed multiple times on a channel.\n\
+                    Applies to both the rendered notes and the audio,\n\
+                    except for Newest Note: the synth can't pick which\n\
+                    note a note off ends, so the audio always ends\n\
+                    the oldest one.\n\
+                    Changes are applied when the next MIDI is loaded.\
+                    ",
+                    );
+                });
+                egui::ComboBox::from_id_salt("note_off_matching_select")
+                    .selected_text(settings.midi.note_off_matching.as_str())
+                    .show_ui(ui, |ui| {
+                        for matching in NoteOffMatching::iter() {
+                            ui.selectable_value(
+                                &mut settings.midi.note_off_matching,
+                                *matching,
+                                matching.as_str(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Transpose:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Number of semitones added to every note while\n\
+                    loading. Notes pushed outside of the 0-255 key\n\
+                    range are dropped.\
+                    ",
+                    );
+                });
+                ui.add(
+                    egui::DragValue::new(&mut settings.midi.transpose)
+                        .speed(1)
+                        .range(-127..=127),
+                );
+                ui.end_row();
+
                 ui.label("Start Delay (s):");
                 ui.add(
                     egui::DragValue::new(&mut settings.midi.start_delay)
@@ -82,6 +154,91 @@ impl SettingsWindow {
                         .range(0.0..=100.0),
                 );
                 ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Remember Per File:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Saves the colors, the random color seed, the key\n\
+                        range, the note speed, the start delay, the mute\n\
+                        and the playback position of each MIDI when it's\n\
+                        closed, and restores them when it's opened again.\
+                        ",
+                    );
+                });
+                ui.checkbox(&mut settings.midi.remember_files, "");
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| ui.add_space(width + 40.0));
+        ui.add_space(super::CATEG_SPACE);
+        ui.horizontal(|ui| {
+            ui.heading("Note Filters");
+            ui.monospace("\u{2139}").on_hover_text(
+                "\
+                Removes notes while the MIDI is loading, which reduces\n\
+                the memory usage of very large MIDIs. The notes are\n\
+                removed from both the visuals and the audio, and the\n\
+                number of removed notes is shown next to the note count.\
+                ",
+            );
+        });
+        egui::Grid::new("midi_filters_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let filters = &mut settings.midi.filters;
+
+                ui.label("Minimum Velocity:");
+                ui.add(
+                    egui::DragValue::new(&mut filters.min_velocity)
+                        .speed(1)
+                        .range(1..=127),
+                );
+                ui.end_row();
+
+                ui.label("Minimum Length (ms):");
+                ui.add(
+                    egui::DragValue::new(&mut filters.min_length)
+                        .speed(1.0)
+                        .range(0.0..=1000.0),
+                );
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Remove Duplicate Notes:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Removes notes which start at the same time as\n\
+                    another note of the same key, track and channel\n\
+                    and end before it, so they are fully covered.\
+                    ",
+                    );
+                });
+                ui.checkbox(&mut filters.remove_duplicates, "");
+                ui.end_row();
+
+                ui.label("Limit Key Range:");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut filters.limit_key_range, "");
+                    let mut firstkey = *filters.key_range.start();
+                    let mut lastkey = *filters.key_range.end();
+                    ui.add_enabled_ui(filters.limit_key_range, |ui| {
+                        ui.add(egui::DragValue::new(&mut firstkey).speed(1).range(0..=127));
+                        ui.add(
+                            egui::DragValue::new(&mut lastkey)
+                                .speed(1)
+                                .range(firstkey..=127),
+                        );
+                    });
+                    if firstkey != *filters.key_range.start() || lastkey != *filters.key_range.end()
+                    {
+                        filters.key_range = firstkey..=lastkey;
+                    }
+                });
+                ui.end_row();
             });
 
         ui.horizontal(|ui| ui.add_space(width + 40.0));
@@ -98,6 +255,29 @@ impl SettingsWindow {
                 ",
             );
         });
+        ui.horizontal(|ui| {
+            ui.label("Color By:");
+            egui::ComboBox::from_id_salt("color_by_select")
+                .selected_text(settings.midi.color_by.as_str())
+                .show_ui(ui, |ui| {
+                    for color_by in ColorBy::iter() {
+                        ui.selectable_value(
+                            &mut settings.midi.color_by,
+                            *color_by,
+                            color_by.as_str(),
+                        );
+                    }
+                });
+            ui.monospace("\u{2139}").on_hover_text(
+                "\
+                Picks the color of each note from its track and\n\
+                channel, only its track or channel, its pitch class\n\
+                (C, C#, D...), its octave or its velocity. Palettes\n\
+                are then read pixel by pixel, row after row.\
+                ",
+            );
+        });
+        ui.add_space(4.0);
         egui::Frame::default()
             .corner_radius(egui::CornerRadius::same(8))
             .stroke(ui.style().visuals.widgets.noninteractive.bg_stroke)
@@ -150,6 +330,19 @@ impl SettingsWindow {
                                 }
                             });
                         });
+                        body.row(row_height, |mut row| {
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        settings.midi.colors == Colors::Gradient,
+                                        Colors::Gradient.as_str(),
+                                    )
+                                    .clicked()
+                                {
+                                    settings.midi.colors = Colors::Gradient;
+                                }
+                            });
+                        });
                         let mut temp = self.palettes.clone();
                         for i in temp.iter_mut() {
                             i.selected = false;
@@ -193,9 +386,62 @@ impl SettingsWindow {
             if ui.button("Open Palettes Directory").clicked() {
                 open::that(WasabiSettings::get_palettes_dir()).unwrap_or_default();
             }
+            if ui.button("Palette Editor").clicked() {
+                state.show_palette_editor = true;
+            }
+            ui.separator();
+            ui.label("Gradient:");
+            let [first, last] = &mut settings.midi.gradient;
+            ui.color_edit_button_srgba(first);
+            ui.color_edit_button_srgba(last);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Palette Order:");
+            egui::ComboBox::from_id_salt("palette_order_select")
+                .selected_text(settings.midi.palette_order.as_str())
+                .show_ui(ui, |ui| {
+                    for order in PaletteOrder::iter() {
+                        ui.selectable_value(
+                            &mut settings.midi.palette_order,
+                            *order,
+                            order.as_str(),
+                        );
+                    }
+                });
+            ui.monospace("\u{2139}").on_hover_text(
+                "\
+                Per Track gives each track a row of the palette and each\n\
+                channel a column. Per Channel swaps them, giving each\n\
+                track a column. Palettes with 32 columns, like some\n\
+                Zenith ones, use the first of every two columns.\
+                ",
+            );
             ui.separator();
             ui.checkbox(&mut settings.midi.randomize_palette, " Randomize Palette")
-                .on_hover_text("Does not affect \"Rainbow\" and \"Random\" palettes.");
+                .on_hover_text("Only affects the palette images.");
+            ui.add_enabled(
+                !settings.midi.randomize_palette,
+                egui::Checkbox::new(&mut settings.midi.palette_random_offset, " Random Offset"),
+            )
+            .on_hover_text("Starts reading the palette at a random track.");
+            ui.separator();
+            if ui
+                .button("Import Zenith Settings...")
+                .on_hover_text("Uses the palette and the palette options of Zenith.")
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Zenith Color Settings", &["json"])
+                    .set_title("Import Zenith Color Settings")
+                    .pick_file()
+                {
+                    let result = settings
+                        .midi
+                        .import_zenith_colors(&path)
+                        .and_then(|_| self.load_palettes(settings));
+                    result.unwrap_or_else(|e| state.errors.error(&e));
+                }
+            }
         });
     }
 }
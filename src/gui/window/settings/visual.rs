@@ -1,10 +1,68 @@
+use std::path::PathBuf;
+
 use egui::WidgetText;
 use egui_extras::{Column, TableBuilder};
 
-use crate::{settings::WasabiSettings, utils::NOTE_SPEED_RANGE};
+use crate::{
+    renderer::devices::GpuInfo,
+    settings::{
+        BackgroundFit, GpuPreference, KeyStyle, KeyboardType, NoteStyle, ScrollDirection,
+        VelocityMode, WasabiSettings,
+    },
+    utils::{IMAGE_EXTENSIONS, KEY_RANGE_PRESETS, NOTE_SPEED_RANGE},
+};
 
 use super::SettingsWindow;
 
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "avi"];
+
+/// Shows the name of the selected file, with buttons to pick another one or remove it
+fn background_file_picker(
+    ui: &mut egui::Ui,
+    path: &mut Option<PathBuf>,
+    filter: &str,
+    extensions: &[&str],
+) {
+    ui.horizontal(|ui| {
+        if ui.button("Browse...").clicked() {
+            if let Some(picked) = rfd::FileDialog::new()
+                .add_filter(filter, extensions)
+                .set_title(format!("Select Background {filter}"))
+                .pick_file()
+            {
+                *path = Some(picked);
+            }
+        }
+        if path.is_some() && ui.button("\u{2716}").on_hover_text("Remove").clicked() {
+            *path = None;
+        }
+        if let Some(name) = path.as_ref().and_then(|p| p.file_name()) {
+            ui.label(name.to_string_lossy());
+        }
+    });
+}
+
+/// Shows the GPUs, with an automatic choice for the most performant one
+fn gpu_select(ui: &mut egui::Ui, id: &str, gpus: &[GpuInfo], gpu: &mut Option<GpuPreference>) {
+    let selected = match gpu {
+        None => "Automatic".to_owned(),
+        Some(preference) => gpus
+            .iter()
+            .find(|g| g.preference() == *preference)
+            .map(|g| g.label())
+            .unwrap_or_else(|| format!("{} (Unavailable)", preference.name)),
+    };
+
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(gpu, None, "Automatic");
+            for g in gpus {
+                ui.selectable_value(gpu, Some(g.preference()), g.label());
+            }
+        });
+}
+
 impl SettingsWindow {
     pub fn show_visual_settings(
         &mut self,
@@ -42,6 +100,110 @@ impl SettingsWindow {
                         .range(0.0..=f64::MAX),
                 );
                 ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Graphics Device:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        The GPU used to draw the window. Changes are\n\
+                        applied after saving the settings and restarting.\
+                        ",
+                    );
+                });
+                gpu_select(
+                    ui,
+                    "render_device_select",
+                    &self.gpus,
+                    &mut settings.gui.render_device,
+                );
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Video Export Device:");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        The GPU used to render exported videos. It can\n\
+                        be another GPU than the one drawing the window.\
+                        ",
+                    );
+                });
+                gpu_select(
+                    ui,
+                    "export_device_select",
+                    &self.gpus,
+                    &mut settings.gui.export_device,
+                );
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Screenshots");
+
+        egui::Grid::new("screenshot_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let screenshot = &mut settings.gui.screenshot;
+
+                ui.horizontal(|ui| {
+                    ui.label("Custom Resolution: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Screenshots are taken with F12, at the size of\n\
+                        the window unless a resolution is set here. They\n\
+                        show the loaded layers as they are on the screen.\
+                        ",
+                    );
+                });
+                let mut custom = screenshot.resolution.is_some();
+                ui.checkbox(&mut custom, "");
+                ui.end_row();
+                if custom != screenshot.resolution.is_some() {
+                    screenshot.resolution = custom.then_some([3840, 2160]);
+                }
+
+                ui.label("Resolution: ");
+                ui.add_enabled_ui(custom, |ui| {
+                    let [mut w, mut h] = screenshot.resolution.unwrap_or([3840, 2160]);
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut w).speed(8).range(16..=16384));
+                        ui.label("x");
+                        ui.add(egui::DragValue::new(&mut h).speed(8).range(16..=16384));
+                    });
+                    if custom {
+                        screenshot.resolution = Some([w, h]);
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Include Statistics: ");
+                ui.checkbox(&mut screenshot.statistics, "");
+                ui.end_row();
+
+                ui.label("Folder: ");
+                ui.horizontal(|ui| {
+                    if ui.button("Browse...").clicked() {
+                        if let Some(picked) = rfd::FileDialog::new()
+                            .set_title("Select Screenshot Folder")
+                            .pick_folder()
+                        {
+                            screenshot.folder = Some(picked);
+                        }
+                    }
+                    if screenshot.folder.is_some()
+                        && ui.button("\u{2716}").on_hover_text("Reset").clicked()
+                    {
+                        screenshot.folder = None;
+                    }
+                    let folder = screenshot
+                        .folder
+                        .clone()
+                        .unwrap_or_else(WasabiSettings::get_screenshots_dir);
+                    ui.label(folder.to_string_lossy());
+                });
+                ui.end_row();
             });
 
         ui.add_space(super::CATEG_SPACE);
@@ -64,13 +226,27 @@ impl SettingsWindow {
                 ui.label("Keyboard Range: ");
                 let mut firstkey = *settings.scene.key_range.start();
                 let mut lastkey = *settings.scene.key_range.end();
-                ui.horizontal(|ui| {
-                    ui.add(egui::DragValue::new(&mut firstkey).speed(1).range(0..=253));
-                    ui.add(
-                        egui::DragValue::new(&mut lastkey)
-                            .speed(1)
-                            .range(firstkey + 1..=254),
-                    );
+                ui.add_enabled_ui(!settings.scene.auto_fit_keys, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut firstkey).speed(1).range(0..=254));
+                        ui.add(
+                            egui::DragValue::new(&mut lastkey)
+                                .speed(1)
+                                .range(firstkey + 1..=255),
+                        );
+                    });
+                });
+                ui.end_row();
+
+                ui.label("Range Presets: ");
+                ui.add_enabled_ui(!settings.scene.auto_fit_keys, |ui| {
+                    ui.horizontal(|ui| {
+                        for (name, range) in KEY_RANGE_PRESETS {
+                            if ui.button(name).clicked() {
+                                (firstkey, lastkey) = (*range.start(), *range.end());
+                            }
+                        }
+                    });
                 });
                 ui.end_row();
                 if firstkey != *settings.scene.key_range.start()
@@ -79,6 +255,37 @@ impl SettingsWindow {
                     settings.scene.key_range = firstkey..=lastkey;
                 }
 
+                ui.horizontal(|ui| {
+                    ui.label("Auto-fit Range: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Shows only the keys between the lowest and the\n\
+                    highest note of the loaded MIDI, instead of the\n\
+                    keyboard range above.\
+                    ",
+                    );
+                });
+                ui.checkbox(&mut settings.scene.auto_fit_keys, "");
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Live Transpose: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                    Shifts the audio and the keyboard view by the given\n\
+                    number of semitones without reloading the MIDI.\n\
+                    Can also be changed with Page Up and Page Down.\n\
+                    Resets when Wasabi is restarted.\
+                    ",
+                    );
+                });
+                ui.add(
+                    egui::DragValue::new(&mut settings.scene.live_transpose)
+                        .speed(1)
+                        .range(-127..=127),
+                );
+                ui.end_row();
+
                 ui.label("Note Speed: ");
                 ui.spacing_mut().slider_width = width / 2.0 - 100.0;
                 ui.add(
@@ -86,6 +293,375 @@ impl SettingsWindow {
                         .logarithmic(true),
                 );
                 ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Scroll Direction: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Downward: the keyboard is at the bottom.\n\
+                        Upward: the keyboard is at the top.\n\
+                        Horizontal: the keyboard is on the left\n\
+                        and the notes move to the left.\
+                        ",
+                    );
+                });
+                egui::ComboBox::from_id_salt("scroll_direction_select")
+                    .selected_text(settings.scene.scroll_direction.as_str())
+                    .show_ui(ui, |ui| {
+                        for direction in ScrollDirection::iter() {
+                            ui.selectable_value(
+                                &mut settings.scene.scroll_direction,
+                                *direction,
+                                direction.as_str(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Note Velocity: ");
+                egui::ComboBox::from_id_salt("velocity_mode_select")
+                    .selected_text(settings.scene.velocity.mode.as_str())
+                    .show_ui(ui, |ui| {
+                        for mode in VelocityMode::iter() {
+                            ui.selectable_value(
+                                &mut settings.scene.velocity.mode,
+                                *mode,
+                                mode.as_str(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Minimum Velocity: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Notes with a lower velocity will be hidden.\n\
+                        With the Cake and Pie algorithms, changes are\n\
+                        applied when the next MIDI is loaded.\
+                        ",
+                    );
+                });
+                ui.add(
+                    egui::DragValue::new(&mut settings.scene.velocity.min_velocity)
+                        .speed(1)
+                        .range(0..=127),
+                );
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Background");
+
+        egui::Grid::new("background_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let background = &mut settings.scene.background;
+
+                ui.label("Image: ");
+                background_file_picker(ui, &mut background.image, "Image", IMAGE_EXTENSIONS);
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Video: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Only used when rendering videos, where it\n\
+                        replaces the image. The frames are decoded\n\
+                        with FFmpeg and the video loops until the\n\
+                        end of the render.\
+                        ",
+                    );
+                });
+                background_file_picker(ui, &mut background.video, "Video", VIDEO_EXTENSIONS);
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Fit: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Fit: shows the whole background.\n\
+                        Fill: covers the notes area and crops the rest.\n\
+                        Tile: repeats the image at its original size.\n\
+                        Videos are filled when Tile is selected.\
+                        ",
+                    );
+                });
+                egui::ComboBox::from_id_salt("background_fit_select")
+                    .selected_text(background.fit.as_str())
+                    .show_ui(ui, |ui| {
+                        for fit in BackgroundFit::iter() {
+                            ui.selectable_value(&mut background.fit, *fit, fit.as_str());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Dim: ");
+                ui.add(egui::Slider::new(&mut background.dim, 0.0..=1.0));
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Notes");
+
+        egui::Grid::new("notes_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let note_style = &mut settings.scene.note_style;
+
+                ui.label("Note Style: ");
+                egui::ComboBox::from_id_salt("note_style_select")
+                    .selected_text(note_style.style.as_str())
+                    .show_ui(ui, |ui| {
+                        for style in NoteStyle::iter() {
+                            ui.selectable_value(&mut note_style.style, *style, style.as_str());
+                        }
+                    });
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Border Width: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Multiplies the default border width, which\n\
+                        depends on the width of the keys.\
+                        ",
+                    );
+                });
+                ui.add(egui::Slider::new(&mut note_style.border_width, 0.0..=4.0));
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Custom Border Color: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        When disabled, the border color is\n\
+                        derived from the color of each note.\
+                        ",
+                    );
+                });
+                ui.horizontal(|ui| {
+                    let mut custom = note_style.border_color.is_some();
+                    if ui.checkbox(&mut custom, "").changed() {
+                        note_style.border_color = custom.then_some(egui::Color32::BLACK);
+                    }
+                    if let Some(color) = note_style.border_color.as_mut() {
+                        ui.color_edit_button_srgba(color);
+                    }
+                });
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Shading: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        The strength of the gradient of the Classic\n\
+                        Gradient and Rounded styles, the darkening\n\
+                        of the inside of Outlined notes and the\n\
+                        brightness of the center of Glow notes.\
+                        ",
+                    );
+                });
+                ui.add(egui::Slider::new(&mut note_style.shading, 0.0..=1.0));
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Grid");
+
+        egui::Grid::new("grid_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let grid = &mut settings.scene.grid;
+
+                ui.horizontal(|ui| {
+                    ui.label("Show Bar Lines: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Draws a line at every bar and beat under the\n\
+                        notes, following the tempo and time signature\n\
+                        events of the MIDI. With the Live loader, the\n\
+                        lines appear once the file has been scanned.\
+                        ",
+                    );
+                });
+                ui.checkbox(&mut grid.enabled, "");
+                ui.end_row();
+
+                ui.label("Bar Color: ");
+                ui.color_edit_button_srgba(&mut grid.bar_color);
+                ui.end_row();
+
+                ui.label("Beat Color: ");
+                ui.color_edit_button_srgba(&mut grid.beat_color);
+                ui.end_row();
+
+                ui.label("Bar Numbers: ");
+                ui.checkbox(&mut grid.bar_numbers, "");
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Key Effects");
+
+        egui::Grid::new("effects_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let effects = &mut settings.scene.effects;
+
+                ui.horizontal(|ui| {
+                    ui.label("Key Glow: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Effects drawn over the notes above the pressed\n\
+                        keys, in the colors of the notes. They only\n\
+                        depend on the time, so the video export renders\n\
+                        them the same way as the scene.\
+                        ",
+                    );
+                });
+                ui.checkbox(&mut effects.key_glow, "");
+                ui.end_row();
+
+                ui.label("Light Beams: ");
+                ui.checkbox(&mut effects.light_beams, "");
+                ui.end_row();
+
+                ui.label("Beam Length: ");
+                ui.add_enabled(
+                    effects.light_beams,
+                    egui::Slider::new(&mut effects.beam_length, 0.05..=1.0),
+                );
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Particles: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Bursts of particles at the start of the notes.\n\
+                        Louder notes throw more particles, higher.\
+                        ",
+                    );
+                });
+                ui.checkbox(&mut effects.particles, "");
+                ui.end_row();
+
+                ui.label("Particle Amount: ");
+                ui.add_enabled(
+                    effects.particles,
+                    egui::Slider::new(&mut effects.particle_amount, 0.0..=4.0),
+                );
+                ui.end_row();
+            });
+
+        ui.add_space(super::CATEG_SPACE);
+        ui.heading("Keyboard");
+
+        egui::Grid::new("keyboard_visual_settings_grid")
+            .num_columns(2)
+            .spacing(super::SPACING)
+            .striped(true)
+            .min_col_width(width / 2.0)
+            .show(ui, |ui| {
+                let keyboard = &mut settings.scene.keyboard;
+
+                ui.label("Layout: ");
+                egui::ComboBox::from_id_salt("keyboard_type_select")
+                    .selected_text(keyboard.keyboard_type.as_str())
+                    .show_ui(ui, |ui| {
+                        for keyboard_type in KeyboardType::iter() {
+                            ui.selectable_value(
+                                &mut keyboard.keyboard_type,
+                                *keyboard_type,
+                                keyboard_type.as_str(),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                let classic = keyboard.keyboard_type == KeyboardType::Classic;
+
+                ui.horizontal(|ui| {
+                    ui.label("Black Key Offsets: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        How far the black keys of the sets of 2 and\n\
+                        3 are moved apart. Only used by the classic layout.\
+                        ",
+                    );
+                });
+                ui.add_enabled_ui(classic, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut keyboard.black_key_2_set_offset)
+                                .speed(0.01)
+                                .range(0.0..=1.0),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut keyboard.black_key_3_set_offset)
+                                .speed(0.01)
+                                .range(0.0..=1.0),
+                        );
+                    });
+                });
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Black Key Width: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        The width of the black keys relative to the\n\
+                        white keys. Only used by the classic layout.\
+                        ",
+                    );
+                });
+                ui.add_enabled(
+                    classic,
+                    egui::Slider::new(&mut keyboard.black_key_scale, 0.3..=1.0),
+                );
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.label("Keyboard Height: ");
+                    ui.monospace("\u{2139}").on_hover_text(
+                        "\
+                        Multiplies the default height, which depends\n\
+                        on the width of the keys. The keyboard never\n\
+                        takes more than half of the screen.\
+                        ",
+                    );
+                });
+                ui.add(egui::Slider::new(&mut keyboard.height, 0.25..=4.0).logarithmic(true));
+                ui.end_row();
+
+                ui.label("Black Key Length: ");
+                ui.add(egui::Slider::new(
+                    &mut keyboard.black_key_length,
+                    0.3..=0.95,
+                ));
+                ui.end_row();
+
+                ui.label("Key Style: ");
+                egui::ComboBox::from_id_salt("key_style_select")
+                    .selected_text(keyboard.key_style.as_str())
+                    .show_ui(ui, |ui| {
+                        for style in KeyStyle::iter() {
+                            ui.selectable_value(&mut keyboard.key_style, *style, style.as_str());
+                        }
+                    });
+                ui.end_row();
             });
 
         ui.add_space(super::CATEG_SPACE);
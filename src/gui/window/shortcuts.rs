@@ -42,6 +42,14 @@ impl GuiWasabiWindow {
                         ui.label("Down Arrow");
                         ui.end_row();
 
+                        ui.label("Transpose Up / Down");
+                        ui.label("Page Up / Page Down");
+                        ui.end_row();
+
+                        ui.label("Transpose by an Octave");
+                        ui.label("Shift + Page Up / Down");
+                        ui.end_row();
+
                         ui.label("Toggle Fullscreen");
                         ui.label("Alt + Enter");
                         ui.end_row();
@@ -61,6 +69,10 @@ impl GuiWasabiWindow {
                         ui.label("Reset Synthesizer");
                         ui.label("Insert");
                         ui.end_row();
+
+                        ui.label("Take Screenshot");
+                        ui.label("F12");
+                        ui.end_row();
                     });
             });
     }
@@ -182,6 +182,11 @@ impl GuiWasabiWindow {
                                         .map(|n| f.fmt2(n).to_string())
                                         .unwrap_or_else(|| "-".to_string())
                                 ));
+                                if let Some(removed) =
+                                    note_stats.removed_notes.filter(|removed| *removed > 0)
+                                {
+                                    ui.small(format!("{} removed by filters", f.fmt2(removed)));
+                                }
                             });
                         }
                         Statistics::Nps => {
@@ -190,8 +195,13 @@ impl GuiWasabiWindow {
                                 ui.with_layout(
                                     egui::Layout::right_to_left(egui::Align::Center),
                                     |ui| {
-                                        self.nps.tick(note_stats.passed_notes.unwrap_or(0) as i64);
-                                        ui.monospace(f.fmt2(self.nps.read()).to_string());
+                                        // Prefer the precomputed note timeline if the file has one
+                                        let nps = note_stats.nps.unwrap_or_else(|| {
+                                            self.nps
+                                                .tick(note_stats.passed_notes.unwrap_or(0) as i64);
+                                            self.nps.read() as u64
+                                        });
+                                        ui.monospace(f.fmt2(nps).to_string());
                                     },
                                 );
                             });
@@ -44,6 +44,46 @@ pub fn main() {
         }
     }
 
+    // `wasabi --benchmark-load <midi> [runs]` compares the sequential and parallel loaders
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--benchmark-load") {
+        let Some(path) = args.get(2) else {
+            eprintln!("Usage: wasabi --benchmark-load <midi> [runs]");
+            return;
+        };
+        let runs = args.get(3).and_then(|r| r.parse().ok()).unwrap_or(3);
+        if let Err(e) = midi::run_load_benchmark(path, runs) {
+            eprintln!("[Benchmark] {e}");
+        }
+        return;
+    }
+
+    // `wasabi --analyze <midi> [json|csv]` prints the statistics of a MIDI, using the
+    // note filters and transpose of the saved MIDI settings
+    if args.get(1).map(String::as_str) == Some("--analyze") {
+        let Some(path) = args.get(2) else {
+            eprintln!("Usage: wasabi --analyze <midi> [json|csv]");
+            return;
+        };
+        let format = args
+            .get(3)
+            .map_or(Ok(midi::AnalysisFormat::Json), |f| f.parse());
+        let format = match format {
+            Ok(format) => format,
+            Err(e) => {
+                eprintln!("[Analyze] {e}");
+                return;
+            }
+        };
+        let midi_settings = settings::WasabiSettings::new_or_load()
+            .map(|s| s.midi)
+            .unwrap_or_default();
+        if let Err(e) = midi::run_analysis(path, format, &midi_settings) {
+            eprintln!("[Analyze] {e}");
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
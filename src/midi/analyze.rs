@@ -0,0 +1,191 @@
+use std::{fmt::Write, str::FromStr};
+
+use rustc_hash::FxHashMap;
+use serde_derive::Serialize;
+
+use crate::{audio_playback::WasabiAudioPlayer, gui::window::WasabiError, settings::MidiSettings};
+
+use super::{shared::track_channel::TrackAndChannel, InRamMIDIFile, LoadProgress, MIDIFileBase};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisFormat {
+    Json,
+    Csv,
+}
+
+impl FromStr for AnalysisFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(AnalysisFormat::Json),
+            "csv" => Ok(AnalysisFormat::Csv),
+            s => Err(format!("{} was not expected.", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackChannelNotes {
+    pub track: u32,
+    pub channel: u8,
+    pub notes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyNotes {
+    pub key: u8,
+    pub notes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TempoChange {
+    /// Time of the change in seconds
+    pub time: f64,
+    pub bpm: f64,
+}
+
+/// The statistics of a whole MIDI, collected without rendering it
+#[derive(Debug, Serialize)]
+pub struct MidiAnalysis {
+    pub file: String,
+    pub note_count: u64,
+    /// Notes removed by the note filters of the MIDI settings
+    pub removed_notes: u64,
+    /// Length in seconds
+    pub length: f64,
+    pub peak_nps: u64,
+    pub peak_nps_time: f64,
+    pub peak_polyphony: u64,
+    pub peak_polyphony_time: f64,
+    /// Only the track and channel combinations which have notes
+    pub track_channels: Vec<TrackChannelNotes>,
+    /// Only the keys which have notes
+    pub keys: Vec<KeyNotes>,
+    pub tempo_changes: Vec<TempoChange>,
+}
+
+impl MidiAnalysis {
+    pub fn to_json(&self) -> Result<String, WasabiError> {
+        serde_json::to_string_pretty(self).map_err(|e| WasabiError::Other(e.to_string()))
+    }
+
+    /// Writes the analysis as a single table, with a row for each value
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("category,item,value\n");
+
+        let file = self.file.replace('"', "\"\"");
+        writeln!(csv, "summary,file,\"{file}\"").ok();
+        writeln!(csv, "summary,note_count,{}", self.note_count).ok();
+        writeln!(csv, "summary,removed_notes,{}", self.removed_notes).ok();
+        writeln!(csv, "summary,length,{}", self.length).ok();
+        writeln!(csv, "summary,peak_nps,{}", self.peak_nps).ok();
+        writeln!(csv, "summary,peak_nps_time,{}", self.peak_nps_time).ok();
+        writeln!(csv, "summary,peak_polyphony,{}", self.peak_polyphony).ok();
+        writeln!(
+            csv,
+            "summary,peak_polyphony_time,{}",
+            self.peak_polyphony_time
+        )
+        .ok();
+
+        for tc in self.track_channels.iter() {
+            writeln!(
+                csv,
+                "track_channel,{}:{},{}",
+                tc.track, tc.channel, tc.notes
+            )
+            .ok();
+        }
+        for key in self.keys.iter() {
+            writeln!(csv, "key,{},{}", key.key, key.notes).ok();
+        }
+        for tempo in self.tempo_changes.iter() {
+            writeln!(csv, "tempo,{},{}", tempo.time, tempo.bpm).ok();
+        }
+
+        csv
+    }
+}
+
+/// Loads the MIDI with the in-RAM loader, applying the transposition and the note
+/// filters of `settings`, and collects its statistics from the loaded notes.
+pub fn analyze_midi(path: &str, settings: &MidiSettings) -> Result<MidiAnalysis, WasabiError> {
+    let midi = InRamMIDIFile::load_from_file(
+        path,
+        WasabiAudioPlayer::empty(),
+        settings,
+        LoadProgress::new(),
+    )?;
+
+    let stats = midi.stats();
+    let (peak_nps, peak_nps_time) = midi.note_timeline().map_or((0, 0.0), |t| t.peak_nps());
+    let (peak_polyphony, peak_polyphony_time) = midi
+        .note_timeline()
+        .map_or((0, 0.0), |t| t.peak_polyphony());
+
+    let mut track_channels = FxHashMap::<TrackAndChannel, u64>::default();
+    let mut keys = Vec::new();
+    for (key, column) in midi.columns().iter().enumerate() {
+        let notes = column.notes();
+        if notes.is_empty() {
+            continue;
+        }
+        keys.push(KeyNotes {
+            key: key as u8,
+            notes: notes.len() as u64,
+        });
+        for note in notes {
+            *track_channels.entry(note.track_chan).or_default() += 1;
+        }
+    }
+
+    let mut track_channels: Vec<_> = track_channels
+        .into_iter()
+        .map(|(track_chan, notes)| TrackChannelNotes {
+            track: track_chan.track(),
+            channel: track_chan.channel(),
+            notes,
+        })
+        .collect();
+    track_channels.sort_by_key(|tc| (tc.track, tc.channel));
+
+    let tempo_changes = midi
+        .beat_grid()
+        .map(|grid| grid.tempo_changes())
+        .unwrap_or_default()
+        .iter()
+        .map(|&(time, tempo)| TempoChange {
+            time,
+            bpm: 60_000_000.0 / tempo as f64,
+        })
+        .collect();
+
+    Ok(MidiAnalysis {
+        file: path.to_owned(),
+        note_count: stats.total_notes.unwrap_or(0),
+        removed_notes: stats.removed_notes.unwrap_or(0),
+        length: midi.midi_length().unwrap_or(0.0),
+        peak_nps,
+        peak_nps_time,
+        peak_polyphony,
+        peak_polyphony_time,
+        track_channels,
+        keys,
+        tempo_changes,
+    })
+}
+
+/// Analyzes the MIDI and prints the report to the standard output
+pub fn run_analysis(
+    path: &str,
+    format: AnalysisFormat,
+    settings: &MidiSettings,
+) -> Result<(), WasabiError> {
+    let analysis = analyze_midi(path, settings)?;
+    match format {
+        AnalysisFormat::Json => println!("{}", analysis.to_json()?),
+        AnalysisFormat::Csv => print!("{}", analysis.to_csv()),
+    }
+    Ok(())
+}
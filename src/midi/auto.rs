@@ -0,0 +1,134 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::{gui::window::WasabiError, settings::MidiParsing, utils};
+
+/// Average size of a note in a MIDI file, a note on (4 bytes) and a note off (3 bytes)
+/// with running status, which is what most large MIDIs use.
+const FILE_BYTES_PER_NOTE: u64 = 7;
+
+/// Approximate memory used per note by each parser, including the audio data
+const RAM_BYTES_PER_NOTE: u64 = 16;
+const CAKE_BYTES_PER_NOTE: u64 = 48;
+const PIE_BYTES_PER_NOTE: u64 = 36;
+/// Extra memory per note needed while loading with the parallel parser
+const PARALLEL_BYTES_PER_NOTE: u64 = 40;
+
+/// MIDIs with less notes than this are rendered fine by the standard renderer,
+/// which also supports all the statistics.
+const RAM_NOTE_LIMIT: u64 = 5_000_000;
+
+/// Only this fraction of the free memory is used as the budget, to leave room
+/// for the rest of the system.
+const MEMORY_BUDGET: f64 = 0.8;
+
+/// One of the parsers a MIDI is actually loaded with, which is what
+/// [`MidiParsing::Auto`] resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiParser {
+    Ram,
+    Live,
+    Cake,
+    Pie,
+}
+
+impl MidiParser {
+    /// The parser picked in the settings, or `None` if it is left to [`choose_parsing`]
+    pub fn from_setting(parsing: MidiParsing) -> Option<Self> {
+        match parsing {
+            MidiParsing::Ram => Some(MidiParser::Ram),
+            MidiParsing::Live => Some(MidiParser::Live),
+            MidiParsing::Cake => Some(MidiParser::Cake),
+            MidiParsing::Pie => Some(MidiParser::Pie),
+            MidiParsing::Auto => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            MidiParser::Ram => MidiParsing::Ram.as_str(),
+            MidiParser::Live => MidiParsing::Live.as_str(),
+            MidiParser::Cake => MidiParsing::Cake.as_str(),
+            MidiParser::Pie => MidiParsing::Pie.as_str(),
+        }
+    }
+}
+
+/// The parser that was picked by [`choose_parsing`] and a message explaining why.
+pub struct AutoParsing {
+    pub parser: MidiParser,
+    pub reason: String,
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.0} MB", mb)
+    }
+}
+
+/// Reads the track count from the header of the MIDI, `None` if it isn't a MIDI
+pub fn read_track_count(path: &Path) -> Result<Option<u16>, WasabiError> {
+    let mut header = [0u8; 14];
+    let mut file = File::open(path).map_err(WasabiError::FilesystemError)?;
+    if file.read_exact(&mut header).is_err() || &header[0..4] != b"MThd" {
+        return Ok(None);
+    }
+    Ok(Some(u16::from_be_bytes([header[10], header[11]])))
+}
+
+/// Picks the most suitable parser for the MIDI by estimating its note count from the
+/// file size, and checking if the notes would fit in the free memory. Falls back to
+/// the live parser if none of the in-memory parsers fit.
+pub fn choose_parsing(path: &Path, parallel: bool) -> Result<AutoParsing, WasabiError> {
+    let file_size = path.metadata().map_err(WasabiError::FilesystemError)?.len();
+    let track_count = read_track_count(path)?.unwrap_or(0) as u64;
+    let notes = file_size / FILE_BYTES_PER_NOTE;
+
+    let needed = |bytes_per_note: u64, parallel: bool| {
+        let extra = if parallel { PARALLEL_BYTES_PER_NOTE } else { 0 };
+        // Every track also keeps a read buffer while parsing
+        notes * (bytes_per_note + extra) + track_count * 64 * 1024
+    };
+
+    let free = utils::available_memory();
+    let budget = free.map(|free| (free as f64 * MEMORY_BUDGET) as u64);
+    let fits = |bytes: u64| budget.is_none_or(|budget| bytes <= budget);
+
+    // The parallel parser is only used by Cake and Pie
+    let ram = needed(RAM_BYTES_PER_NOTE, false);
+    let cake = needed(CAKE_BYTES_PER_NOTE, parallel);
+    let pie = needed(PIE_BYTES_PER_NOTE, parallel);
+
+    let (parser, needed) = if notes < RAM_NOTE_LIMIT && fits(ram) {
+        (MidiParser::Ram, ram)
+    } else if fits(cake) {
+        (MidiParser::Cake, cake)
+    } else if fits(pie) {
+        (MidiParser::Pie, pie)
+    } else {
+        (MidiParser::Live, 0)
+    };
+
+    let free = free.map_or("unknown".to_string(), format_bytes);
+    let reason = match parser {
+        MidiParser::Live => format!(
+            "Auto selected {}: ~{:.1}M notes in {} tracks would not fit in the free memory ({})",
+            parser.as_str(),
+            notes as f64 / 1_000_000.0,
+            track_count,
+            free,
+        ),
+        _ => format!(
+            "Auto selected {}: ~{:.1}M notes in {} tracks, ~{} needed, {} free",
+            parser.as_str(),
+            notes as f64 / 1_000_000.0,
+            track_count,
+            format_bytes(needed),
+            free,
+        ),
+    };
+
+    Ok(AutoParsing { parser, reason })
+}
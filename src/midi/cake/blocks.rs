@@ -11,6 +11,7 @@ pub struct CakeNoteData {
     pub start_time: u32,
     pub end_time: u32,
     pub color: MIDIColor,
+    pub velocity: u8,
 }
 
 impl CakeBlock {
@@ -43,6 +44,7 @@ impl CakeBlock {
                 start_time: note.note_start(),
                 end_time: note.note_end(),
                 color: MIDIColor::from_u32(note.note_color()),
+                velocity: note.note_velocity(),
             })
         }
     }
@@ -19,12 +19,12 @@ impl IntVector4 {
         }
     }
 
-    pub fn new_note(start: i32, end: i32, color: i32) -> IntVector4 {
+    pub fn new_note(start: i32, end: i32, color: i32, velocity: u8) -> IntVector4 {
         IntVector4 {
             val1: start,
             val2: end,
             val3: color,
-            val4: 0,
+            val4: velocity as i32,
         }
     }
 
@@ -87,6 +87,10 @@ impl IntVector4 {
         self.val3 as u32
     }
 
+    pub fn note_velocity(&self) -> u8 {
+        self.val4 as u8
+    }
+
     pub fn is_note_empty(&self) -> bool {
         self.val3 == -1
     }
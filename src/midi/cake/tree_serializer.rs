@@ -1,5 +1,7 @@
 use std::collections::VecDeque;
 
+use crate::{midi::shared::timeline::KeyTimeline, settings::NoteOffMatching};
+
 use super::{intvec4::IntVector4, unended_note_batch::UnendedNotes};
 
 enum TreeFrame {
@@ -18,6 +20,7 @@ struct NoteMarker {
     start: i32,
     track_channel: i32,
     color: i32,
+    velocity: u8,
     written_pos: Option<i32>,
 }
 
@@ -34,6 +37,10 @@ pub struct TreeSerializer {
 
     added_notes: u32,
     last_tree_time: i32,
+
+    end_all_matching: bool,
+    min_velocity: u8,
+    timeline: KeyTimeline,
 }
 
 impl std::fmt::Debug for TreeSerializer {
@@ -43,17 +50,25 @@ impl std::fmt::Debug for TreeSerializer {
 }
 
 impl TreeSerializer {
-    pub fn new() -> TreeSerializer {
+    pub fn new(
+        ticks_per_second: u32,
+        matching: NoteOffMatching,
+        min_velocity: u8,
+    ) -> TreeSerializer {
         let written_values = vec![IntVector4::new_empty()];
 
         TreeSerializer {
-            note_stack: UnendedNotes::new(),
+            note_stack: UnendedNotes::new(matching),
             tree_frames: VecDeque::new(),
 
             written_values,
 
             added_notes: 0,
             last_tree_time: 0,
+
+            end_all_matching: matching == NoteOffMatching::EndAll,
+            min_velocity,
+            timeline: KeyTimeline::new(ticks_per_second),
         }
     }
 
@@ -67,8 +82,12 @@ impl TreeSerializer {
                 Some(pos) => -pos,
                 None => {
                     let written_pos = self.written_values.len() as i32;
-                    self.written_values
-                        .push(IntVector4::new_note(marker.start, 0, marker.color));
+                    self.written_values.push(IntVector4::new_note(
+                        marker.start,
+                        0,
+                        marker.color,
+                        marker.velocity,
+                    ));
                     marker.written_pos = Some(written_pos);
                     -written_pos
                 }
@@ -111,12 +130,13 @@ impl TreeSerializer {
 
     /// Processes a note start. If the time is greater than the last tree time, the tree is
     /// updated to the new time. Then, the note is pushed to the note stack.
-    pub fn start_note(&mut self, time: i32, track_channel: i32, color: i32) {
+    pub fn start_note(&mut self, time: i32, track_channel: i32, color: i32, velocity: u8) {
         if time > self.last_tree_time {
             self.process_change(time);
         }
 
         self.added_notes += 1;
+        self.timeline.note_start(time);
 
         self.note_stack.push_note(
             track_channel,
@@ -124,28 +144,38 @@ impl TreeSerializer {
                 start: time,
                 track_channel,
                 color,
+                velocity,
                 written_pos: None,
             },
+            velocity >= self.min_velocity,
         );
     }
 
     /// Processes a note end. If the time is greater than the last tree time, the tree is
     /// updated to the new time. Then, the note is popped from the note stack, and the
-    /// end for the note is also written.
+    /// end for the note is also written. With end all matching, every unended note of the
+    /// track and channel is ended.
     pub fn end_note(&mut self, time: i32, track_channel: i32) {
         if time > self.last_tree_time {
             self.process_change(time);
         }
 
+        while self.end_single_note(time, track_channel) && self.end_all_matching {}
+    }
+
+    /// Ends one note of the track and channel, returns false if there was no note to end
+    fn end_single_note(&mut self, time: i32, track_channel: i32) -> bool {
         let marker = self.note_stack.get_note_for(track_channel);
 
         let marker = if let Some(marker) = marker {
             marker
         } else {
             //ignore
-            return;
+            return false;
         };
 
+        self.timeline.note_end(time);
+
         if marker.is_last {
             // last note
 
@@ -157,11 +187,13 @@ impl TreeSerializer {
         if let Some(index) = marker.value.written_pos {
             self.written_values[index as usize].set_note_end(time);
         }
+
+        true
     }
 
     /// Ends all notes, finishes all stack frames, inserts the address of the last item into the start of the array,
-    /// and returns the array.
-    pub fn complete_and_seal(mut self, time: i32) -> Vec<IntVector4> {
+    /// and returns the array along with the timeline of the key.
+    pub fn complete_and_seal(mut self, time: i32) -> (Vec<IntVector4>, KeyTimeline) {
         self.end_all_notes(time);
         self.end_all_frames();
 
@@ -172,7 +204,7 @@ impl TreeSerializer {
         self.written_values
             .insert(0, IntVector4::new_length_marker(self.written_values.len()));
 
-        self.written_values
+        (self.written_values, self.timeline)
     }
 
     fn process_change(&mut self, until: i32) {
@@ -289,6 +321,7 @@ impl TreeSerializer {
 
         self.process_change(time);
         for marker in self.note_stack.drain_all() {
+            self.timeline.note_end(time);
             if let Some(index) = marker.written_pos {
                 self.written_values[index as usize].set_note_end(time);
             }
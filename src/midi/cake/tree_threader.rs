@@ -2,6 +2,11 @@ use std::sync::{Arc, Mutex};
 
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
 
+use crate::{
+    midi::shared::{progress::LoadProgress, timeline::KeyTimeline},
+    settings::NoteOffMatching,
+};
+
 use super::{intvec4::IntVector4, tree_serializer::TreeSerializer};
 
 pub struct MidiData {
@@ -14,6 +19,7 @@ pub enum NoteEvent {
         time: i32,
         channel_track: i32,
         color: i32,
+        velocity: u8,
     },
     Off {
         time: i32,
@@ -37,8 +43,14 @@ impl ThreadedTreeSerializers {
         (0..256).map(|_| Vec::new()).collect()
     }
 
-    pub fn new() -> ThreadedTreeSerializers {
-        let trees = (0..256).map(|_| TreeSerializer::new()).collect::<Vec<_>>();
+    pub fn new(
+        ticks_per_second: u32,
+        matching: NoteOffMatching,
+        min_velocity: u8,
+    ) -> ThreadedTreeSerializers {
+        let trees = (0..256)
+            .map(|_| TreeSerializer::new(ticks_per_second, matching, min_velocity))
+            .collect::<Vec<_>>();
         let trees = Arc::new(Mutex::new(trees));
 
         let (snd_in, rcv_in) = crossbeam_channel::unbounded::<Vec<Vec<NoteEvent>>>();
@@ -58,8 +70,9 @@ impl ThreadedTreeSerializers {
                                     time,
                                     channel_track,
                                     color,
+                                    velocity,
                                 } => {
-                                    tree.start_note(time, channel_track, color);
+                                    tree.start_note(time, channel_track, color, velocity);
                                 }
                                 NoteEvent::Off {
                                     time,
@@ -105,7 +118,21 @@ impl ThreadedTreeSerializers {
         }
     }
 
-    pub fn seal(self, time: i32) -> Vec<Vec<IntVector4>> {
+    /// Stops the tree thread without building the trees, freeing all the data
+    /// which was collected so far.
+    pub fn discard(self) {
+        drop(self.snd);
+        for _ in self.rcv.iter() {}
+        self.join.join().unwrap();
+    }
+
+    /// Completes the trees of every key, or returns `None` if the load gets cancelled
+    /// in the meantime.
+    pub fn seal(
+        self,
+        time: i32,
+        progress: &LoadProgress,
+    ) -> Option<Vec<(Vec<IntVector4>, KeyTimeline)>> {
         self.snd.send(self.current_vec).unwrap();
         drop(self.snd);
 
@@ -118,10 +145,13 @@ impl ThreadedTreeSerializers {
 
         let mut serialized = Vec::new();
         for tree in trees.into_iter() {
+            if progress.is_cancelled() {
+                return None;
+            }
             let sealed = tree.complete_and_seal(time);
             serialized.push(sealed);
         }
 
-        serialized
+        Some(serialized)
     }
 }
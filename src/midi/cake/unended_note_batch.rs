@@ -1,4 +1,6 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::settings::NoteOffMatching;
 
 pub struct RemovedValue<T> {
     pub value: T,
@@ -6,16 +8,22 @@ pub struct RemovedValue<T> {
 }
 
 pub struct UnendedNotes<K: Ord, T> {
+    matching: NoteOffMatching,
     id_counter: u32,
     notes: BTreeMap<u32, T>,
+    /// The ids of the visible notes, so the top visible note is found without going
+    /// through the hidden ones
+    visible: BTreeSet<u32>,
     ids: BTreeMap<K, VecDeque<u32>>,
 }
 
 impl<K: Ord, T> UnendedNotes<K, T> {
-    pub fn new() -> Self {
+    pub fn new(matching: NoteOffMatching) -> Self {
         UnendedNotes {
+            matching,
             id_counter: 0,
             notes: BTreeMap::new(),
+            visible: BTreeSet::new(),
             ids: BTreeMap::new(),
         }
     }
@@ -24,24 +32,33 @@ impl<K: Ord, T> UnendedNotes<K, T> {
         self.notes.len()
     }
 
+    /// The last pushed visible note
     pub fn top_mut(&mut self) -> Option<&mut T> {
-        let key = *self.notes.last_entry()?.key();
-        self.notes.get_mut(&key)
+        let id = self.visible.last()?;
+        self.notes.get_mut(id)
     }
 
+    /// Removes the note which should be ended by a note off for `key`. With
+    /// [`NoteOffMatching::EndAll`], this should be called until it returns `None`.
+    /// The note is marked as last if it was the top visible note.
     pub fn get_note_for(&mut self, key: K) -> Option<RemovedValue<T>> {
         let ids = self.ids.get_mut(&key)?;
-        let id = ids.pop_front()?;
-        let last_key = *self.notes.last_entry()?.key();
+        let id = match self.matching {
+            NoteOffMatching::Fifo | NoteOffMatching::EndAll => ids.pop_front()?,
+            NoteOffMatching::Lifo => ids.pop_back()?,
+        };
 
         let note = self.notes.remove(&id)?;
+        let is_last = self.visible.remove(&id) && self.visible.range(id..).next().is_none();
         Some(RemovedValue {
             value: note,
-            is_last: id == last_key,
+            is_last,
         })
     }
 
-    pub fn push_note(&mut self, key: K, note: T) -> u32 {
+    /// Pushes a note and returns its id. Hidden notes are never the top note, so they
+    /// are left out of the tree and the notes under them are shown.
+    pub fn push_note(&mut self, key: K, note: T, visible: bool) -> u32 {
         let id = self.id_counter;
         self.id_counter += 1;
 
@@ -49,12 +66,16 @@ impl<K: Ord, T> UnendedNotes<K, T> {
         ids.push_back(id);
 
         self.notes.insert(id, note);
+        if visible {
+            self.visible.insert(id);
+        }
 
         id
     }
 
     pub fn drain_all(&mut self) -> impl '_ + Iterator<Item = T> {
         let notes = std::mem::take(&mut self.notes);
+        self.visible = BTreeSet::new();
         self.ids = BTreeMap::new();
 
         notes.into_values()
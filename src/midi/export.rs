@@ -0,0 +1,301 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum},
+    io::{MIDIWriter, TrackWriter},
+    pipe,
+    sequence::{event::Delta, unwrap_items, TimeCaster},
+};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    gui::window::WasabiError,
+    settings::{MidiSettings, NoteOffMatching},
+};
+
+use super::{
+    open_midi_with_progress,
+    shared::{
+        note_filter::{note_id, FilteredBatch, NoteFilter},
+        tempo::TempoMap,
+        transpose::transpose_midi_key,
+    },
+    LoadProgress,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct MidiExportOptions {
+    /// Only the notes starting inside of this range, in seconds, are exported. The
+    /// events before it are moved to its start, and the exported MIDI starts there.
+    pub range: Option<(f64, f64)>,
+    /// Whether the events of each track are exported, all of them if `None`. The
+    /// tempo and time signature events of the other tracks are kept, so the timing
+    /// of the exported tracks doesn't change.
+    pub tracks: Option<Vec<bool>>,
+}
+
+/// Writes the MIDI at `path` into `output` as a format 1 MIDI with the original PPQ,
+/// tracks and tempo map, after applying the transposition and the note filters of
+/// `settings`. Returns the number of exported notes.
+pub fn export_midi(
+    path: &str,
+    output: &Path,
+    settings: &MidiSettings,
+    options: MidiExportOptions,
+    progress: Arc<LoadProgress>,
+) -> Result<u64, WasabiError> {
+    // Writing over the source would truncate it while it's still being read
+    let source = Path::new(path)
+        .canonicalize()
+        .map_err(WasabiError::FilesystemError)?;
+    if output.canonicalize().is_ok_and(|output| output == source) {
+        return Err(WasabiError::MidiExportError(
+            "The MIDI can't be exported over itself".to_owned(),
+        ));
+    }
+
+    // The MIDI is written next to the output and only moved over it once complete,
+    // so a failed or cancelled export leaves the existing files untouched
+    let partial = partial_path(output);
+    let result = write_midi(path, &partial, settings, options, &progress).and_then(|notes| {
+        std::fs::rename(&partial, output).map_err(WasabiError::FilesystemError)?;
+        Ok(notes)
+    });
+    if result.is_err() {
+        std::fs::remove_file(&partial).ok();
+    }
+    result
+}
+
+/// A path next to `output` which no file uses yet
+fn partial_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut i = 1;
+    loop {
+        let file_name = match i {
+            1 => format!("{name}.part"),
+            _ => format!("{name}.{i}.part"),
+        };
+        let path = output.with_file_name(file_name);
+        if !path.exists() {
+            return path;
+        }
+        i += 1;
+    }
+}
+
+fn write_midi(
+    path: &str,
+    output: &Path,
+    settings: &MidiSettings,
+    options: MidiExportOptions,
+    progress: &Arc<LoadProgress>,
+) -> Result<u64, WasabiError> {
+    let (midi, _) = open_midi_with_progress(path, progress)?;
+
+    let ppq = midi.ppq();
+    let writer = MIDIWriter::new(&output.to_string_lossy(), ppq)
+        .map_err(|e| WasabiError::MidiExportError(format!("{e:?}")))?;
+    let mut exporter = TrackExporter::new(&writer, midi.track_count(), settings, options);
+
+    // The tempo events are kept in the exported MIDI, so the ticks are converted here
+    // instead of the tempo events being cancelled like in the loaders
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>unwrap_items()
+    );
+
+    let mut filter = NoteFilter::new(settings, progress.clone());
+    let mut tempo_map = TempoMap::new(ppq);
+    let mut tick = 0.0;
+    let mut time = 0.0;
+
+    for mut batch in merged {
+        if progress.is_cancelled() {
+            return Err(WasabiError::MidiLoadCancelled);
+        }
+
+        // Tempo events only apply to the batches after them, so the range edges
+        // are found before the tempo events of this batch are added
+        tick += batch.delta;
+        let batch_time = tempo_map.seconds_at(tick);
+        exporter.find_range_ticks(&tempo_map, batch_time);
+
+        batch.delta = batch_time - time;
+        time = batch_time;
+        exporter.batch_ticks.push_back(tick);
+
+        for event in batch.iter_events() {
+            if let Event::Tempo(e) = event.as_event() {
+                tempo_map.add_tempo(tick, e.tempo);
+            }
+        }
+
+        for batch in filter.push(batch) {
+            exporter.write_batch(&batch)?;
+        }
+    }
+    for batch in filter.finish() {
+        exporter.write_batch(&batch)?;
+    }
+
+    let note_count = exporter.finish()?;
+    writer
+        .end()
+        .map_err(|e| WasabiError::MidiExportError(format!("{e:?}")))?;
+
+    Ok(note_count)
+}
+
+/// Writes the filtered batches into the tracks of the exported MIDI
+struct TrackExporter<'a> {
+    tracks: Vec<TrackWriter<'a>>,
+    /// Tick of the last event written into each track
+    last_ticks: Vec<u64>,
+    matching: NoteOffMatching,
+    transpose: i32,
+    range: (f64, f64),
+    selected_tracks: Option<Vec<bool>>,
+    /// The range in ticks, found while the batches are held back by the note filter
+    start_tick: Option<f64>,
+    end_tick: Option<f64>,
+    /// Ticks of the batches which were not written yet
+    batch_ticks: VecDeque<f64>,
+    time: f64,
+    /// Number of exported notes without a note off yet, keyed by their note id
+    unended: FxHashMap<u64, u32>,
+    note_count: u64,
+}
+
+impl<'a> TrackExporter<'a> {
+    fn new(
+        writer: &'a MIDIWriter,
+        track_count: usize,
+        settings: &MidiSettings,
+        options: MidiExportOptions,
+    ) -> Self {
+        Self {
+            tracks: (0..track_count).map(|_| writer.open_next_track()).collect(),
+            last_ticks: vec![0; track_count],
+            matching: settings.note_off_matching,
+            transpose: settings.transpose,
+            range: options.range.unwrap_or((0.0, f64::INFINITY)),
+            selected_tracks: options.tracks,
+            start_tick: None,
+            end_tick: None,
+            batch_ticks: VecDeque::new(),
+            time: 0.0,
+            unended: FxHashMap::default(),
+            note_count: 0,
+        }
+    }
+
+    fn find_range_ticks(&mut self, tempo_map: &TempoMap, batch_time: f64) {
+        let (start, end) = self.range;
+        if self.start_tick.is_none() && batch_time >= start {
+            self.start_tick = Some(tempo_map.ticks_at(start));
+        }
+        if self.end_tick.is_none() && batch_time >= end {
+            self.end_tick = Some(tempo_map.ticks_at(end));
+        }
+    }
+
+    fn write_batch(&mut self, batch: &FilteredBatch) -> Result<(), WasabiError> {
+        self.time += batch.delta;
+        let tick = self.batch_ticks.pop_front().unwrap_or_default();
+
+        let (start, end) = self.range;
+        let in_range = self.time >= start && self.time < end;
+        let after_range = self.time >= end;
+
+        // Each edge is known once a batch at or after it reaches the note filter
+        let output_tick = match self.start_tick {
+            Some(start_tick) if self.time >= start => {
+                let end_tick = self.end_tick.unwrap_or(f64::INFINITY);
+                (tick.min(end_tick) - start_tick).max(0.0).round() as u64
+            }
+            _ => 0,
+        };
+
+        for (event, kept) in batch.iter_events().zip(batch.kept_events()) {
+            if !kept {
+                continue;
+            }
+
+            let track = event.track;
+            let selected = self
+                .selected_tracks
+                .as_ref()
+                .is_none_or(|tracks| tracks.get(track as usize).copied().unwrap_or(true));
+            let mut event = event.as_event().clone();
+            let write = match &mut event {
+                Event::EndOfTrack(_) => true,
+                Event::Tempo(_) | Event::TimeSignature(_) if !selected => !after_range,
+                _ if !selected => false,
+                Event::NoteOn(e) => match transpose_midi_key(e.key, self.transpose) {
+                    Some(key) if in_range => {
+                        e.key = key;
+                        *self
+                            .unended
+                            .entry(note_id(key, track, e.channel))
+                            .or_default() += 1;
+                        self.note_count += 1;
+                        true
+                    }
+                    _ => false,
+                },
+                Event::NoteOff(e) => match transpose_midi_key(e.key, self.transpose) {
+                    Some(key) => {
+                        e.key = key;
+                        // Only the note offs of exported notes are kept, the ones after
+                        // the range are moved to its end
+                        match self.unended.get_mut(&note_id(key, track, e.channel)) {
+                            Some(unended) if *unended > 0 => {
+                                *unended = match self.matching {
+                                    NoteOffMatching::EndAll => 0,
+                                    _ => *unended - 1,
+                                };
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    None => false,
+                },
+                _ => !after_range,
+            };
+
+            if write {
+                self.write_event(track, output_tick, event)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_event(&mut self, track: u32, tick: u64, event: Event) -> Result<(), WasabiError> {
+        let track = track as usize;
+        let delta = tick.saturating_sub(self.last_ticks[track]);
+        self.last_ticks[track] = self.last_ticks[track].max(tick);
+
+        self.tracks[track]
+            .write_event(Delta::new(delta, event))
+            .map_err(|e| WasabiError::MidiExportError(format!("{e:?}")))?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<u64, WasabiError> {
+        for track in self.tracks.iter_mut() {
+            track
+                .end()
+                .map_err(|e| WasabiError::MidiExportError(format!("{e:?}")))?;
+        }
+        Ok(self.note_count)
+    }
+}
@@ -0,0 +1,229 @@
+use std::{ops::RangeInclusive, path::PathBuf, sync::Arc};
+
+use time::Duration;
+
+use crate::audio_playback::WasabiAudioPlayer;
+
+use super::{
+    shared::timer::TimeKeeper, BeatGrid, MIDIFileBase, MIDIFileStats, MIDIFileUnion,
+    MIDIFileUniqueSignature, NoteTimeline,
+};
+
+/// A single MIDI of a [`MIDIFileGroup`]
+pub struct MIDILayer {
+    pub file: MIDIFileUnion,
+    pub path: PathBuf,
+    /// The player the audio of this layer is sent through
+    player: Arc<WasabiAudioPlayer>,
+    /// Seconds the layer starts after the start of the group
+    offset: f64,
+    muted: bool,
+}
+
+impl MIDILayer {
+    pub fn new(file: MIDIFileUnion, path: PathBuf, player: Arc<WasabiAudioPlayer>) -> Self {
+        Self {
+            file,
+            path,
+            player,
+            offset: 0.0,
+            muted: false,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.player.set_muted(muted);
+    }
+}
+
+/// Several MIDIs played at the same time. The timers of the layers share the
+/// timeline of the group timer, each of them running behind it by the offset of its
+/// layer.
+pub struct MIDIFileGroup {
+    layers: Vec<MIDILayer>,
+    timer: TimeKeeper,
+    /// The note timelines of the layers merged at their offsets, when there is more
+    /// than one layer or the layer is moved
+    timeline: Option<NoteTimeline>,
+    /// The grid of the first layer moved by its offset, when it is moved
+    beat_grid: Option<BeatGrid>,
+}
+
+impl MIDIFileGroup {
+    pub fn new(mut layer: MIDILayer, start_delay: f64) -> Self {
+        let timer = TimeKeeper::new(start_delay);
+        layer.file.timer_mut().join(&timer, Duration::ZERO);
+
+        Self {
+            layers: vec![layer],
+            timer,
+            timeline: None,
+            beat_grid: None,
+        }
+    }
+
+    pub fn layers(&self) -> &[MIDILayer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [MIDILayer] {
+        &mut self.layers
+    }
+
+    pub fn add_layer(&mut self, mut layer: MIDILayer) {
+        let offset = Duration::seconds_f64(layer.offset);
+        layer.file.timer_mut().join(&self.timer, offset);
+        self.layers.push(layer);
+        self.update_merged();
+    }
+
+    /// Removes a layer, unless it is the last one. Its playing notes are released
+    /// without cutting the notes of the other layers.
+    pub fn remove_layer(&mut self, index: usize) {
+        if index < self.layers.len() && self.layers.len() > 1 {
+            let mut layer = self.layers.remove(index);
+            layer.set_muted(true);
+            self.update_merged();
+        }
+    }
+
+    /// Moves a layer on the timeline of the group. Layers which can't seek backward,
+    /// like the ones parsed with Live, can't be moved, and the layers window doesn't
+    /// let them be.
+    pub fn set_offset(&mut self, index: usize, offset: f64) {
+        let Some(layer) = self.layers.get_mut(index) else {
+            return;
+        };
+
+        if layer.file.allows_seeking_backward() {
+            layer.offset = offset;
+            layer
+                .file
+                .timer_mut()
+                .set_offset(Duration::seconds_f64(offset));
+            self.update_merged();
+        }
+    }
+
+    /// Rebuilds the note timeline and the grid of the group after the layers or
+    /// their offsets changed
+    fn update_merged(&mut self) {
+        let moved = match self.layers.as_slice() {
+            [layer] => layer.offset != 0.0,
+            _ => true,
+        };
+
+        // Live layers have no timeline, so the group has none either
+        self.timeline = moved
+            .then(|| {
+                self.layers
+                    .iter()
+                    .map(|layer| Some((layer.file.note_timeline()?, layer.offset)))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .flatten()
+            .map(|timelines| NoteTimeline::merge(&timelines));
+
+        // The grid follows the first layer, the other ones may have other tempos
+        let first = &self.layers[0];
+        self.beat_grid = (first.offset != 0.0)
+            .then(|| first.file.beat_grid())
+            .flatten()
+            .map(|grid| grid.shifted(first.offset));
+    }
+}
+
+impl MIDIFileBase for MIDIFileGroup {
+    fn midi_length(&self) -> Option<f64> {
+        self.layers
+            .iter()
+            .map(|layer| Some(layer.file.midi_length()? + layer.offset))
+            .try_fold(0.0f64, |length, layer| Some(length.max(layer?)))
+    }
+
+    fn parsed_up_to(&self) -> Option<f64> {
+        self.layers
+            .iter()
+            .filter_map(|layer| Some(layer.file.parsed_up_to()? + layer.offset))
+            .reduce(f64::min)
+    }
+
+    fn timer(&self) -> &TimeKeeper {
+        &self.timer
+    }
+
+    fn timer_mut(&mut self) -> &mut TimeKeeper {
+        &mut self.timer
+    }
+
+    fn stats(&self) -> MIDIFileStats {
+        fn sum(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            Some(a? + b?)
+        }
+
+        let mut layers = self.layers.iter().map(|layer| layer.file.stats());
+        let first = layers.next().unwrap_or_default();
+        layers.fold(first, |stats, layer| MIDIFileStats {
+            total_notes: sum(stats.total_notes, layer.total_notes),
+            passed_notes: sum(stats.passed_notes, layer.passed_notes),
+            nps: sum(stats.nps, layer.nps),
+            // Layers without active filters don't report removed notes
+            removed_notes: match (stats.removed_notes, layer.removed_notes) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            },
+        })
+    }
+
+    fn allows_seeking_backward(&self) -> bool {
+        self.layers
+            .iter()
+            .all(|layer| layer.file.allows_seeking_backward())
+    }
+
+    fn signature(&self) -> &MIDIFileUniqueSignature {
+        self.layers[0].file.signature()
+    }
+
+    fn note_timeline(&self) -> Option<&NoteTimeline> {
+        match self.layers.as_slice() {
+            [layer] if layer.offset == 0.0 => layer.file.note_timeline(),
+            _ => self.timeline.as_ref(),
+        }
+    }
+
+    fn key_range(&self) -> Option<RangeInclusive<u8>> {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.file.key_range())
+            .reduce(|a, b| *a.start().min(b.start())..=*a.end().max(b.end()))
+    }
+
+    fn beat_grid(&self) -> Option<&BeatGrid> {
+        // The grid of a layer which isn't moved is used directly, as the one of a
+        // Live layer is only known once its file has been scanned
+        let layer = &self.layers[0];
+        if layer.offset == 0.0 {
+            layer.file.beat_grid()
+        } else {
+            self.beat_grid.as_ref()
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use std::{
+    ops::RangeInclusive,
     path::PathBuf,
-    sync::{Arc, RwLock},
+    sync::{Arc, OnceLock, RwLock},
     thread,
 };
 
-use midi_toolkit::{io::MIDIFile as TKMIDIFile, sequence::event::get_channels_array_statistics};
+use midi_toolkit::sequence::event::get_channels_array_statistics;
 
 use crate::{audio_playback::WasabiAudioPlayer, gui::window::WasabiError, settings::MidiSettings};
 
@@ -14,8 +15,10 @@ use self::{
 };
 
 use super::{
-    open_file_and_signature, shared::timer::TimeKeeper, MIDIColor, MIDIFile, MIDIFileBase,
-    MIDIFileStats, MIDIFileUniqueSignature, MIDIViewRange,
+    open_midi_with_progress,
+    shared::{grid::BeatGridBuilder, timer::TimeKeeper},
+    BeatGrid, LoadProgress, MIDIFile, MIDIFileBase, MIDIFileStats, MIDIFileUniqueSignature,
+    MIDIViewRange, NoteColors, NoteTimeline,
 };
 
 pub mod block;
@@ -32,6 +35,8 @@ pub struct LiveLoadMIDIFile {
     view_data: LiveNoteViewData,
     timer: TimeKeeper,
     stats: Arc<RwLock<Option<ParseStats>>>,
+    beat_grid: Arc<OnceLock<BeatGrid>>,
+    progress: Arc<LoadProgress>,
     signature: MIDIFileUniqueSignature,
 }
 
@@ -40,16 +45,24 @@ impl LiveLoadMIDIFile {
         path: impl Into<PathBuf>,
         player: Arc<WasabiAudioPlayer>,
         settings: &MidiSettings,
+        progress: Arc<LoadProgress>,
     ) -> Result<Self, WasabiError> {
-        let (file, signature) = open_file_and_signature(path)?;
+        let (midi, signature) = open_midi_with_progress(path, &progress)?;
 
-        let midi = TKMIDIFile::open_from_stream(file, None).map_err(WasabiError::MidiLoadError)?;
+        // The notes are streamed during playback, so only opening the file can be cancelled
+        if progress.is_cancelled() {
+            return Err(WasabiError::MidiLoadCancelled);
+        }
 
         let stats_outer = Arc::new(RwLock::new(None));
         let stats = stats_outer.clone();
 
+        let beat_grid_outer = Arc::new(OnceLock::new());
+        let beat_grid = beat_grid_outer.clone();
+
         let ppq = midi.ppq();
         let tracks = midi.iter_all_tracks().collect();
+        let grid_tracks: Vec<_> = midi.iter_all_tracks().collect();
         thread::spawn(move || {
             let stats = get_channels_array_statistics(tracks);
             if let Ok(stats) = stats {
@@ -59,19 +72,35 @@ impl LiveLoadMIDIFile {
                     note_count: stats.note_count(),
                 });
             }
+
+            // The tempo events are only read by the stream when it reaches them
+            let mut grid = BeatGridBuilder::new(ppq);
+            let mut end_tick = 0;
+            for track in grid_tracks {
+                let mut tick = 0;
+                // The notes stream reports read errors, so a broken track just ends here
+                for event in track.map_while(Result::ok) {
+                    tick += event.delta;
+                    grid.add_event_at(tick as f64, &event.event);
+                }
+                end_tick = end_tick.max(tick);
+            }
+            beat_grid_outer.set(grid.build(Some(end_tick as f64))).ok();
         });
 
         let mut timer = TimeKeeper::new(settings.start_delay);
 
-        let colors = MIDIColor::new_vec_from_settings(midi.track_count(), settings)?;
+        let colors = NoteColors::from_settings(midi.track_count(), settings)?;
 
-        let parser = LiveMidiParser::init(&midi, player, &mut timer);
+        let parser = LiveMidiParser::init(&midi, player, &mut timer, settings, progress.clone());
         let file = LiveNoteViewData::new(parser, colors);
 
         Ok(LiveLoadMIDIFile {
             view_data: file,
             timer,
             stats,
+            beat_grid,
+            progress,
             signature,
         })
     }
@@ -104,13 +133,29 @@ impl MIDIFileBase for LiveLoadMIDIFile {
 
         MIDIFileStats {
             passed_notes: Some(self.view_data.passed_notes()),
+            nps: None,
             total_notes: stats.as_ref().map(|stats| stats.note_count),
+            // The notes are filtered while they are streamed, so this keeps growing
+            removed_notes: Some(self.progress.removed_notes()),
         }
     }
 
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn note_timeline(&self) -> Option<&NoteTimeline> {
+        None
+    }
+
+    fn key_range(&self) -> Option<RangeInclusive<u8>> {
+        // Grows while the notes are streamed
+        self.progress.key_range()
+    }
+
+    fn beat_grid(&self) -> Option<&BeatGrid> {
+        self.beat_grid.get()
+    }
 }
 
 impl MIDIFile for LiveLoadMIDIFile {
@@ -7,11 +7,10 @@ use time::Duration;
 use atomic_float::AtomicF64;
 use crossbeam_channel::Receiver;
 use midi_toolkit::{
-    events::Event,
     io::{DiskReader, MIDIFile as TKMIDIFile},
     pipe,
     sequence::{
-        event::{cancel_tempo_events, scale_event_time, Delta, EventBatch, Track},
+        event::{cancel_tempo_events, scale_event_time},
         unwrap_items, TimeCaster,
     },
 };
@@ -20,8 +19,13 @@ use crate::{
     audio_playback::WasabiAudioPlayer,
     midi::{
         audio::live::LiveAudioPlayer,
-        shared::timer::{TimeKeeper, WaitResult},
+        shared::{
+            note_filter::{FilteredBatch, NoteFilter},
+            timer::{TimeKeeper, WaitResult},
+        },
+        LoadProgress,
     },
+    settings::MidiSettings,
 };
 
 use self::notes::LiveNoteBlockWithKey;
@@ -29,8 +33,6 @@ use self::notes::LiveNoteBlockWithKey;
 mod audio;
 mod notes;
 
-pub type TrackEventBatch = Delta<f64, Track<EventBatch<Event>>>;
-
 pub struct ThreadManager {
     parse_time: Arc<AtomicF64>,
     handle: JoinHandle<()>,
@@ -48,6 +50,8 @@ impl LiveMidiParser {
         midi: &TKMIDIFile<DiskReader>,
         player: Arc<WasabiAudioPlayer>,
         timer: &mut TimeKeeper,
+        settings: &MidiSettings,
+        progress: Arc<LoadProgress>,
     ) -> Self {
         let ppq = midi.ppq();
         let merged = pipe!(
@@ -58,11 +62,13 @@ impl LiveMidiParser {
             |>unwrap_items()
         );
 
-        let (note_snd, note_rcv) = crossbeam_channel::bounded::<Arc<TrackEventBatch>>(1000);
-        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<TrackEventBatch>>(1000);
+        let (note_snd, note_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
+        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
 
-        let notes = notes::init_note_manager(note_rcv);
-        let audio = audio::init_audio_manager(audio_rcv);
+        let matching = settings.note_off_matching;
+        let notes =
+            notes::init_note_manager(note_rcv, matching, settings.transpose, progress.clone());
+        let audio = audio::init_audio_manager(audio_rcv, matching, settings.transpose);
 
         LiveAudioPlayer::new(audio.reciever, timer.get_listener(), player).spawn_playback();
 
@@ -70,7 +76,13 @@ impl LiveMidiParser {
 
         let parse_time_outer = Arc::new(AtomicF64::default());
         let parse_time = parse_time_outer.clone();
+        let mut filter = NoteFilter::new(settings, progress);
         let file_handle = thread::spawn(move || {
+            let send = |block: FilteredBatch| {
+                let block = Arc::new(block);
+                note_snd.send(block.clone()).is_ok() && audio_snd.send(block).is_ok()
+            };
+
             let mut time = 0.0;
             for block in merged {
                 if block.delta > 0.0 {
@@ -81,21 +93,15 @@ impl LiveMidiParser {
                 let playback_time = time - 10.0; // 10 seconds offset
                 let waited = parser_timer.wait_until(Duration::seconds_f64(playback_time));
                 if let WaitResult::Killed = waited {
-                    break;
-                }
-
-                let block = Arc::new(block);
-
-                let res = note_snd.send(block.clone());
-                if res.is_err() {
-                    break;
+                    return;
                 }
 
-                let res = audio_snd.send(block);
-                if res.is_err() {
-                    break;
+                if !filter.push(block).all(&send) {
+                    return;
                 }
             }
+
+            filter.finish().all(&send);
         });
 
         Self {
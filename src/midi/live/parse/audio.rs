@@ -3,22 +3,29 @@ use std::sync::{atomic::Ordering, Arc};
 use atomic_float::AtomicF64;
 use crossbeam_channel::Receiver;
 
-use crate::midi::shared::audio::RawAudioBlock;
+use crate::{
+    midi::shared::{audio::RawAudioBlock, note_filter::FilteredBatch},
+    settings::NoteOffMatching,
+};
 
-use super::{ThreadManager, TrackEventBatch};
+use super::ThreadManager;
 
 pub struct AudioParserResult {
     pub reciever: Receiver<RawAudioBlock>,
     pub manager: ThreadManager,
 }
 
-pub fn init_audio_manager(blocks: Receiver<Arc<TrackEventBatch>>) -> AudioParserResult {
+pub fn init_audio_manager(
+    blocks: Receiver<Arc<FilteredBatch>>,
+    matching: NoteOffMatching,
+    transpose: i32,
+) -> AudioParserResult {
     let (sender, reciever) = crossbeam_channel::unbounded();
     let parse_time_outer = Arc::new(AtomicF64::default());
 
     let parse_time = parse_time_outer.clone();
     let join_handle = std::thread::spawn(move || {
-        for block in RawAudioBlock::build_raw_blocks(blocks.into_iter()) {
+        for block in RawAudioBlock::build_raw_blocks(blocks.into_iter(), matching, transpose) {
             parse_time.store(block.time, Ordering::Relaxed);
             let res = sender.send(block);
             if res.is_err() {
@@ -7,12 +7,18 @@ use atomic_float::AtomicF64;
 use crossbeam_channel::{Receiver, Sender};
 use midi_toolkit::events::{Event, MIDIEventEnum};
 
-use crate::midi::{
-    live::block::{LiveNoteEnderHandle, LiveRefNoteBlock},
-    shared::track_channel::TrackAndChannel,
+use crate::{
+    midi::{
+        live::block::{LiveNoteEnderHandle, LiveRefNoteBlock},
+        shared::{
+            note_filter::FilteredBatch, track_channel::TrackAndChannel, transpose::transpose_key,
+        },
+        LoadProgress,
+    },
+    settings::NoteOffMatching,
 };
 
-use super::{ThreadManager, TrackEventBatch};
+use super::ThreadManager;
 
 pub struct LiveNoteBlockWithKey {
     pub block: LiveRefNoteBlock,
@@ -47,10 +53,25 @@ impl TrackUnendedNotes {
         }
     }
 
-    fn end_note(&mut self, key: u8, channel: u8, time: f64) {
+    fn end_note(&mut self, key: u8, channel: u8, time: f64, matching: NoteOffMatching) {
         let index = self.get_index(key, channel);
-        if let Some(mut note) = self.queues[index].pop_front() {
-            note.end(time);
+        let queue = &mut self.queues[index];
+        match matching {
+            NoteOffMatching::Fifo => {
+                if let Some(mut note) = queue.pop_front() {
+                    note.end(time);
+                }
+            }
+            NoteOffMatching::Lifo => {
+                if let Some(mut note) = queue.pop_back() {
+                    note.end(time);
+                }
+            }
+            NoteOffMatching::EndAll => {
+                while let Some(mut note) = queue.pop_front() {
+                    note.end(time);
+                }
+            }
         }
     }
 
@@ -87,26 +108,28 @@ impl UnendedNotesHandler {
 }
 
 struct ParserState {
+    matching: NoteOffMatching,
     unended_notes: UnendedNotesHandler,
-    keys: Box<[Vec<TrackAndChannel>]>,
+    keys: Box<[Vec<(TrackAndChannel, u8)>]>,
     sender: Sender<LiveNoteBlockWithKey>,
 }
 
 impl ParserState {
-    fn new(sender: Sender<LiveNoteBlockWithKey>) -> Self {
+    fn new(sender: Sender<LiveNoteBlockWithKey>, matching: NoteOffMatching) -> Self {
         let mut keys = Vec::with_capacity(256);
         for _ in 0..256 {
             keys.push(Vec::new());
         }
         ParserState {
+            matching,
             unended_notes: UnendedNotesHandler::new(),
             keys: keys.into_boxed_slice(),
             sender,
         }
     }
 
-    fn add_note(&mut self, key: u8, track_chan: TrackAndChannel) {
-        self.keys[key as usize].push(track_chan);
+    fn add_note(&mut self, key: u8, track_chan: TrackAndChannel, velocity: u8) {
+        self.keys[key as usize].push((track_chan, velocity));
     }
 
     fn flush(&mut self, time: f64) -> Result<(), ()> {
@@ -132,7 +155,7 @@ impl ParserState {
 
     fn end_note(&mut self, key: u8, track_chan: TrackAndChannel, time: f64) {
         let track = self.unended_notes.get_track(track_chan.track());
-        track.end_note(key, track_chan.channel(), time);
+        track.end_note(key, track_chan.channel(), time, self.matching);
     }
 
     fn end_all_notes(&mut self, time: f64) {
@@ -145,13 +168,18 @@ pub struct NoteParserResult {
     pub manager: ThreadManager,
 }
 
-pub fn init_note_manager(blocks: Receiver<Arc<TrackEventBatch>>) -> NoteParserResult {
+pub fn init_note_manager(
+    blocks: Receiver<Arc<FilteredBatch>>,
+    matching: NoteOffMatching,
+    transpose: i32,
+    progress: Arc<LoadProgress>,
+) -> NoteParserResult {
     let (sender, reciever) = crossbeam_channel::unbounded();
     let parse_time_outer = Arc::new(AtomicF64::default());
 
     let parse_time = parse_time_outer.clone();
 
-    let mut state = ParserState::new(sender);
+    let mut state = ParserState::new(sender, matching);
     let join_handle = std::thread::spawn(move || {
         let mut time: f64 = 0.0;
         for block in blocks.into_iter() {
@@ -163,13 +191,25 @@ pub fn init_note_manager(blocks: Receiver<Arc<TrackEventBatch>>) -> NoteParserRe
                 time += block.delta;
             }
 
-            for event in block.iter_events() {
+            for (event, kept) in block.iter_events().zip(block.kept_events()) {
+                if !kept {
+                    continue;
+                }
                 match event.as_event() {
                     Event::NoteOn(e) => {
-                        state.add_note(e.key, TrackAndChannel::new(event.track, e.channel));
+                        if let Some(key) = transpose_key(e.key, transpose) {
+                            progress.add_key(key);
+                            state.add_note(
+                                key,
+                                TrackAndChannel::new(event.track, e.channel),
+                                e.velocity,
+                            );
+                        }
                     }
                     Event::NoteOff(e) => {
-                        state.end_note(e.key, TrackAndChannel::new(event.track, e.channel), time);
+                        if let Some(key) = transpose_key(e.key, transpose) {
+                            state.end_note(key, TrackAndChannel::new(event.track, e.channel), time);
+                        }
                     }
                     _ => {}
                 }
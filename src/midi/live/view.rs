@@ -3,14 +3,16 @@
 use gen_iter::GenIter;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
-use crate::midi::{DisplacedMIDINote, MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange};
+use crate::midi::{
+    DisplacedMIDINote, MIDINoteColumnView, MIDINoteViews, MIDIViewRange, NoteColors,
+};
 
 use super::{column::LiveNoteColumn, parse::LiveMidiParser};
 
 pub struct LiveNoteViewData {
     parser: LiveMidiParser,
     columns: Vec<LiveNoteColumn>,
-    default_track_colors: Vec<MIDIColor>,
+    colors: NoteColors,
     view_range: MIDIViewRange,
 }
 
@@ -25,7 +27,7 @@ impl<'a> LiveCurrentNoteViews<'a> {
 }
 
 impl LiveNoteViewData {
-    pub fn new(parser: LiveMidiParser, colors: Vec<MIDIColor>) -> Self {
+    pub fn new(parser: LiveMidiParser, colors: NoteColors) -> Self {
         let mut columns = Vec::with_capacity(256);
         columns.resize_with(256, LiveNoteColumn::new);
         LiveNoteViewData {
@@ -35,7 +37,7 @@ impl LiveNoteViewData {
                 start: f64::NEG_INFINITY,
                 end: f64::NEG_INFINITY,
             },
-            default_track_colors: colors,
+            colors,
         }
     }
 
@@ -111,6 +113,7 @@ impl LiveNoteViewData {
 pub struct LiveNoteColumnView<'a> {
     view: &'a LiveNoteViewData,
     column: &'a LiveNoteColumn,
+    key: u8,
     view_range: MIDIViewRange,
 }
 
@@ -124,6 +127,7 @@ impl<'a> MIDINoteViews for LiveCurrentNoteViews<'a> {
         LiveNoteColumnView {
             view: self.data,
             column: &self.data.columns[key],
+            key: key as u8,
             view_range: self.data.view_range,
         }
     }
@@ -145,7 +149,8 @@ impl<'a> MIDINoteColumnView for LiveNoteColumnView<'a> {
         Self: 'b;
 
     fn iterate_displaced_notes(&self) -> Self::Iter<'_> {
-        let colors = &self.view.default_track_colors;
+        let colors = &self.view.colors;
+        let key = self.key;
 
         let iter = GenIter(
             #[coroutine]
@@ -158,7 +163,8 @@ impl<'a> MIDINoteColumnView for LiveNoteColumnView<'a> {
                         yield DisplacedMIDINote {
                             start,
                             len: note.len,
-                            color: colors[note.track_chan.as_usize()],
+                            color: colors.get(note.track_chan.as_u32(), key, note.velocity),
+                            velocity: note.velocity,
                         };
                     }
                 }
@@ -5,18 +5,31 @@ mod live;
 #[allow(dead_code)]
 mod ram;
 
+mod analyze;
 mod audio;
+mod auto;
+mod benchmark;
+mod export;
+mod group;
 
 mod shared;
-use std::{fs::File, path::PathBuf, time::UNIX_EPOCH};
+use std::{fs::File, ops::RangeInclusive, path::PathBuf, sync::Arc, time::UNIX_EPOCH};
 
+use egui::Color32;
 use enum_dispatch::enum_dispatch;
 use image::{DynamicImage, GenericImageView, ImageReader};
+use midi_toolkit::io::{DiskReader, MIDIFile as TKMIDIFile};
 use palette::{convert::FromColorUnclamped, Hsv, Srgb};
+use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
+pub use analyze::{run_analysis, AnalysisFormat};
+pub use auto::{choose_parsing, read_track_count, AutoParsing, MidiParser};
+pub use benchmark::run_load_benchmark;
 pub use cake::{CakeBlock, CakeMIDIFile, CakeSignature, IntVector4};
+pub use export::{export_midi, MidiExportOptions};
+pub use group::{MIDIFileGroup, MIDILayer};
 pub mod pie;
 pub use live::LiveLoadMIDIFile;
 pub use pie::{PieMIDIFile, PieSignature};
@@ -24,15 +37,25 @@ pub use ram::InRamMIDIFile;
 
 use crate::{
     gui::window::WasabiError,
-    settings::{Colors, MidiSettings},
+    settings::{ColorBy, Colors, MidiSettings, PaletteOrder},
 };
 
-use self::shared::timer::TimeKeeper;
+pub use self::shared::{
+    grid::{BeatGrid, GridLine},
+    progress::LoadProgress,
+    timeline::NoteTimeline,
+    transpose::transpose_audio_event,
+};
+use self::shared::{progress::ProgressReader, timer::TimeKeeper};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct MIDIFileStats {
     pub total_notes: Option<u64>,
     pub passed_notes: Option<u64>,
+    /// Notes per second, if the file has a precomputed note timeline
+    pub nps: Option<u64>,
+    /// Notes removed by the note filters while loading
+    pub removed_notes: Option<u64>,
 }
 
 /// A struct that represents the view range of a midi screen render
@@ -83,6 +106,20 @@ fn open_file_and_signature(
     Ok((file, signature))
 }
 
+/// Opens a MIDI file for parsing, reporting the bytes read by the parser into `progress`.
+fn open_midi_with_progress(
+    path: impl Into<PathBuf>,
+    progress: &Arc<LoadProgress>,
+) -> Result<(TKMIDIFile<DiskReader>, MIDIFileUniqueSignature), WasabiError> {
+    let (file, signature) = open_file_and_signature(path)?;
+    progress.set_bytes_total(signature.length_in_bytes);
+
+    let reader = ProgressReader::new(file, progress.clone());
+    let midi = TKMIDIFile::open_from_stream(reader, None).map_err(WasabiError::MidiLoadError)?;
+
+    Ok((midi, signature))
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct MIDIColor(u32);
 
@@ -116,51 +153,114 @@ impl MIDIColor {
         vec
     }
 
-    pub fn new_random_vec(tracks: usize) -> Vec<Self> {
-        let count = tracks * 16;
+    /// Colors evenly spread around the hue circle
+    pub fn new_hue_vec(count: usize) -> Vec<Self> {
+        (0..count)
+            .map(|i| MIDIColor::new_from_hue(i as f64 * 360.0 / count as f64))
+            .collect()
+    }
 
+    pub fn new_random_vec(count: usize, rng: &mut impl Rng) -> Vec<Self> {
         let mut vec = Vec::with_capacity(count);
         for _ in 0..count {
-            let r = rand::rng().random_range(0..255) as u8;
-            let g = rand::rng().random_range(0..255) as u8;
-            let b = rand::rng().random_range(0..255) as u8;
+            let r = rng.random_range(0..255) as u8;
+            let g = rng.random_range(0..255) as u8;
+            let b = rng.random_range(0..255) as u8;
             vec.push(MIDIColor::new(r, g, b));
         }
 
         vec
     }
 
-    pub fn new_white_vec(tracks: usize) -> Vec<Self> {
-        let count = tracks * 16;
+    pub fn new_white_vec(count: usize) -> Vec<Self> {
         vec![MIDIColor::new(255, 255, 255); count]
     }
 
-    pub fn new_vec_from_palette(tracks: usize, image: DynamicImage, randomize: bool) -> Vec<Self> {
-        let image = image.to_rgb8();
-        let all_colors = image.pixels().map(|p| Self::new(p.0[0], p.0[1], p.0[2]));
+    /// Colors interpolated from `first` to `last`
+    pub fn new_gradient_vec(count: usize, first: Color32, last: Color32) -> Vec<Self> {
+        let lerp = |a: u8, b: u8, t: f32| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        (0..count)
+            .map(|i| {
+                let t = if count > 1 {
+                    i as f32 / (count - 1) as f32
+                } else {
+                    0.0
+                };
+                MIDIColor::new(
+                    lerp(first.r(), last.r(), t),
+                    lerp(first.g(), last.g(), t),
+                    lerp(first.b(), last.b(), t),
+                )
+            })
+            .collect()
+    }
 
-        let num = tracks * 16;
-        if randomize {
-            let mut rng = rand::rng();
-            all_colors
-                .choose_multiple(&mut rng, num)
+    /// Reads the colors of a palette image with 16 columns, or 32 like the Zenith
+    /// palettes, which have two colors for each channel of which the first is used.
+    /// The rows loop when the palette has less of them than needed.
+    pub fn new_vec_from_palette(
+        count: usize,
+        image: DynamicImage,
+        settings: &MidiSettings,
+        rng: &mut impl Rng,
+    ) -> Vec<Self> {
+        let image = image.to_rgb8();
+        let step = image.width() / 16;
+        let rows = image.height() as usize;
+        let pixel = |column: usize, row: usize| {
+            let p = image.get_pixel(column as u32 * step, row as u32);
+            Self::new(p.0[0], p.0[1], p.0[2])
+        };
+
+        if settings.randomize_palette {
+            return (0..rows)
+                .flat_map(|row| (0..16).map(move |column| (column, row)))
+                .map(|(column, row)| pixel(column, row))
+                .choose_multiple(rng, count)
                 .into_iter()
                 .cycle()
-                .take(num)
-                .collect()
-        } else {
-            all_colors.cycle().take(num).collect()
+                .take(count)
+                .collect();
         }
+
+        let offset = match (settings.palette_random_offset, settings.palette_order) {
+            (false, _) => 0,
+            (true, PaletteOrder::Track) => rng.random_range(0..rows),
+            (true, PaletteOrder::Channel) => rng.random_range(0..16),
+        };
+
+        (0..count)
+            .map(|i| {
+                let (track, channel) = (i / 16 + offset, i % 16);
+                match settings.palette_order {
+                    PaletteOrder::Track => pixel(channel, track % rows),
+                    PaletteOrder::Channel => pixel(track % 16, channel % rows),
+                }
+            })
+            .collect()
     }
 
     pub fn new_vec_from_settings(
         tracks: usize,
         settings: &MidiSettings,
     ) -> Result<Vec<Self>, WasabiError> {
+        let count = settings.color_by.color_count(tracks);
+        let mut rng = match settings.color_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+
         match settings.colors {
-            Colors::Rainbow => Ok(MIDIColor::new_vec(tracks)),
-            Colors::Random => Ok(MIDIColor::new_random_vec(tracks)),
-            Colors::White => Ok(MIDIColor::new_white_vec(tracks)),
+            Colors::Rainbow => match settings.color_by {
+                ColorBy::TrackChannel => Ok(MIDIColor::new_vec(tracks)),
+                _ => Ok(MIDIColor::new_hue_vec(count)),
+            },
+            Colors::Random => Ok(MIDIColor::new_random_vec(count, &mut rng)),
+            Colors::White => Ok(MIDIColor::new_white_vec(count)),
+            Colors::Gradient => {
+                let [first, last] = settings.gradient;
+                Ok(MIDIColor::new_gradient_vec(count, first, last))
+            }
             Colors::Palette => {
                 let path = &settings.palette_path;
                 if path.exists() {
@@ -173,11 +273,10 @@ impl MIDIColor {
                         .decode()
                         .map_err(|e| WasabiError::PaletteError(e.to_string()))?;
 
-                    if image.dimensions().0 == 16 {
+                    let (width, height) = image.dimensions();
+                    if (width == 16 || width == 32) && height > 0 {
                         Ok(MIDIColor::new_vec_from_palette(
-                            tracks,
-                            image,
-                            settings.randomize_palette,
+                            count, image, settings, &mut rng,
                         ))
                     } else {
                         Err(WasabiError::PaletteError(format!(
@@ -214,6 +313,37 @@ impl MIDIColor {
     }
 }
 
+/// The colors of the notes of a MIDI, looked up for each note depending on what
+/// the notes are colored by.
+#[derive(Debug, Clone)]
+pub struct NoteColors {
+    colors: Vec<MIDIColor>,
+    color_by: ColorBy,
+}
+
+impl NoteColors {
+    pub fn from_settings(tracks: usize, settings: &MidiSettings) -> Result<Self, WasabiError> {
+        Ok(Self {
+            colors: MIDIColor::new_vec_from_settings(tracks, settings)?,
+            color_by: settings.color_by,
+        })
+    }
+
+    /// The color of a note, where `track_chan` is `track * 16 + channel`
+    #[inline]
+    pub fn get(&self, track_chan: u32, key: u8, velocity: u8) -> MIDIColor {
+        let index = match self.color_by {
+            ColorBy::TrackChannel => track_chan as usize,
+            ColorBy::Track => track_chan as usize / 16,
+            ColorBy::Channel => track_chan as usize % 16,
+            ColorBy::PitchClass => key as usize % 12,
+            ColorBy::Octave => key as usize / 12,
+            ColorBy::Velocity => velocity.min(127) as usize,
+        };
+        self.colors[index]
+    }
+}
+
 /// The basic shared functions in a midi file. The columns related functions are
 /// inside the [`MIDIFile`] trait.
 #[allow(dead_code)]
@@ -230,6 +360,15 @@ pub trait MIDIFileBase {
     fn allows_seeking_backward(&self) -> bool;
 
     fn signature(&self) -> &MIDIFileUniqueSignature;
+
+    /// The note timeline computed while parsing, if the loader builds one
+    fn note_timeline(&self) -> Option<&NoteTimeline>;
+
+    /// The lowest and the highest key with notes, among the notes parsed so far
+    fn key_range(&self) -> Option<RangeInclusive<u8>>;
+
+    /// The bar and beat lines, once the tempo and time signature events are read
+    fn beat_grid(&self) -> Option<&BeatGrid>;
 }
 
 /// This trait contains a function to retrieve the column view of the midi
@@ -262,6 +401,7 @@ pub struct DisplacedMIDINote {
     pub start: f32,
     pub len: f32,
     pub color: MIDIColor,
+    pub velocity: u8,
 }
 
 #[enum_dispatch(MIDIFileBase)]
@@ -17,11 +17,11 @@ pub struct PieBlockInfo {
 
 #[derive(Clone, Copy)]
 pub struct PieNoteData {
-    #[allow(dead_code)]
     pub start_time: u32,
     #[allow(dead_code)]
     pub end_time: u32,
     pub color: MIDIColor,
+    pub velocity: u8,
 }
 
 impl FlatPieBlocks {
@@ -123,7 +123,8 @@ impl FlatPieBlocks {
             Some(PieNoteData {
                 start_time: note_start as u32,
                 end_time: note_end as u32,
-                color: MIDIColor::from_u32(note_color as u32),
+                color: MIDIColor::from_u32(note_color as u32 & 0xFFFFFF),
+                velocity: (note_color >> 24) as u8,
             })
         }
     }
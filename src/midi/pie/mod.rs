@@ -1,12 +1,12 @@
-use std::{path::PathBuf, sync::Arc, thread};
+use std::{ops::RangeInclusive, path::PathBuf, sync::Arc, thread};
 use time::Duration;
 
 use midi_toolkit::{
     events::{Event, MIDIEventEnum},
-    io::MIDIFile as TKMIDIFile,
+    io::{DiskReader, MIDIFile as TKMIDIFile},
     pipe,
     sequence::{
-        event::{cancel_tempo_events, scale_event_time, Delta, EventBatch, Track},
+        event::{cancel_tempo_events, scale_event_time},
         unwrap_items, TimeCaster,
     },
 };
@@ -16,16 +16,22 @@ use crate::{
     gui::window::WasabiError,
     midi::{
         audio::ram::InRamAudioPlayer,
-        open_file_and_signature,
+        open_midi_with_progress,
         pie::{
             blocks::FlatPieBlocks,
+            tree_serializer::TreeSerializer,
             tree_threader::{NoteEvent, ThreadedTreeSerializers},
         },
         shared::{
             audio::{FlatAudio, RawAudioBlock},
+            grid::{BeatGrid, BeatGridBuilder},
+            note_filter::{FilteredBatch, NoteFilter},
+            parallel::{KeyNoteEvent, ParallelParsedMIDI},
+            timeline::NoteTimeline,
             timer::TimeKeeper,
+            transpose::transpose_key,
         },
-        MIDIColor,
+        LoadProgress, NoteColors,
     },
     settings::MidiSettings,
 };
@@ -45,6 +51,10 @@ pub struct PieMIDIFile {
     length: f64,
     note_count: u64,
     ticks_per_second: u32,
+    timeline: NoteTimeline,
+    beat_grid: BeatGrid,
+    removed_notes: u64,
+    key_range: Option<RangeInclusive<u8>>,
     signature: MIDIFileUniqueSignature,
 }
 
@@ -53,29 +63,57 @@ impl PieMIDIFile {
         path: impl Into<PathBuf>,
         player: Arc<WasabiAudioPlayer>,
         settings: &MidiSettings,
+        progress: Arc<LoadProgress>,
     ) -> Result<Self, WasabiError> {
         let ticks_per_second = 10000;
 
-        let (file, signature) = open_file_and_signature(path)?;
-        let midi = TKMIDIFile::open_from_stream(file, None).map_err(WasabiError::MidiLoadError)?;
+        let (midi, signature) = open_midi_with_progress(path, &progress)?;
+
+        if settings.parallel_parsing {
+            return Self::load_parallel(
+                midi,
+                signature,
+                ticks_per_second,
+                player,
+                settings,
+                progress,
+            );
+        }
 
         let ppq = midi.ppq();
-        let merged = pipe!(
+        let mut grid = BeatGridBuilder::new(ppq);
+        let batches = pipe!(
             midi.iter_all_track_events_merged_batches()
             |>TimeCaster::<f64>::cast_event_delta()
+        )
+        .inspect(|batch| {
+            // Read before the tempo events are cancelled
+            if let Ok(batch) = batch {
+                grid.add_delta(batch.delta);
+                for event in batch.iter_events() {
+                    grid.add_event(event.as_event());
+                }
+            }
+        });
+        let merged = pipe!(
+            batches
             |>cancel_tempo_events(250000)
             |>scale_event_time(1.0 / ppq as f64)
             |>unwrap_items()
         );
 
-        let colors = MIDIColor::new_vec_from_settings(midi.track_count(), settings)?;
+        let colors = NoteColors::from_settings(midi.track_count(), settings)?;
+        let note_off_matching = settings.note_off_matching;
+        let min_velocity = settings.hidden_velocity;
+        let transpose = settings.transpose;
 
-        type Ev = Delta<f64, Track<EventBatch<Event>>>;
-        let (key_snd, key_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
-        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
+        let (key_snd, key_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
+        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
 
+        let key_progress = progress.clone();
         let key_join_handle = thread::spawn(move || {
-            let mut trees = ThreadedTreeSerializers::new();
+            let mut trees =
+                ThreadedTreeSerializers::new(ticks_per_second, note_off_matching, min_velocity);
 
             let mut time = 0.0;
 
@@ -89,31 +127,44 @@ impl PieMIDIFile {
                 let channel_track =
                     |channel: u8, track: u32| -> i32 { (channel as i32) + (track as i32) * 16 };
 
-                for event in batch.iter_events() {
+                for (event, kept) in batch.iter_events().zip(batch.kept_events()) {
+                    if !kept {
+                        continue;
+                    }
                     let track = event.track;
                     match event.as_event() {
                         Event::NoteOn(e) => {
+                            let Some(key) = transpose_key(e.key, transpose) else {
+                                continue;
+                            };
                             let channel_track = channel_track(e.channel, track);
+                            key_progress.add_key(key);
 
                             trees.push_event(
-                                e.key as usize,
+                                key as usize,
                                 NoteEvent::On {
                                     time: int_time,
                                     channel_track,
-                                    color: colors[channel_track as usize].as_u32() as i32,
+                                    color: colors
+                                        .get(channel_track as u32, key, e.velocity)
+                                        .as_u32() as i32,
+                                    velocity: e.velocity,
                                 },
                             );
                             note_count += 1;
                         }
                         Event::NoteOff(e) => {
+                            let Some(key) = transpose_key(e.key, transpose) else {
+                                continue;
+                            };
                             let channel_track = channel_track(e.channel, track);
 
                             trees.push_event(
-                                e.key as usize,
+                                key as usize,
                                 NoteEvent::Off {
                                     time: int_time,
                                     channel_track,
-                                    color: colors[channel_track as usize].as_u32() as i32,
+                                    color: colors.get(channel_track as u32, key, 0).as_u32() as i32,
                                 },
                             );
                         }
@@ -121,34 +172,133 @@ impl PieMIDIFile {
                     }
                 }
             }
+            if key_progress.is_cancelled() {
+                trees.discard();
+                return None;
+            }
+
             let final_time = (time * ticks_per_second as f64) as i32;
-            let serialized = trees.seal(final_time);
+            let (serialized, timelines): (Vec<_>, Vec<_>) =
+                trees.seal(final_time, &key_progress)?.into_iter().unzip();
 
+            let timeline = NoteTimeline::from_keys(timelines.iter(), time);
             let blocks = FlatPieBlocks::build_blocks(serialized, 0, final_time as u32);
 
-            (blocks, note_count)
+            Some((blocks, note_count, timeline))
         });
 
         let audio_join_handle = thread::spawn(move || {
-            let raw_blocks_iter = RawAudioBlock::build_raw_blocks(audio_rcv.into_iter());
+            let raw_blocks_iter = RawAudioBlock::build_raw_blocks(
+                audio_rcv.into_iter(),
+                note_off_matching,
+                transpose,
+            );
             FlatAudio::build_blocks(raw_blocks_iter)
         });
 
         let mut length = 0.0;
+        let mut filter = NoteFilter::new(settings, progress.clone());
 
-        // Write events to the threads
-        for batch in merged {
-            length += batch.delta;
+        let send = |batch: FilteredBatch| {
             let batch = Arc::new(batch);
             key_snd.send(batch.clone()).unwrap();
             audio_snd.send(batch).unwrap();
+        };
+
+        // Write events to the threads
+        for batch in merged {
+            if progress.is_cancelled() {
+                break;
+            }
+            length += batch.delta;
+            progress.add_events(batch.count() as u64);
+            filter.push(batch).for_each(&send);
         }
+        filter.finish().for_each(&send);
         // Drop the writers so the threads finish
         drop(key_snd);
         drop(audio_snd);
 
-        let (blocks, note_count) = key_join_handle.join().unwrap();
-        let audio = Arc::new(audio_join_handle.join().unwrap());
+        let blocks = key_join_handle.join().unwrap();
+        let audio = audio_join_handle.join().unwrap();
+
+        let Some((blocks, note_count, timeline)) = blocks else {
+            return Err(WasabiError::MidiLoadCancelled);
+        };
+        let beat_grid = grid.build(None);
+        let audio = Arc::new(audio);
+
+        let mut timer = TimeKeeper::new(settings.start_delay);
+
+        InRamAudioPlayer::new(audio.clone(), timer.get_listener(), player).spawn_playback();
+
+        Ok(PieMIDIFile {
+            blocks,
+            audio,
+            timer,
+            length,
+            note_count,
+            ticks_per_second,
+            timeline,
+            beat_grid,
+            removed_notes: progress.removed_notes(),
+            key_range: progress.key_range(),
+            signature,
+        })
+    }
+
+    /// Parses the tracks concurrently and builds the key trees in parallel. All the
+    /// note events are kept in memory until the trees are built, so the peak memory
+    /// usage is higher than with the sequential loader.
+    fn load_parallel(
+        midi: TKMIDIFile<DiskReader>,
+        signature: MIDIFileUniqueSignature,
+        ticks_per_second: u32,
+        player: Arc<WasabiAudioPlayer>,
+        settings: &MidiSettings,
+        progress: Arc<LoadProgress>,
+    ) -> Result<Self, WasabiError> {
+        let colors = NoteColors::from_settings(midi.track_count(), settings)?;
+
+        let note_off_matching = settings.note_off_matching;
+        let min_velocity = settings.hidden_velocity;
+        let Some(parsed) = ParallelParsedMIDI::parse(&midi, settings, &progress) else {
+            return Err(WasabiError::MidiLoadCancelled);
+        };
+
+        let length = parsed.length();
+        let note_count = parsed.note_count();
+        let beat_grid = parsed.beat_grid().clone();
+        let final_time = (length * ticks_per_second as f64) as i32;
+
+        let (trees, audio) = parsed.build(ticks_per_second, note_off_matching, |key, events| {
+            let mut tree = TreeSerializer::new(ticks_per_second, note_off_matching, min_velocity);
+            for event in events {
+                match event {
+                    KeyNoteEvent::On {
+                        time,
+                        channel_track,
+                        velocity,
+                    } => {
+                        let color = colors.get(channel_track as u32, key, velocity).as_u32() as i32;
+                        tree.start_note(time, channel_track, color, velocity);
+                    }
+                    KeyNoteEvent::Off {
+                        time,
+                        channel_track,
+                    } => {
+                        tree.end_note(time, channel_track);
+                    }
+                }
+            }
+            tree.complete_and_seal(final_time)
+        });
+
+        let (trees, timelines): (Vec<_>, Vec<_>) = trees.into_iter().unzip();
+
+        let timeline = NoteTimeline::from_keys(timelines.iter(), length);
+        let blocks = FlatPieBlocks::build_blocks(trees, 0, final_time as u32);
+        let audio = Arc::new(audio);
 
         let mut timer = TimeKeeper::new(settings.start_delay);
 
@@ -161,6 +311,10 @@ impl PieMIDIFile {
             length,
             note_count,
             ticks_per_second,
+            timeline,
+            beat_grid,
+            removed_notes: progress.removed_notes(),
+            key_range: progress.key_range(),
             signature,
         })
     }
@@ -178,6 +332,10 @@ impl PieMIDIFile {
         self.ticks_per_second
     }
 
+    pub fn timeline(&self) -> &NoteTimeline {
+        &self.timeline
+    }
+
     pub fn current_time(&self) -> Duration {
         self.timer.get_time()
     }
@@ -234,10 +392,24 @@ impl MIDIFileBase for PieMIDIFile {
         MIDIFileStats {
             total_notes: Some(self.note_count),
             passed_notes: Some(passed_notes),
+            nps: Some(self.timeline.nps_at(time)),
+            removed_notes: Some(self.removed_notes),
         }
     }
 
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn note_timeline(&self) -> Option<&NoteTimeline> {
+        Some(&self.timeline)
+    }
+
+    fn key_range(&self) -> Option<RangeInclusive<u8>> {
+        self.key_range.clone()
+    }
+
+    fn beat_grid(&self) -> Option<&BeatGrid> {
+        Some(&self.beat_grid)
+    }
 }
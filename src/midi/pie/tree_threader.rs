@@ -2,6 +2,11 @@ use std::sync::{Arc, Mutex};
 
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
 
+use crate::{
+    midi::shared::{progress::LoadProgress, timeline::KeyTimeline},
+    settings::NoteOffMatching,
+};
+
 use super::tree_serializer::TreeSerializer;
 
 pub enum NoteEvent {
@@ -9,6 +14,7 @@ pub enum NoteEvent {
         time: i32,
         channel_track: i32,
         color: i32,
+        velocity: u8,
     },
     Off {
         time: i32,
@@ -32,8 +38,14 @@ impl ThreadedTreeSerializers {
         (0..256).map(|_| Vec::new()).collect()
     }
 
-    pub fn new() -> ThreadedTreeSerializers {
-        let trees = (0..256).map(|_| TreeSerializer::new()).collect::<Vec<_>>();
+    pub fn new(
+        ticks_per_second: u32,
+        matching: NoteOffMatching,
+        min_velocity: u8,
+    ) -> ThreadedTreeSerializers {
+        let trees = (0..256)
+            .map(|_| TreeSerializer::new(ticks_per_second, matching, min_velocity))
+            .collect::<Vec<_>>();
         let trees = Arc::new(Mutex::new(trees));
 
         let (snd_in, rcv_in) = crossbeam_channel::unbounded::<Vec<Vec<NoteEvent>>>();
@@ -53,8 +65,9 @@ impl ThreadedTreeSerializers {
                                     time,
                                     channel_track,
                                     color,
+                                    velocity,
                                 } => {
-                                    tree.start_note(time, channel_track, color);
+                                    tree.start_note(time, channel_track, color, velocity);
                                 }
                                 NoteEvent::Off {
                                     time,
@@ -100,7 +113,17 @@ impl ThreadedTreeSerializers {
         }
     }
 
-    pub fn seal(self, time: i32) -> Vec<Vec<i32>> {
+    /// Stops the tree thread without building the trees, freeing all the data
+    /// which was collected so far.
+    pub fn discard(self) {
+        drop(self.snd);
+        for _ in self.rcv.iter() {}
+        self.join.join().unwrap();
+    }
+
+    /// Completes the trees of every key, or returns `None` if the load gets cancelled
+    /// in the meantime.
+    pub fn seal(self, time: i32, progress: &LoadProgress) -> Option<Vec<(Vec<i32>, KeyTimeline)>> {
         self.snd.send(self.current_vec).unwrap();
         drop(self.snd);
 
@@ -113,10 +136,13 @@ impl ThreadedTreeSerializers {
 
         let mut serialized = Vec::new();
         for tree in trees.into_iter() {
+            if progress.is_cancelled() {
+                return None;
+            }
             let sealed = tree.complete_and_seal(time);
             serialized.push(sealed);
         }
 
-        serialized
+        Some(serialized)
     }
 }
@@ -93,6 +93,11 @@ impl FlatNoteColumn {
         &self.notes_buffer[start..end]
     }
 
+    /// Get the notes of every block
+    pub fn notes(&self) -> &[BasicMIDINote] {
+        &self.notes_buffer
+    }
+
     /// Get block info for a specific block
     pub fn get_block_info(&self, block_index: usize) -> NoteBlockInfo {
         self.block_info[block_index]
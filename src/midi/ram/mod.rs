@@ -1,8 +1,13 @@
-use self::view::{InRamCurrentNoteViews, InRamNoteViewData};
+use std::ops::RangeInclusive;
+
+use self::{
+    column::FlatNoteColumn,
+    view::{InRamCurrentNoteViews, InRamNoteViewData},
+};
 
 use super::{
-    shared::timer::TimeKeeper, MIDIFile, MIDIFileBase, MIDIFileStats, MIDIFileUniqueSignature,
-    MIDIViewRange,
+    shared::timer::TimeKeeper, BeatGrid, MIDIFile, MIDIFileBase, MIDIFileStats,
+    MIDIFileUniqueSignature, MIDIViewRange, NoteTimeline,
 };
 
 pub mod block;
@@ -15,10 +20,19 @@ pub struct InRamMIDIFile {
     timer: TimeKeeper,
     length: f64,
     note_count: u64,
+    timeline: NoteTimeline,
+    beat_grid: BeatGrid,
+    removed_notes: u64,
+    key_range: Option<RangeInclusive<u8>>,
     signature: MIDIFileUniqueSignature,
 }
 
-impl InRamMIDIFile {}
+impl InRamMIDIFile {
+    /// The note columns of the keys, in key order
+    pub fn columns(&self) -> &[FlatNoteColumn] {
+        self.view_data.columns()
+    }
+}
 
 impl MIDIFileBase for InRamMIDIFile {
     fn midi_length(&self) -> Option<f64> {
@@ -42,15 +56,31 @@ impl MIDIFileBase for InRamMIDIFile {
     }
 
     fn stats(&self) -> MIDIFileStats {
+        let time = self.timer.get_time().as_seconds_f64();
+
         MIDIFileStats {
             total_notes: Some(self.note_count),
             passed_notes: Some(self.view_data.passed_notes()),
+            nps: Some(self.timeline.nps_at(time)),
+            removed_notes: Some(self.removed_notes),
         }
     }
 
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn note_timeline(&self) -> Option<&NoteTimeline> {
+        Some(&self.timeline)
+    }
+
+    fn key_range(&self) -> Option<RangeInclusive<u8>> {
+        self.key_range.clone()
+    }
+
+    fn beat_grid(&self) -> Option<&BeatGrid> {
+        Some(&self.beat_grid)
+    }
 }
 
 impl MIDIFile for InRamMIDIFile {
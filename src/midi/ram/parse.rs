@@ -2,10 +2,9 @@ use std::{collections::VecDeque, path::PathBuf, sync::Arc, thread};
 
 use midi_toolkit::{
     events::{Event, MIDIEventEnum},
-    io::MIDIFile as TKMIDIFile,
     pipe,
     sequence::{
-        event::{cancel_tempo_events, scale_event_time, Delta, EventBatch, Track},
+        event::{cancel_tempo_events, scale_event_time},
         unwrap_items, TimeCaster,
     },
 };
@@ -16,44 +15,53 @@ use crate::{
     gui::window::WasabiError,
     midi::{
         audio::ram::InRamAudioPlayer,
-        open_file_and_signature,
+        open_midi_with_progress,
         ram::{column::FlatNoteColumn, view::InRamNoteViewData},
         shared::{
             audio::{FlatAudio, RawAudioBlock},
+            grid::BeatGridBuilder,
+            note_filter::{FilteredBatch, NoteFilter},
+            timeline::{KeyTimeline, NoteTimeline},
             timer::TimeKeeper,
             track_channel::TrackAndChannel,
+            transpose::transpose_key,
         },
-        MIDIColor,
+        LoadProgress, NoteColors,
     },
-    settings::MidiSettings,
+    settings::{MidiSettings, NoteOffMatching},
 };
 
 use super::{block::InRamNoteBlock, InRamMIDIFile};
 
+/// Resolution of the times passed to the note timeline
+const TIMELINE_TICKS_PER_SECOND: u32 = 10000;
+
 struct UnendedNote {
     column_index: usize,
     block_index: usize,
 }
 
 struct Key {
+    matching: NoteOffMatching,
     column: Vec<InRamNoteBlock>,
-    block_builder: Vec<TrackAndChannel>,
+    block_builder: Vec<(TrackAndChannel, u8)>,
     unended_notes: FxHashMap<TrackAndChannel, VecDeque<UnendedNote>>,
 }
 
 impl Key {
-    fn new() -> Self {
+    fn new(matching: NoteOffMatching) -> Self {
         Key {
+            matching,
             column: Vec::new(),
             block_builder: Vec::new(),
             unended_notes: FxHashMap::default(),
         }
     }
 
-    fn add_note(&mut self, track_chan: TrackAndChannel) {
+    fn add_note(&mut self, track_chan: TrackAndChannel, velocity: u8) {
         let block_index = self.block_builder.len();
         let column_index = self.column.len();
-        self.block_builder.push(track_chan);
+        self.block_builder.push((track_chan, velocity));
         let unended_queue = self.unended_notes.entry(track_chan).or_default();
         unended_queue.push_back(UnendedNote {
             column_index,
@@ -61,13 +69,24 @@ impl Key {
         });
     }
 
-    pub fn end_note(&mut self, track_chan: TrackAndChannel, time: f64) {
-        let note = self
-            .unended_notes
-            .get_mut(&track_chan)
-            .and_then(|unended_queue| unended_queue.pop_front());
+    /// Ends the unended notes of `track_chan` picked by the note off matching, and
+    /// returns how many were ended.
+    pub fn end_note(&mut self, track_chan: TrackAndChannel, time: f64) -> u32 {
+        let mut ended = 0;
+
+        loop {
+            let note = self
+                .unended_notes
+                .get_mut(&track_chan)
+                .and_then(|unended_queue| match self.matching {
+                    NoteOffMatching::Fifo | NoteOffMatching::EndAll => unended_queue.pop_front(),
+                    NoteOffMatching::Lifo => unended_queue.pop_back(),
+                });
+
+            let Some(note) = note else {
+                break;
+            };
 
-        if let Some(note) = note {
             if note.column_index == self.column.len() {
                 // Note is zero length
                 // We don't need to remove it, because when it gets added,
@@ -79,7 +98,14 @@ impl Key {
                 }
                 block.set_note_end_time(note.block_index, time);
             }
+            ended += 1;
+
+            if self.matching != NoteOffMatching::EndAll {
+                break;
+            }
         }
+
+        ended
     }
 
     pub fn flush(&mut self, time: f64) {
@@ -89,12 +115,16 @@ impl Key {
         }
     }
 
-    pub fn end_all(&mut self, time: f64) {
+    /// Ends all the unended notes, and returns how many were ended
+    pub fn end_all(&mut self, time: f64) -> u32 {
+        let mut ended = 0;
         for (_, mut queue) in self.unended_notes.drain() {
             for note in queue.drain(..) {
                 self.column[note.column_index].set_note_end_time(note.block_index, time);
+                ended += 1;
             }
         }
+        ended
     }
 }
 
@@ -103,25 +133,41 @@ impl InRamMIDIFile {
         path: impl Into<PathBuf>,
         player: Arc<WasabiAudioPlayer>,
         settings: &MidiSettings,
+        progress: Arc<LoadProgress>,
     ) -> Result<Self, WasabiError> {
-        let (file, signature) = open_file_and_signature(path)?;
-        let midi = TKMIDIFile::open_from_stream(file, None).map_err(WasabiError::MidiLoadError)?;
+        let (midi, signature) = open_midi_with_progress(path, &progress)?;
+        let note_off_matching = settings.note_off_matching;
+        let transpose = settings.transpose;
 
         let ppq = midi.ppq();
-        let merged = pipe!(
+        let mut grid = BeatGridBuilder::new(ppq);
+        let batches = pipe!(
             midi.iter_all_track_events_merged_batches()
             |>TimeCaster::<f64>::cast_event_delta()
+        )
+        .inspect(|batch| {
+            // Read before the tempo events are cancelled
+            if let Ok(batch) = batch {
+                grid.add_delta(batch.delta);
+                for event in batch.iter_events() {
+                    grid.add_event(event.as_event());
+                }
+            }
+        });
+        let merged = pipe!(
+            batches
             |>cancel_tempo_events(250000)
             |>scale_event_time(1.0 / ppq as f64)
             |>unwrap_items()
         );
 
-        type Ev = Delta<f64, Track<EventBatch<Event>>>;
-        let (key_snd, key_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
-        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
+        let (key_snd, key_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
+        let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<FilteredBatch>>(1000);
 
-        let key_join_handle = thread::spawn(|| {
-            let mut keys: Vec<Key> = (0..256).map(|_| Key::new()).collect();
+        let key_progress = progress.clone();
+        let key_join_handle = thread::spawn(move || {
+            let mut keys: Vec<Key> = (0..256).map(|_| Key::new(note_off_matching)).collect();
+            let mut timeline = KeyTimeline::new(TIMELINE_TICKS_PER_SECOND);
 
             let mut time = 0.0;
 
@@ -139,53 +185,93 @@ impl InRamMIDIFile {
                     time += batch.delta;
                 }
 
-                for event in batch.iter_events() {
+                let int_time = (time * TIMELINE_TICKS_PER_SECOND as f64) as i32;
+
+                for (event, kept) in batch.iter_events().zip(batch.kept_events()) {
+                    if !kept {
+                        continue;
+                    }
                     let track = event.track;
                     match event.as_event() {
                         Event::NoteOn(e) => {
+                            let Some(key) = transpose_key(e.key, transpose) else {
+                                continue;
+                            };
                             let track_chan = TrackAndChannel::new(track, e.channel);
-                            keys[e.key as usize].add_note(track_chan);
+                            keys[key as usize].add_note(track_chan, e.velocity);
+                            key_progress.add_key(key);
+                            timeline.note_start(int_time);
                             notes += 1;
                         }
                         Event::NoteOff(e) => {
+                            let Some(key) = transpose_key(e.key, transpose) else {
+                                continue;
+                            };
                             let track_chan = TrackAndChannel::new(track, e.channel);
-                            keys[e.key as usize].end_note(track_chan, time);
+                            let ended = keys[key as usize].end_note(track_chan, time);
+                            timeline.notes_end(int_time, ended);
                         }
                         _ => {}
                     }
                 }
             }
 
+            if key_progress.is_cancelled() {
+                return None;
+            }
+
             flush_keys(time, &mut keys);
 
+            let int_time = (time * TIMELINE_TICKS_PER_SECOND as f64) as i32;
             for key in keys.iter_mut() {
-                key.end_all(time);
+                timeline.notes_end(int_time, key.end_all(time));
             }
 
-            (keys, notes)
+            let timeline = NoteTimeline::from_keys(std::iter::once(&timeline), time);
+
+            Some((keys, notes, timeline))
         });
 
         let audio_join_handle = thread::spawn(move || {
-            let raw_blocks: Vec<_> =
-                RawAudioBlock::build_raw_blocks(audio_rcv.into_iter()).collect();
+            let raw_blocks: Vec<_> = RawAudioBlock::build_raw_blocks(
+                audio_rcv.into_iter(),
+                note_off_matching,
+                transpose,
+            )
+            .collect();
             FlatAudio::build_blocks(raw_blocks.into_iter())
         });
         let mut length = 0.0;
+        let mut filter = NoteFilter::new(settings, progress.clone());
 
-        // Write events to the threads
-        for batch in merged {
-            length += batch.delta;
+        let send = |batch: FilteredBatch| {
             let batch = Arc::new(batch);
             key_snd.send(batch.clone()).unwrap();
             audio_snd.send(batch).unwrap();
+        };
+
+        // Write events to the threads
+        for batch in merged {
+            if progress.is_cancelled() {
+                break;
+            }
+            length += batch.delta;
+            progress.add_events(batch.count() as u64);
+            filter.push(batch).for_each(&send);
         }
+        filter.finish().for_each(&send);
         // Drop the writers so the threads finish
         drop(key_snd);
         drop(audio_snd);
 
-        let (keys, note_count) = key_join_handle.join().unwrap();
+        let keys = key_join_handle.join().unwrap();
         let audio = audio_join_handle.join().unwrap();
 
+        let Some((keys, note_count, timeline)) = keys else {
+            return Err(WasabiError::MidiLoadCancelled);
+        };
+        let beat_grid = grid.build(None);
+
         let mut timer = TimeKeeper::new(settings.start_delay);
 
         InRamAudioPlayer::new(Arc::new(audio), timer.get_listener(), player).spawn_playback();
@@ -195,13 +281,17 @@ impl InRamMIDIFile {
             .map(|key| FlatNoteColumn::build_from_blocks(key.column))
             .collect();
 
-        let colors = MIDIColor::new_vec_from_settings(midi.track_count(), settings)?;
+        let colors = NoteColors::from_settings(midi.track_count(), settings)?;
 
         Ok(InRamMIDIFile {
             view_data: InRamNoteViewData::new(columns, colors),
             timer,
             length,
             note_count,
+            timeline,
+            beat_grid,
+            removed_notes: progress.removed_notes(),
+            key_range: progress.key_range(),
             signature,
         })
     }
@@ -1,13 +1,15 @@
 use gen_iter::GenIter;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
-use crate::midi::{DisplacedMIDINote, MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange};
+use crate::midi::{
+    DisplacedMIDINote, MIDINoteColumnView, MIDINoteViews, MIDIViewRange, NoteColors,
+};
 
 use super::column::FlatNoteColumn;
 
 pub struct InRamNoteViewData {
     columns: Vec<FlatNoteColumn>,
-    default_track_colors: Vec<MIDIColor>,
+    colors: NoteColors,
     view_range: MIDIViewRange,
 }
 
@@ -22,17 +24,21 @@ impl<'a> InRamCurrentNoteViews<'a> {
 }
 
 impl InRamNoteViewData {
-    pub fn new(columns: Vec<FlatNoteColumn>, colors: Vec<MIDIColor>) -> Self {
+    pub fn new(columns: Vec<FlatNoteColumn>, colors: NoteColors) -> Self {
         InRamNoteViewData {
             columns,
             view_range: MIDIViewRange {
                 start: 0.0,
                 end: 0.0,
             },
-            default_track_colors: colors,
+            colors,
         }
     }
 
+    pub fn columns(&self) -> &[FlatNoteColumn] {
+        &self.columns
+    }
+
     pub fn passed_notes(&self) -> u64 {
         self.columns
             .iter()
@@ -155,6 +161,7 @@ impl<'a> MIDINoteViews for InRamCurrentNoteViews<'a> {
         InRamNoteColumnView {
             view: self.data,
             column: &self.data.columns[key],
+            key: key as u8,
             view_range: self.data.view_range,
         }
     }
@@ -172,6 +179,7 @@ struct InRamNoteBlockIter<'a, Iter: Iterator<Item = DisplacedMIDINote>> {
 pub struct InRamNoteColumnView<'a> {
     view: &'a InRamNoteViewData,
     column: &'a FlatNoteColumn,
+    key: u8,
     view_range: MIDIViewRange,
 }
 
@@ -182,7 +190,8 @@ impl<'a> MIDINoteColumnView for InRamNoteColumnView<'a> {
         Self: 'b;
 
     fn iterate_displaced_notes(&self) -> Self::Iter<'_> {
-        let colors = &self.view.default_track_colors;
+        let colors = &self.view.colors;
+        let key = self.key;
 
         let iter = GenIter(
             #[coroutine]
@@ -196,7 +205,8 @@ impl<'a> MIDINoteColumnView for InRamNoteColumnView<'a> {
                         yield DisplacedMIDINote {
                             start,
                             len: note.len,
-                            color: colors[note.track_chan.as_usize()],
+                            color: colors.get(note.track_chan.as_u32(), key, note.velocity),
+                            velocity: note.velocity,
                         };
                     }
                 }
@@ -1,11 +1,133 @@
 use std::sync::Arc;
 
 use gen_iter::GenIter;
-use midi_toolkit::{
-    events::{Event, MIDIEventEnum},
-    sequence::event::{Delta, EventBatch, Track},
+use midi_toolkit::events::{Event, MIDIEventEnum};
+use rustc_hash::FxHashMap;
+
+use crate::settings::NoteOffMatching;
+
+use super::{
+    note_filter::{note_id, FilteredBatch},
+    transpose::transpose_key,
 };
 
+/// A single audio event in the compact byte format used by [`RawAudioBlock`]
+#[derive(Debug, Clone, Copy)]
+pub struct EncodedAudioEvent {
+    bytes: [u8; 3],
+    len: u8,
+    control: bool,
+}
+
+impl EncodedAudioEvent {
+    /// Encodes an event with its key shifted by `transpose` semitones, returning `None`
+    /// if the event isn't relevant for audio playback or its key is out of range
+    pub fn encode(event: &Event, transpose: i32) -> Option<Self> {
+        let (bytes, len, control) = match event {
+            Event::NoteOn(e) => {
+                let key = transpose_key(e.key, transpose)?;
+                ([EV_ON | e.channel, key, e.velocity], 3, false)
+            }
+            Event::NoteOff(e) => {
+                let key = transpose_key(e.key, transpose)?;
+                ([EV_OFF | e.channel, key, 0], 2, false)
+            }
+            Event::PolyphonicKeyPressure(e) => {
+                let key = transpose_key(e.key, transpose)?;
+                ([EV_POLYPHONIC | e.channel, key, e.velocity], 3, false)
+            }
+            Event::ControlChange(e) => ([EV_CONTROL | e.channel, e.controller, e.value], 3, true),
+            Event::ProgramChange(e) => ([EV_PROGRAM | e.channel, e.program, 0], 2, true),
+            Event::ChannelPressure(e) => ([EV_CHAN_PRESSURE | e.channel, e.pressure, 0], 2, true),
+            Event::PitchWheelChange(e) => {
+                let value = e.pitch + 8192;
+                (
+                    [
+                        EV_PITCH_BEND | e.channel,
+                        (value & 0x7F) as u8,
+                        ((value >> 7) & 0x7F) as u8,
+                    ],
+                    3,
+                    true,
+                )
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            bytes,
+            len,
+            control,
+        })
+    }
+
+    /// The key of note on and note off events
+    pub fn note_key(&self) -> Option<u8> {
+        match self.bytes[0] & 0xF0 {
+            EV_ON | EV_OFF => Some(self.bytes[1]),
+            _ => None,
+        }
+    }
+
+    fn write_into(&self, data: &mut Vec<u8>, control_data: &mut Vec<u8>) {
+        let bytes = &self.bytes[..self.len as usize];
+        data.extend_from_slice(bytes);
+        if self.control {
+            control_data.extend_from_slice(bytes);
+        }
+    }
+}
+
+/// Makes the note offs sent to the synth agree with the note off matching used for the
+/// rendered notes. Synths end a single voice per note off, so with [`NoteOffMatching::EndAll`]
+/// the note off is repeated for every playing note of its track, channel and key.
+///
+/// Synths can't be told which voice a note off ends, so [`NoteOffMatching::Lifo`] only
+/// applies to the rendered notes and the audio always ends the oldest voice.
+pub struct AudioNoteMatcher {
+    matching: NoteOffMatching,
+    /// Keyed by [`note_id`]
+    playing: FxHashMap<u64, u32>,
+}
+
+impl AudioNoteMatcher {
+    pub fn new(matching: NoteOffMatching) -> Self {
+        Self {
+            matching,
+            playing: FxHashMap::default(),
+        }
+    }
+
+    /// Passes the events which should be sent to the synth for `event` of `track`
+    /// into `write`
+    pub fn process(
+        &mut self,
+        track: u32,
+        event: EncodedAudioEvent,
+        mut write: impl FnMut(EncodedAudioEvent),
+    ) {
+        if self.matching != NoteOffMatching::EndAll {
+            write(event);
+            return;
+        }
+
+        let id = note_id(event.bytes[1], track, event.bytes[0] & 0x0F);
+        match event.bytes[0] & 0xF0 {
+            EV_ON => {
+                *self.playing.entry(id).or_default() += 1;
+                write(event);
+            }
+            EV_OFF => {
+                let count = self.playing.remove(&id).unwrap_or(0);
+                for _ in 0..count.max(1) {
+                    write(event);
+                }
+            }
+            _ => write(event),
+        }
+    }
+}
+
 // New struct to represent individual audio blocks, similar to the old CompressedAudio
 pub struct RawAudioBlock {
     pub time: f64,
@@ -37,14 +159,14 @@ const EV_CHAN_PRESSURE: u8 = 0xD0;
 const EV_PITCH_BEND: u8 = 0xE0;
 
 impl RawAudioBlock {
-    pub fn build_raw_blocks<
-        Iter: Iterator<Item = Arc<Delta<f64, Track<EventBatch<E>>>>>,
-        E: MIDIEventEnum,
-    >(
+    pub fn build_raw_blocks<Iter: Iterator<Item = Arc<FilteredBatch>>>(
         iter: Iter,
+        matching: NoteOffMatching,
+        transpose: i32,
     ) -> impl Iterator<Item = RawAudioBlock> {
         let mut builder_vec: Vec<u8> = Vec::new();
         let mut control_builder_vec: Vec<u8> = Vec::new();
+        let mut matcher = AudioNoteMatcher::new(matching);
         GenIter(
             #[coroutine]
             move || {
@@ -59,50 +181,16 @@ impl RawAudioBlock {
                     builder_vec.clear();
                     control_builder_vec.clear(); // Clear control builder for each block
 
-                    for event in block.iter_events() {
-                        match event.as_event() {
-                            Event::NoteOn(e) => {
-                                let head = EV_ON | e.channel;
-                                let events = &[head, e.key, e.velocity];
-                                builder_vec.extend_from_slice(events);
-                            }
-                            Event::NoteOff(e) => {
-                                let head = EV_OFF | e.channel;
-                                let events = &[head, e.key];
-                                builder_vec.extend_from_slice(events);
-                            }
-                            Event::PolyphonicKeyPressure(e) => {
-                                let head = EV_POLYPHONIC | e.channel;
-                                let events = &[head, e.key, e.velocity];
-                                builder_vec.extend_from_slice(events);
-                            }
-                            Event::ControlChange(e) => {
-                                let head = EV_CONTROL | e.channel;
-                                let events = &[head, e.controller, e.value];
-                                builder_vec.extend_from_slice(events);
-                                control_builder_vec.extend_from_slice(events);
-                            }
-                            Event::ProgramChange(e) => {
-                                let head = EV_PROGRAM | e.channel;
-                                let events = &[head, e.program];
-                                builder_vec.extend_from_slice(events);
-                                control_builder_vec.extend_from_slice(events);
-                            }
-                            Event::ChannelPressure(e) => {
-                                let head = EV_CHAN_PRESSURE | e.channel;
-                                let events = &[head, e.pressure];
-                                builder_vec.extend_from_slice(events);
-                                control_builder_vec.extend_from_slice(events);
-                            }
-                            Event::PitchWheelChange(e) => {
-                                let head = EV_PITCH_BEND | e.channel;
-                                let value = e.pitch + 8192;
-                                let events =
-                                    &[head, (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8];
-                                builder_vec.extend_from_slice(events);
-                                control_builder_vec.extend_from_slice(events);
-                            }
-                            _ => {}
+                    for (event, kept) in block.iter_events().zip(block.kept_events()) {
+                        if !kept {
+                            continue;
+                        }
+                        if let Some(encoded) =
+                            EncodedAudioEvent::encode(event.as_event(), transpose)
+                        {
+                            matcher.process(event.track, encoded, |e| {
+                                e.write_into(&mut builder_vec, &mut control_builder_vec)
+                            });
                         }
                     }
 
@@ -124,6 +212,28 @@ impl RawAudioBlock {
         )
     }
 
+    /// Builds a block from events which were already encoded, e.g. by the parallel parser
+    pub fn from_encoded_events(
+        time: f64,
+        events: impl Iterator<Item = EncodedAudioEvent>,
+    ) -> RawAudioBlock {
+        let mut data = Vec::new();
+        let mut control_data = Vec::new();
+        for event in events {
+            event.write_into(&mut data, &mut control_data);
+        }
+
+        RawAudioBlock {
+            time,
+            data,
+            control_only_data: if control_data.is_empty() {
+                None
+            } else {
+                Some(control_data)
+            },
+        }
+    }
+
     pub fn iter_events(&self) -> impl '_ + Iterator<Item = u32> {
         RawAudioBlock::iter_events_from_vec(self.data.iter().cloned())
     }
@@ -0,0 +1,160 @@
+use midi_toolkit::events::Event;
+
+use super::tempo::TempoMap;
+
+/// Files with tiny beats or very long lengths would otherwise produce millions of lines
+const MAX_GRID_LINES: usize = 1_000_000;
+
+/// A bar or beat line of a [`BeatGrid`]
+#[derive(Debug, Clone, Copy)]
+pub struct GridLine {
+    /// Time of the line in seconds
+    pub time: f64,
+    /// Number of the bar starting at the line, counted from 1. `None` for the other beats.
+    pub bar: Option<u32>,
+}
+
+/// The bar and beat lines of a MIDI, sorted by time
+#[derive(Debug, Clone, Default)]
+pub struct BeatGrid {
+    lines: Vec<GridLine>,
+    /// Time in seconds and tempo in microseconds per beat of the tempo events
+    tempos: Vec<(f64, u32)>,
+}
+
+impl BeatGrid {
+    /// The lines between `start` and `end` seconds
+    pub fn lines_between(&self, start: f64, end: f64) -> &[GridLine] {
+        let first = self.lines.partition_point(|line| line.time < start);
+        let last = self.lines.partition_point(|line| line.time <= end);
+        &self.lines[first..last]
+    }
+
+    /// The tempo events of the MIDI, sorted by time
+    pub fn tempo_changes(&self) -> &[(f64, u32)] {
+        &self.tempos
+    }
+
+    /// The same grid, starting `offset` seconds later
+    pub fn shifted(&self, offset: f64) -> BeatGrid {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| GridLine {
+                time: line.time + offset,
+                ..*line
+            })
+            .collect();
+        let tempos = self
+            .tempos
+            .iter()
+            .map(|&(time, tempo)| (time + offset, tempo))
+            .collect();
+        BeatGrid { lines, tempos }
+    }
+}
+
+/// Collects the tempo and time signature events of a MIDI and builds its [`BeatGrid`]
+pub struct BeatGridBuilder {
+    ppq: u16,
+    /// Tick of the events passed to `add_event`
+    tick: f64,
+    tempos: Vec<(f64, u32)>,
+    /// Tick, numerator and the power of two of the denominator
+    time_signatures: Vec<(f64, u8, u8)>,
+}
+
+impl BeatGridBuilder {
+    pub fn new(ppq: u16) -> Self {
+        Self {
+            ppq: ppq.max(1),
+            tick: 0.0,
+            tempos: Vec::new(),
+            time_signatures: Vec::new(),
+        }
+    }
+
+    /// Moves the time of the next events forward, in ticks
+    pub fn add_delta(&mut self, delta: f64) {
+        self.tick += delta;
+    }
+
+    /// Records the event if it changes the tempo or the time signature
+    pub fn add_event(&mut self, event: &Event) {
+        self.add_event_at(self.tick, event);
+    }
+
+    /// Same as `add_event`, for events whose tick is tracked by the caller
+    pub fn add_event_at(&mut self, tick: f64, event: &Event) {
+        match event {
+            Event::Tempo(e) => self.add_tempo(tick, e.tempo),
+            Event::TimeSignature(e) => self.add_time_signature(tick, e.numerator, e.denominator),
+            _ => {}
+        }
+    }
+
+    pub fn add_tempo(&mut self, tick: f64, tempo: u32) {
+        self.tempos.push((tick, tempo));
+    }
+
+    pub fn add_time_signature(&mut self, tick: f64, numerator: u8, denominator: u8) {
+        self.time_signatures.push((tick, numerator, denominator));
+    }
+
+    /// Builds the lines up to `end_tick`, or up to the last added delta if it's `None`
+    pub fn build(mut self, end_tick: Option<f64>) -> BeatGrid {
+        let end_tick = end_tick.unwrap_or(self.tick);
+
+        // Stable, so the last of several simultaneous events is the one used
+        self.time_signatures.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.tempos.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let tempo_map = TempoMap::from_tempos(self.tempos.clone(), self.ppq);
+        let tempos = self
+            .tempos
+            .iter()
+            .map(|&(tick, tempo)| (tempo_map.seconds_at(tick), tempo))
+            .collect();
+        let mut signatures = self.time_signatures.iter().peekable();
+
+        let mut lines = Vec::new();
+        let mut bar = 1;
+        let (mut start, mut numerator, mut denominator) = (0.0, 4, 2);
+
+        while start <= end_tick && lines.len() < MAX_GRID_LINES {
+            // Apply every signature starting here, the last one wins
+            while let Some(&&(tick, num, denom)) = signatures.peek() {
+                if tick > start {
+                    break;
+                }
+                (numerator, denominator) = (num.max(1), denom.min(6));
+                signatures.next();
+            }
+
+            let next_signature = signatures.peek().map_or(f64::INFINITY, |s| s.0);
+            let beat_ticks = self.ppq as f64 * 4.0 / (1u32 << denominator) as f64;
+
+            // Each signature starts a new bar, even if the previous one is incomplete
+            let mut beat = 0u32;
+            loop {
+                let tick = start + beat as f64 * beat_ticks;
+                if tick >= next_signature || tick > end_tick || lines.len() >= MAX_GRID_LINES {
+                    start = next_signature;
+                    break;
+                }
+
+                let is_bar = beat % numerator as u32 == 0;
+                lines.push(GridLine {
+                    time: tempo_map.seconds_at(tick),
+                    bar: is_bar.then_some(bar),
+                });
+                if is_bar {
+                    bar += 1;
+                }
+                beat += 1;
+            }
+        }
+
+        BeatGrid { lines, tempos }
+    }
+}
@@ -1,3 +1,10 @@
 pub mod audio;
+pub mod grid;
+pub mod note_filter;
+pub mod parallel;
+pub mod progress;
+pub mod tempo;
+pub mod timeline;
 pub mod timer;
 pub mod track_channel;
+pub mod transpose;
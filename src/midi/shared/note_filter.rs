@@ -0,0 +1,369 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::Deref,
+    sync::Arc,
+};
+
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum},
+    sequence::event::{Delta, EventBatch, Track},
+};
+use rustc_hash::FxHashMap;
+
+use crate::settings::{MidiSettings, NoteFilterSettings, NoteOffMatching};
+
+use super::{progress::LoadProgress, transpose::transpose_key};
+
+pub type TrackEventBatch = Delta<f64, Track<EventBatch<Event>>>;
+
+struct FilteredNote<P> {
+    start: f64,
+    position: P,
+    removed: bool,
+    serial: u64,
+    /// The kept note which started at the same time. The note is a duplicate if it
+    /// ends while that note is still playing.
+    duplicate_of: Option<u64>,
+    /// Number of notes waiting to know if they are duplicates of this note
+    duplicates: u32,
+}
+
+/// Decides which notes are removed by the note filters. The note offs are paired with
+/// their note ons using the note off matching, so the note off of a removed note is
+/// removed as well and the remaining notes keep their original lengths.
+///
+/// `P` identifies the note on event of a note, so that it can still be removed when the
+/// note turns out to be too short, or to be a duplicate.
+pub struct NoteFilterMatcher<P> {
+    settings: NoteFilterSettings,
+    matching: NoteOffMatching,
+    /// Minimum note length in seconds
+    min_length: f64,
+    /// Keyed by an id which is unique for each key, track and channel
+    unended: FxHashMap<u64, VecDeque<FilteredNote<P>>>,
+    next_serial: u64,
+    /// End time and number of undecided duplicates of the ended notes which still
+    /// have some, keyed by the serial of the note
+    ended_originals: FxHashMap<u64, (f64, u32)>,
+    /// The note ons of the possible duplicates which haven't ended yet, with their ids
+    undecided: BTreeMap<P, u64>,
+    removed: u64,
+}
+
+impl<P: Copy + Ord> NoteFilterMatcher<P> {
+    pub fn new(settings: &NoteFilterSettings, matching: NoteOffMatching) -> Self {
+        Self {
+            settings: settings.clone(),
+            matching,
+            min_length: settings.min_length.max(0.0) / 1000.0,
+            unended: FxHashMap::default(),
+            next_serial: 0,
+            ended_originals: FxHashMap::default(),
+            undecided: BTreeMap::new(),
+            removed: 0,
+        }
+    }
+
+    /// Minimum note length in seconds
+    pub fn min_length(&self) -> f64 {
+        self.min_length
+    }
+
+    /// The earliest note on which may still be removed as a duplicate, the events from
+    /// there on can't be passed on yet
+    pub fn oldest_undecided(&self) -> Option<P> {
+        self.undecided.keys().next().copied()
+    }
+
+    /// Keeps the possible duplicates whose note ons come before `position`, e.g. because
+    /// they can't be held back any longer
+    pub fn keep_undecided_before(&mut self, position: P) {
+        while let Some(entry) = self.undecided.first_entry() {
+            if *entry.key() >= position {
+                break;
+            }
+            let (position, id) = entry.remove_entry();
+
+            // A duplicate is in the same queue as its original
+            let Some(queue) = self.unended.get_mut(&id) else {
+                continue;
+            };
+            let Some(original) = queue
+                .iter_mut()
+                .find(|note| note.position == position)
+                .and_then(|note| note.duplicate_of.take())
+            else {
+                continue;
+            };
+
+            if let Some(original) = queue.iter_mut().find(|note| note.serial == original) {
+                original.duplicates -= 1;
+            } else if let Some((_, remaining)) = self.ended_originals.get_mut(&original) {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.ended_originals.remove(&original);
+                }
+            }
+        }
+    }
+
+    /// Returns whether the note on event should be kept. A note which starts at the same
+    /// time as a kept note is kept for now, and removed by [`Self::note_off`] if it ends
+    /// within that note.
+    pub fn note_on(&mut self, id: u64, key: u8, velocity: u8, time: f64, position: P) -> bool {
+        let queue = self.unended.entry(id).or_default();
+
+        let removed = velocity < self.settings.min_velocity
+            || (self.settings.limit_key_range && !self.settings.key_range.contains(&key));
+
+        let original = if !removed && self.settings.remove_duplicates {
+            queue
+                .iter_mut()
+                .find(|note| !note.removed && note.duplicate_of.is_none() && note.start == time)
+        } else {
+            None
+        };
+        let duplicate_of = original.map(|original| {
+            original.duplicates += 1;
+            original.serial
+        });
+        if duplicate_of.is_some() {
+            self.undecided.insert(position, id);
+        }
+
+        queue.push_back(FilteredNote {
+            start: time,
+            position,
+            removed,
+            serial: self.next_serial,
+            duplicate_of,
+            duplicates: 0,
+        });
+        self.next_serial += 1;
+        if removed {
+            self.removed += 1;
+        }
+
+        !removed
+    }
+
+    /// Returns whether the note off event should be kept. The note ons of the notes
+    /// which ended before reaching the minimum length, or within the note they
+    /// duplicate, are passed to `remove_note_on`.
+    pub fn note_off(&mut self, id: u64, time: f64, mut remove_note_on: impl FnMut(P)) -> bool {
+        let Some(queue) = self.unended.get_mut(&id) else {
+            return true;
+        };
+
+        let mut matched = false;
+        let mut keep = false;
+        loop {
+            let note = match self.matching {
+                NoteOffMatching::Fifo | NoteOffMatching::EndAll => queue.pop_front(),
+                NoteOffMatching::Lifo => queue.pop_back(),
+            };
+            let Some(note) = note else {
+                break;
+            };
+            matched = true;
+
+            if note.duplicates > 0 {
+                self.ended_originals
+                    .insert(note.serial, (time, note.duplicates));
+            }
+
+            let duplicate = note.duplicate_of.is_some_and(|original| {
+                self.undecided.remove(&note.position);
+                if let Some(original) = queue.iter_mut().find(|n| n.serial == original) {
+                    // Still playing, so it ends after this note
+                    original.duplicates -= 1;
+                    return true;
+                }
+                let Some((end, remaining)) = self.ended_originals.get_mut(&original) else {
+                    return false;
+                };
+                let within = *end >= time;
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.ended_originals.remove(&original);
+                }
+                within
+            });
+
+            if !note.removed {
+                if duplicate || time - note.start < self.min_length {
+                    remove_note_on(note.position);
+                    self.removed += 1;
+                } else {
+                    keep = true;
+                }
+            }
+
+            if self.matching != NoteOffMatching::EndAll {
+                break;
+            }
+        }
+
+        // Note offs without a note on don't affect anything, so they are left alone
+        keep || !matched
+    }
+
+    /// Returns the number of notes removed since the last call
+    pub fn take_removed(&mut self) -> u64 {
+        std::mem::take(&mut self.removed)
+    }
+}
+
+/// Combines the key, track and channel of a note into the id used by [`NoteFilterMatcher`]
+pub fn note_id(key: u8, track: u32, channel: u8) -> u64 {
+    ((track as u64 * 16 + channel as u64) << 8) | key as u64
+}
+
+/// An event batch of the merged MIDI, with the events removed by the note filters marked
+pub struct FilteredBatch {
+    batch: TrackEventBatch,
+    /// Follows the order of `iter_events`, `None` if no events were removed
+    removed: Option<Vec<bool>>,
+}
+
+impl FilteredBatch {
+    /// Whether each event of `iter_events` was kept by the filters, meant to be zipped
+    /// with it
+    pub fn kept_events(&self) -> impl '_ + Iterator<Item = bool> {
+        let removed = self.removed.as_deref();
+        (0..).map(move |i| removed.is_none_or(|removed| !removed[i]))
+    }
+
+    fn remove_event(&mut self, index: usize) {
+        let count = self.batch.count();
+        self.removed.get_or_insert_with(|| vec![false; count])[index] = true;
+    }
+}
+
+impl Deref for FilteredBatch {
+    type Target = TrackEventBatch;
+
+    fn deref(&self) -> &Self::Target {
+        &self.batch
+    }
+}
+
+/// Applies the note filters to the merged event batches of the sequential loaders.
+/// The batches are held back until every note they start is at least as long as the
+/// minimum length, or has ended, and until every possible duplicate they start has
+/// ended, so the visuals and the audio receive the same events. A possible duplicate
+/// which is still playing after [`MAX_DUPLICATE_HOLD_BACK`] is kept, so a long note
+/// can't hold back the rest of the MIDI.
+pub struct NoteFilter {
+    matcher: Option<NoteFilterMatcher<(u64, usize)>>,
+    transpose: i32,
+    pending: VecDeque<(f64, FilteredBatch)>,
+    /// Index of the first pending batch since the start of the MIDI
+    first_index: u64,
+    time: f64,
+    progress: Arc<LoadProgress>,
+}
+
+/// Seconds a batch is held back at most for the possible duplicates it starts
+const MAX_DUPLICATE_HOLD_BACK: f64 = 5.0;
+
+impl NoteFilter {
+    pub fn new(settings: &MidiSettings, progress: Arc<LoadProgress>) -> Self {
+        Self {
+            matcher: settings
+                .filters
+                .is_active()
+                .then(|| NoteFilterMatcher::new(&settings.filters, settings.note_off_matching)),
+            transpose: settings.transpose,
+            pending: VecDeque::new(),
+            first_index: 0,
+            time: 0.0,
+            progress,
+        }
+    }
+
+    /// Adds the next batch of the MIDI, and returns the batches which are ready to be
+    /// sent to the visuals and the audio
+    pub fn push(&mut self, batch: TrackEventBatch) -> impl '_ + Iterator<Item = FilteredBatch> {
+        self.time += batch.delta;
+
+        let mut batch = FilteredBatch {
+            batch,
+            removed: None,
+        };
+
+        let min_length = match self.matcher.as_mut() {
+            Some(matcher) => {
+                let index = self.first_index + self.pending.len() as u64;
+                let mut removed = Vec::new();
+
+                for (i, event) in batch.iter_events().enumerate() {
+                    let track = event.track;
+                    match event.as_event() {
+                        Event::NoteOn(e) => {
+                            // Notes transposed out of range are skipped by the loaders anyway
+                            let Some(key) = transpose_key(e.key, self.transpose) else {
+                                continue;
+                            };
+                            let id = note_id(key, track, e.channel);
+                            if !matcher.note_on(id, key, e.velocity, self.time, (index, i)) {
+                                removed.push((index, i));
+                            }
+                        }
+                        Event::NoteOff(e) => {
+                            let Some(key) = transpose_key(e.key, self.transpose) else {
+                                continue;
+                            };
+                            let id = note_id(key, track, e.channel);
+                            let keep = matcher.note_off(id, self.time, |on| removed.push(on));
+                            if !keep {
+                                removed.push((index, i));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                for (batch_index, event_index) in removed {
+                    if batch_index == index {
+                        batch.remove_event(event_index);
+                    } else {
+                        let pending = (batch_index - self.first_index) as usize;
+                        self.pending[pending].1.remove_event(event_index);
+                    }
+                }
+
+                self.progress.add_removed_notes(matcher.take_removed());
+                matcher.min_length()
+            }
+            None => 0.0,
+        };
+
+        self.pending.push_back((self.time, batch));
+
+        std::iter::from_fn(move || {
+            let time = self.pending.front()?.0;
+            if time + min_length > self.time {
+                return None;
+            }
+
+            if let Some(matcher) = self.matcher.as_mut() {
+                if time + MAX_DUPLICATE_HOLD_BACK <= self.time {
+                    matcher.keep_undecided_before((self.first_index + 1, 0));
+                }
+                let undecided = matcher.oldest_undecided();
+                if undecided.is_some_and(|(index, _)| index <= self.first_index) {
+                    return None;
+                }
+            }
+
+            self.first_index += 1;
+            self.pending.pop_front().map(|(_, batch)| batch)
+        })
+    }
+
+    /// Returns the remaining batches once the end of the MIDI is reached
+    pub fn finish(self) -> impl Iterator<Item = FilteredBatch> {
+        self.pending.into_iter().map(|(_, batch)| batch)
+    }
+}
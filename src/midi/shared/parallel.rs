@@ -0,0 +1,373 @@
+use std::fmt::Debug;
+
+use midi_toolkit::{
+    events::Event,
+    io::{DiskReader, MIDIFile as TKMIDIFile},
+    pipe,
+    sequence::{event::Delta, unwrap_items},
+};
+use rayon::prelude::*;
+
+use crate::settings::{MidiSettings, NoteFilterSettings, NoteOffMatching};
+
+use super::{
+    audio::{AudioNoteMatcher, EncodedAudioEvent, FlatAudio, RawAudioBlock},
+    grid::{BeatGrid, BeatGridBuilder},
+    note_filter::NoteFilterMatcher,
+    progress::LoadProgress,
+    tempo::TempoMap,
+    transpose::transpose_key,
+};
+
+/// How many events a track parser processes between progress updates
+const PROGRESS_INTERVAL: u64 = 1 << 16;
+
+/// A note event stored compactly while the tracks are being parsed.
+/// The top bit of `channel_track` marks note on events.
+#[derive(Clone, Copy)]
+struct RawKeyEvent {
+    tick: u64,
+    channel_track: u32,
+    velocity: u8,
+}
+
+const NOTE_ON_FLAG: u32 = 1 << 31;
+
+/// A note event of a single key, with the time already converted to integer ticks
+/// of the loader's resolution.
+pub enum KeyNoteEvent {
+    On {
+        time: i32,
+        channel_track: i32,
+        velocity: u8,
+    },
+    Off {
+        time: i32,
+        channel_track: i32,
+    },
+}
+
+struct ParsedTrack {
+    keys: Vec<Vec<RawKeyEvent>>,
+    audio: Vec<(u64, EncodedAudioEvent)>,
+    tempos: Vec<(u64, u32)>,
+    /// Tick, numerator and the power of two of the denominator
+    time_signatures: Vec<(u64, u8, u8)>,
+    end_tick: u64,
+    note_count: u64,
+}
+
+impl ParsedTrack {
+    /// Removes the notes rejected by the note filters from both the key and the audio
+    /// events, and returns how many notes were removed. The notes are matched within
+    /// each key, which gives the same result as filtering the merged MIDI, because the
+    /// filters only compare notes of the same key, track and channel.
+    fn apply_filters(
+        &mut self,
+        filters: &NoteFilterSettings,
+        matching: NoteOffMatching,
+        tempo_map: &TempoMap,
+    ) -> u64 {
+        let mut matcher = NoteFilterMatcher::new(filters, matching);
+
+        let kept: Vec<Vec<bool>> = self
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(key, events)| {
+                let mut kept = vec![true; events.len()];
+                for (i, event) in events.iter().enumerate() {
+                    let time = tempo_map.seconds_at(event.tick as f64);
+                    let id = (((event.channel_track & !NOTE_ON_FLAG) as u64) << 8) | key as u64;
+                    let keep = if event.channel_track & NOTE_ON_FLAG != 0 {
+                        matcher.note_on(id, key as u8, event.velocity, time, i)
+                    } else {
+                        matcher.note_off(id, time, |on| kept[on] = false)
+                    };
+                    kept[i] = keep;
+                }
+                kept
+            })
+            .collect();
+
+        let removed = matcher.take_removed();
+        if removed == 0 {
+            return 0;
+        }
+
+        // Every note event has both a key event and an audio event, in the same order
+        let mut key_positions = vec![0; kept.len()];
+        self.audio.retain(|(_, event)| match event.note_key() {
+            Some(key) => {
+                let position = &mut key_positions[key as usize];
+                *position += 1;
+                kept[key as usize][*position - 1]
+            }
+            None => true,
+        });
+
+        for (events, kept) in self.keys.iter_mut().zip(kept) {
+            let mut kept = kept.into_iter();
+            events.retain(|_| kept.next().unwrap_or(true));
+        }
+
+        self.note_count -= removed;
+        removed
+    }
+}
+
+/// A MIDI file whose tracks were parsed concurrently. The note events are grouped by key,
+/// so the per-key trees can also be built in parallel afterwards.
+pub struct ParallelParsedMIDI {
+    /// Indexed by `[key][track]`, each list is sorted by time
+    keys: Vec<Vec<Vec<RawKeyEvent>>>,
+    /// Indexed by track, each list is sorted by time
+    audio: Vec<Vec<(u64, EncodedAudioEvent)>>,
+    tempo_map: TempoMap,
+    beat_grid: BeatGrid,
+    length: f64,
+    note_count: u64,
+}
+
+impl ParallelParsedMIDI {
+    /// Parses all the tracks of the MIDI concurrently, and applies the transposition and
+    /// the note filters of `settings`. Returns `None` if the loading was cancelled
+    /// through `progress`.
+    pub fn parse(
+        midi: &TKMIDIFile<DiskReader>,
+        settings: &MidiSettings,
+        progress: &LoadProgress,
+    ) -> Option<Self> {
+        let tracks: Vec<_> = midi.iter_all_tracks().collect();
+
+        let mut parsed = tracks
+            .into_par_iter()
+            .enumerate()
+            .map(|(track, events)| {
+                Self::parse_track(track as u32, events, settings.transpose, progress)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let tempos: Vec<_> = parsed
+            .iter()
+            .flat_map(|track| track.tempos.iter())
+            .map(|&(tick, tempo)| (tick as f64, tempo))
+            .collect();
+
+        let mut grid = BeatGridBuilder::new(midi.ppq());
+        for track in parsed.iter() {
+            for &(tick, tempo) in track.tempos.iter() {
+                grid.add_tempo(tick as f64, tempo);
+            }
+            for &(tick, numerator, denominator) in track.time_signatures.iter() {
+                grid.add_time_signature(tick as f64, numerator, denominator);
+            }
+        }
+
+        let tempo_map = TempoMap::from_tempos(tempos, midi.ppq());
+
+        if settings.filters.is_active() {
+            let removed: u64 = parsed
+                .par_iter_mut()
+                .map(|track| {
+                    track.apply_filters(&settings.filters, settings.note_off_matching, &tempo_map)
+                })
+                .sum();
+            progress.add_removed_notes(removed);
+        }
+
+        let mut keys: Vec<Vec<Vec<RawKeyEvent>>> = (0..256).map(|_| Vec::new()).collect();
+        let mut audio = Vec::with_capacity(parsed.len());
+        let mut end_tick = 0;
+        let mut note_count = 0;
+
+        for track in parsed {
+            for (key, events) in track.keys.into_iter().enumerate() {
+                if events.iter().any(|e| e.channel_track & NOTE_ON_FLAG != 0) {
+                    progress.add_key(key as u8);
+                }
+                if !events.is_empty() {
+                    keys[key].push(events);
+                }
+            }
+            audio.push(track.audio);
+            end_tick = end_tick.max(track.end_tick);
+            note_count += track.note_count;
+        }
+
+        let length = tempo_map.seconds_at(end_tick as f64);
+        let beat_grid = grid.build(Some(end_tick as f64));
+
+        Some(ParallelParsedMIDI {
+            keys,
+            audio,
+            tempo_map,
+            beat_grid,
+            length,
+            note_count,
+        })
+    }
+
+    fn parse_track<Err: Debug>(
+        track: u32,
+        events: impl Iterator<Item = Result<Delta<u64, Event>, Err>>,
+        transpose: i32,
+        progress: &LoadProgress,
+    ) -> Option<ParsedTrack> {
+        let mut parsed = ParsedTrack {
+            keys: (0..256).map(|_| Vec::new()).collect(),
+            audio: Vec::new(),
+            tempos: Vec::new(),
+            time_signatures: Vec::new(),
+            end_tick: 0,
+            note_count: 0,
+        };
+
+        let mut tick = 0;
+        let mut processed = 0;
+
+        for event in pipe!(events |> unwrap_items()) {
+            tick += event.delta;
+
+            match &event.event {
+                Event::NoteOn(e) => {
+                    if let Some(key) = transpose_key(e.key, transpose) {
+                        let channel_track = e.channel as u32 + track * 16;
+                        parsed.keys[key as usize].push(RawKeyEvent {
+                            tick,
+                            channel_track: channel_track | NOTE_ON_FLAG,
+                            velocity: e.velocity,
+                        });
+                        parsed.note_count += 1;
+                    }
+                }
+                Event::NoteOff(e) => {
+                    if let Some(key) = transpose_key(e.key, transpose) {
+                        let channel_track = e.channel as u32 + track * 16;
+                        parsed.keys[key as usize].push(RawKeyEvent {
+                            tick,
+                            channel_track,
+                            velocity: 0,
+                        });
+                    }
+                }
+                Event::Tempo(e) => {
+                    parsed.tempos.push((tick, e.tempo));
+                }
+                Event::TimeSignature(e) => {
+                    parsed
+                        .time_signatures
+                        .push((tick, e.numerator, e.denominator));
+                }
+                _ => {}
+            }
+
+            if let Some(encoded) = EncodedAudioEvent::encode(&event.event, transpose) {
+                parsed.audio.push((tick, encoded));
+            }
+
+            processed += 1;
+            if processed == PROGRESS_INTERVAL {
+                progress.add_events(processed);
+                processed = 0;
+                if progress.is_cancelled() {
+                    return None;
+                }
+            }
+        }
+        progress.add_events(processed);
+
+        parsed.end_tick = tick;
+        Some(parsed)
+    }
+
+    /// Length of the MIDI in seconds
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    pub fn note_count(&self) -> u64 {
+        self.note_count
+    }
+
+    pub fn beat_grid(&self) -> &BeatGrid {
+        &self.beat_grid
+    }
+
+    /// Builds the data of every key with `build_key` in parallel, while also building
+    /// the audio data. `build_key` receives the key and its events, sorted by time, with
+    /// simultaneous events kept in track order. The note offs of the audio are
+    /// processed with `matching`, like the sequential loaders do.
+    pub fn build<T: Send>(
+        self,
+        ticks_per_second: u32,
+        matching: NoteOffMatching,
+        build_key: impl Fn(u8, &mut dyn Iterator<Item = KeyNoteEvent>) -> T + Sync,
+    ) -> (Vec<T>, FlatAudio) {
+        let ParallelParsedMIDI {
+            keys,
+            audio,
+            tempo_map,
+            ..
+        } = self;
+
+        rayon::join(
+            || {
+                keys.into_par_iter()
+                    .enumerate()
+                    .map(|(key, tracks)| {
+                        let mut events: Vec<RawKeyEvent> = tracks.into_iter().flatten().collect();
+                        events.sort_by_key(|e| e.tick);
+
+                        let mut iter = events.into_iter().map(|e| {
+                            let time = (tempo_map.seconds_at(e.tick as f64)
+                                * ticks_per_second as f64)
+                                as i32;
+                            let channel_track = (e.channel_track & !NOTE_ON_FLAG) as i32;
+                            if e.channel_track & NOTE_ON_FLAG != 0 {
+                                KeyNoteEvent::On {
+                                    time,
+                                    channel_track,
+                                    velocity: e.velocity,
+                                }
+                            } else {
+                                KeyNoteEvent::Off {
+                                    time,
+                                    channel_track,
+                                }
+                            }
+                        });
+
+                        build_key(key as u8, &mut iter)
+                    })
+                    .collect()
+            },
+            || {
+                let mut events: Vec<_> = audio
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(track, events)| {
+                        events
+                            .into_iter()
+                            .map(move |(tick, event)| (tick, track as u32, event))
+                    })
+                    .collect();
+                events.par_sort_by_key(|(tick, _, _)| *tick);
+
+                let mut matcher = AudioNoteMatcher::new(matching);
+                let mut block_events = Vec::new();
+
+                let raw_blocks = events.chunk_by(|a, b| a.0 == b.0).map(|chunk| {
+                    for &(_, track, event) in chunk {
+                        matcher.process(track, event, |e| block_events.push(e));
+                    }
+                    RawAudioBlock::from_encoded_events(
+                        tempo_map.seconds_at(chunk[0].0 as f64),
+                        block_events.drain(..),
+                    )
+                });
+                FlatAudio::build_blocks(raw_blocks)
+            },
+        )
+    }
+}
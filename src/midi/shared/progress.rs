@@ -0,0 +1,125 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Shared progress counters for a MIDI loader. The parser threads write into it
+/// and the GUI reads it to display the loading progress, or sets the cancel flag.
+#[derive(Debug, Default)]
+pub struct LoadProgress {
+    bytes_total: AtomicU64,
+    bytes_read: AtomicU64,
+    events_processed: AtomicU64,
+    notes_removed: AtomicU64,
+    /// One bit per key which has at least one note
+    used_keys: [AtomicU64; 4],
+    cancelled: AtomicBool,
+}
+
+impl LoadProgress {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn set_bytes_total(&self, bytes: u64) {
+        self.bytes_total.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn bytes_total(&self) -> u64 {
+        self.bytes_total.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+            .load(Ordering::Relaxed)
+            .min(self.bytes_total())
+    }
+
+    pub fn add_events(&self, count: u64) {
+        self.events_processed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn events_processed(&self) -> u64 {
+        self.events_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn add_removed_notes(&self, count: u64) {
+        self.notes_removed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Number of notes removed by the note filters so far
+    pub fn removed_notes(&self) -> u64 {
+        self.notes_removed.load(Ordering::Relaxed)
+    }
+
+    /// Marks a key as used by the notes of the MIDI
+    pub fn add_key(&self, key: u8) {
+        let bits = &self.used_keys[key as usize / 64];
+        let mask = 1 << (key % 64);
+        // Most notes are on keys which were already seen, so avoid the write
+        if bits.load(Ordering::Relaxed) & mask == 0 {
+            bits.fetch_or(mask, Ordering::Relaxed);
+        }
+    }
+
+    /// The range between the lowest and the highest key used so far, or `None` if no
+    /// notes were parsed yet
+    pub fn key_range(&self) -> Option<RangeInclusive<u8>> {
+        let bits = self.used_keys.each_ref().map(|b| b.load(Ordering::Relaxed));
+        let first = bits.iter().position(|b| *b != 0)?;
+        let last = bits.iter().rposition(|b| *b != 0)?;
+        let start = first * 64 + bits[first].trailing_zeros() as usize;
+        let end = last * 64 + 63 - bits[last].leading_zeros() as usize;
+        Some(start as u8..=end as u8)
+    }
+
+    /// Returns the read progress of the file in the range `0.0..=1.0`
+    pub fn fraction(&self) -> f32 {
+        let total = self.bytes_total();
+        if total == 0 {
+            0.0
+        } else {
+            (self.bytes_read() as f64 / total as f64) as f32
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A reader wrapper which reports the number of bytes read into a [`LoadProgress`].
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<LoadProgress>,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, progress: Arc<LoadProgress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress
+            .bytes_read
+            .fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,73 @@
+/// Tempo of a MIDI before its first tempo event, in microseconds per beat
+pub const DEFAULT_TEMPO: u32 = 500000;
+
+struct TempoChange {
+    tick: f64,
+    seconds: f64,
+    seconds_per_tick: f64,
+}
+
+/// Converts MIDI ticks into seconds and back using the tempo events of the file.
+/// The tempo changes can be added while the MIDI is read, or all at once.
+pub struct TempoMap {
+    ppq: f64,
+    /// Starts with the default tempo at tick 0
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    pub fn new(ppq: u16) -> Self {
+        let ppq = ppq.max(1) as f64;
+        Self {
+            ppq,
+            changes: vec![TempoChange {
+                tick: 0.0,
+                seconds: 0.0,
+                seconds_per_tick: DEFAULT_TEMPO as f64 / 1_000_000.0 / ppq,
+            }],
+        }
+    }
+
+    /// Builds the map from the tempo events of all the tracks, in any order
+    pub fn from_tempos(mut tempos: Vec<(f64, u32)>, ppq: u16) -> Self {
+        // Stable, so the last of several simultaneous events is the one used
+        tempos.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut map = Self::new(ppq);
+        for (tick, tempo) in tempos {
+            map.add_tempo(tick, tempo);
+        }
+        map
+    }
+
+    /// Adds a tempo change, which can't be before the previously added one
+    pub fn add_tempo(&mut self, tick: f64, tempo: u32) {
+        let seconds = self.seconds_at(tick);
+        let seconds_per_tick = tempo as f64 / 1_000_000.0 / self.ppq;
+
+        match self.changes.last_mut() {
+            Some(last) if last.tick >= tick => last.seconds_per_tick = seconds_per_tick,
+            _ => self.changes.push(TempoChange {
+                tick,
+                seconds,
+                seconds_per_tick,
+            }),
+        }
+    }
+
+    pub fn seconds_at(&self, tick: f64) -> f64 {
+        let i = self.changes.partition_point(|c| c.tick <= tick).max(1) - 1;
+        let change = &self.changes[i];
+        change.seconds + (tick - change.tick) * change.seconds_per_tick
+    }
+
+    pub fn ticks_at(&self, seconds: f64) -> f64 {
+        let i = self
+            .changes
+            .partition_point(|c| c.seconds <= seconds)
+            .max(1)
+            - 1;
+        let change = &self.changes[i];
+        change.tick + (seconds - change.seconds) / change.seconds_per_tick
+    }
+}
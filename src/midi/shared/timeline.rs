@@ -0,0 +1,197 @@
+use std::ops::RangeInclusive;
+
+/// Number of timeline buckets per second of the MIDI
+const BUCKETS_PER_SECOND: f64 = 100.0;
+
+/// The window used for calculating the notes per second
+const NPS_WINDOW: f64 = 1.0;
+
+#[derive(Clone, Copy)]
+struct KeyTimelineBucket {
+    bucket: u32,
+    starts: u32,
+    ends: u32,
+}
+
+/// Records when the notes of a single key start and end while its tree is being built.
+/// Only the buckets which contain events are stored, as the events arrive in order.
+pub struct KeyTimeline {
+    ticks_per_bucket: f64,
+    buckets: Vec<KeyTimelineBucket>,
+}
+
+impl KeyTimeline {
+    pub fn new(ticks_per_second: u32) -> KeyTimeline {
+        KeyTimeline {
+            ticks_per_bucket: ticks_per_second as f64 / BUCKETS_PER_SECOND,
+            buckets: Vec::new(),
+        }
+    }
+
+    fn bucket_mut(&mut self, time: i32) -> &mut KeyTimelineBucket {
+        let bucket = (time.max(0) as f64 / self.ticks_per_bucket) as u32;
+
+        if self.buckets.last().is_none_or(|last| last.bucket != bucket) {
+            self.buckets.push(KeyTimelineBucket {
+                bucket,
+                starts: 0,
+                ends: 0,
+            });
+        }
+
+        self.buckets.last_mut().unwrap()
+    }
+
+    pub fn note_start(&mut self, time: i32) {
+        self.bucket_mut(time).starts += 1;
+    }
+
+    pub fn note_end(&mut self, time: i32) {
+        self.notes_end(time, 1);
+    }
+
+    pub fn notes_end(&mut self, time: i32, count: u32) {
+        if count > 0 {
+            self.bucket_mut(time).ends += count;
+        }
+    }
+}
+
+/// The note density and polyphony of the whole MIDI over time, merged from the
+/// timelines of all the keys. This makes the statistics independent from the frame
+/// rate, and they stay correct when seeking.
+pub struct NoteTimeline {
+    /// Number of notes started up to and including each bucket
+    passed: Vec<u64>,
+    /// Number of notes which were playing at any point of each bucket
+    polyphony: Vec<u64>,
+}
+
+impl NoteTimeline {
+    pub fn from_keys<'a>(keys: impl Iterator<Item = &'a KeyTimeline>, length: f64) -> Self {
+        let bucket_count = (length.max(0.0) * BUCKETS_PER_SECOND) as usize + 1;
+
+        let mut starts = vec![0u64; bucket_count];
+        let mut ends = vec![0u64; bucket_count];
+
+        for key in keys {
+            for bucket in key.buckets.iter() {
+                let index = (bucket.bucket as usize).min(bucket_count - 1);
+                starts[index] += bucket.starts as u64;
+                ends[index] += bucket.ends as u64;
+            }
+        }
+
+        let mut passed = Vec::with_capacity(bucket_count);
+        let mut polyphony = Vec::with_capacity(bucket_count);
+
+        let mut started = 0;
+        let mut ended = 0;
+        for (starts, ends) in starts.into_iter().zip(ends) {
+            started += starts;
+            // Notes ending inside of this bucket were still playing during it
+            polyphony.push(started - ended);
+            ended += ends;
+            passed.push(started);
+        }
+
+        NoteTimeline { passed, polyphony }
+    }
+
+    /// Merges the timelines of several MIDIs played together, each of them starting
+    /// the given number of seconds later
+    pub fn merge(timelines: &[(&NoteTimeline, f64)]) -> Self {
+        let length = timelines
+            .iter()
+            .map(|(timeline, offset)| timeline.passed.len() as f64 / BUCKETS_PER_SECOND + offset)
+            .fold(0.0, f64::max);
+        let bucket_count = (length * BUCKETS_PER_SECOND) as usize + 1;
+
+        let (passed, polyphony) = (0..bucket_count)
+            .map(|bucket| {
+                let time = bucket as f64 / BUCKETS_PER_SECOND;
+                timelines
+                    .iter()
+                    .map(|(timeline, offset)| {
+                        (
+                            timeline.passed_notes_at(time - offset),
+                            timeline.polyphony_at(time - offset),
+                        )
+                    })
+                    .fold((0, 0), |(a, b), (c, d)| (a + c, b + d))
+            })
+            .unzip();
+
+        NoteTimeline { passed, polyphony }
+    }
+
+    fn bucket_at(&self, time: f64) -> Option<usize> {
+        if time < 0.0 || self.passed.is_empty() {
+            None
+        } else {
+            Some(((time * BUCKETS_PER_SECOND) as usize).min(self.passed.len() - 1))
+        }
+    }
+
+    /// Number of notes that started before or at `time`, in seconds
+    pub fn passed_notes_at(&self, time: f64) -> u64 {
+        self.bucket_at(time).map_or(0, |i| self.passed[i])
+    }
+
+    /// Number of notes that started during the second before `time`
+    pub fn nps_at(&self, time: f64) -> u64 {
+        self.passed_notes_at(time) - self.passed_notes_at(time - NPS_WINDOW)
+    }
+
+    /// Splits `range` into `bins` equal sections, and returns the average notes per
+    /// second of each of them.
+    pub fn density_histogram(&self, range: RangeInclusive<f64>, bins: usize) -> Vec<f64> {
+        let (start, end) = (*range.start(), *range.end());
+        let bin_length = (end - start) / bins as f64;
+        if bin_length <= 0.0 {
+            return vec![0.0; bins];
+        }
+
+        (0..bins)
+            .map(|i| {
+                let bin_start = start + bin_length * i as f64;
+                let notes =
+                    self.passed_notes_at(bin_start + bin_length) - self.passed_notes_at(bin_start);
+                notes as f64 / bin_length
+            })
+            .collect()
+    }
+
+    /// The highest notes per second of the whole MIDI, and the time it was reached at
+    pub fn peak_nps(&self) -> (u64, f64) {
+        let window = (NPS_WINDOW * BUCKETS_PER_SECOND) as usize;
+        let (nps, bucket) = (0..self.passed.len())
+            .map(|i| {
+                let before = i.checked_sub(window).map_or(0, |j| self.passed[j]);
+                (self.passed[i] - before, i)
+            })
+            .max_by_key(|(nps, _)| *nps)
+            .unwrap_or_default();
+        (nps, bucket as f64 / BUCKETS_PER_SECOND)
+    }
+
+    /// The highest polyphony of the whole MIDI, and the time it was reached at
+    pub fn peak_polyphony(&self) -> (u64, f64) {
+        let (polyphony, bucket) = self
+            .polyphony
+            .iter()
+            .enumerate()
+            .map(|(i, polyphony)| (*polyphony, i))
+            .max_by_key(|(polyphony, _)| *polyphony)
+            .unwrap_or_default();
+        (polyphony, bucket as f64 / BUCKETS_PER_SECOND)
+    }
+
+    /// Number of notes playing at `time`, in seconds
+    pub fn polyphony_at(&self, time: f64) -> u64 {
+        if time > self.passed.len() as f64 / BUCKETS_PER_SECOND {
+            return 0;
+        }
+        self.bucket_at(time).map_or(0, |i| self.polyphony[i])
+    }
+}
@@ -1,5 +1,9 @@
 #![allow(dead_code)]
 
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard,
+};
 use std::time::Instant;
 use time::Duration;
 
@@ -33,104 +37,213 @@ impl TimerState {
     fn is_paused(&self) -> bool {
         matches!(self, TimerState::Paused { .. })
     }
+
+    /// The same state, `offset` behind
+    fn shifted(&self, offset: Duration) -> TimerState {
+        match self {
+            TimerState::Running {
+                continue_time,
+                time_offset,
+            } => TimerState::Running {
+                continue_time: *continue_time,
+                time_offset: *time_offset - offset,
+            },
+            TimerState::Paused { time_offset } => TimerState::Paused {
+                time_offset: *time_offset - offset,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct TimeKeeper {
+struct Listener {
+    sender: crossbeam_channel::Sender<NotifySignal>,
+    /// The keeper the listener was created from, which sets its offset
+    keeper: u64,
+    offset: Duration,
+}
+
+/// The state shared by the keepers of a timeline
+#[derive(Debug)]
+struct Timeline {
     current_state: TimerState,
-    listeners: Vec<crossbeam_channel::Sender<NotifySignal>>,
+    listeners: Vec<Listener>,
+}
+
+impl Timeline {
+    /// Sends the new state to the listeners, or only to the ones of `keeper`
+    fn notify_listeners(&mut self, seeked: bool, keeper: Option<u64>) {
+        let mut i = 0;
+        while i < self.listeners.len() {
+            let listener = &self.listeners[i];
+            if keeper.is_some_and(|keeper| keeper != listener.keeper) {
+                i += 1;
+                continue;
+            }
+
+            let signal = NotifySignal {
+                new_state: self.current_state.shifted(listener.offset),
+                has_seeked: seeked,
+            };
+
+            match listener.sender.send(signal) {
+                Ok(_) => i += 1,
+                Err(_e) => {
+                    // The listener has been dropped, so we remove the sender
+                    self.listeners.remove(i);
+                }
+            }
+        }
+    }
+}
+
+static NEXT_KEEPER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The playback time of a MIDI. Several keepers can share a timeline with
+/// [`TimeKeeper::join`], each of them running a fixed offset behind it, so pausing
+/// or seeking any of them moves all of them together.
+#[derive(Debug)]
+pub struct TimeKeeper {
+    timeline: Arc<Mutex<Timeline>>,
+    id: u64,
+    /// Time this keeper runs behind the timeline
+    offset: Duration,
 }
 
 impl TimeKeeper {
     pub fn new(start_delay: f64) -> Self {
         let start_delay = Duration::seconds_f64(start_delay);
         Self {
-            current_state: TimerState::Paused {
-                time_offset: -start_delay,
-            },
-            listeners: Vec::new(),
+            timeline: Arc::new(Mutex::new(Timeline {
+                current_state: TimerState::Paused {
+                    time_offset: -start_delay,
+                },
+                listeners: Vec::new(),
+            })),
+            id: NEXT_KEEPER_ID.fetch_add(1, Ordering::Relaxed),
+            offset: Duration::ZERO,
         }
     }
 
+    fn timeline(&self) -> MutexGuard<'_, Timeline> {
+        self.timeline.lock().unwrap()
+    }
+
     pub fn get_time(&self) -> Duration {
-        self.current_state.get_time()
+        self.timeline().current_state.get_time() - self.offset
     }
 
     pub fn is_paused(&self) -> bool {
-        self.current_state.is_paused()
+        self.timeline().current_state.is_paused()
     }
 
     pub fn get_listener(&mut self) -> TimeListener {
         let (snd, rcv) = crossbeam_channel::unbounded();
-        self.listeners.push(snd);
+        let mut timeline = self.timeline();
+        timeline.listeners.push(Listener {
+            sender: snd,
+            keeper: self.id,
+            offset: self.offset,
+        });
         TimeListener {
             reciever: rcv,
-            current: self.current_state.clone(),
+            current: timeline.current_state.shifted(self.offset),
         }
     }
 
-    fn notify_listeners(&mut self, seeked: bool) {
-        let mut i = 0;
-        while i < self.listeners.len() {
-            let listener = &mut self.listeners[i];
-
-            let signal = NotifySignal {
-                new_state: self.current_state.clone(),
-                has_seeked: seeked,
-            };
-
-            match listener.send(signal) {
-                Ok(_) => i += 1,
-                Err(_e) => {
-                    // The listener has been dropped, so we remove the sender
-                    self.listeners.remove(i);
-                }
+    /// Changes the timeline state, keeping the current time
+    fn set_state(&mut self, paused: bool) {
+        let mut timeline = self.timeline();
+        let now = timeline.current_state.get_time();
+        timeline.current_state = if paused {
+            TimerState::Paused { time_offset: now }
+        } else {
+            TimerState::Running {
+                continue_time: Instant::now(),
+                time_offset: now,
             }
-        }
+        };
+        timeline.notify_listeners(false, None);
     }
 
     pub fn toggle_pause(&mut self) {
-        let now = self.get_time();
-        match self.current_state {
-            TimerState::Paused { .. } => {
-                self.current_state = TimerState::Running {
-                    continue_time: Instant::now(),
-                    time_offset: now,
-                };
-            }
-            TimerState::Running { .. } => {
-                self.current_state = TimerState::Paused { time_offset: now };
-            }
-        }
-
-        self.notify_listeners(false);
+        let paused = self.is_paused();
+        self.set_state(!paused);
     }
 
     pub fn pause(&mut self) {
-        let now = self.get_time();
-        self.current_state = TimerState::Paused { time_offset: now };
-        self.notify_listeners(false);
+        self.set_state(true);
     }
 
     pub fn play(&mut self) {
-        let now = self.get_time();
-        self.current_state = TimerState::Running {
-            continue_time: Instant::now(),
-            time_offset: now,
-        };
-        self.notify_listeners(false);
+        self.set_state(false);
     }
 
     pub fn seek(&mut self, time: Duration) {
-        if self.current_state.is_paused() {
-            self.current_state = TimerState::Paused { time_offset: time };
+        let mut timeline = self.timeline();
+        let time_offset = time + self.offset;
+        timeline.current_state = if timeline.current_state.is_paused() {
+            TimerState::Paused { time_offset }
         } else {
-            self.current_state = TimerState::Running {
+            TimerState::Running {
                 continue_time: Instant::now(),
-                time_offset: time,
-            };
+                time_offset,
+            }
+        };
+        timeline.notify_listeners(true, None);
+    }
+
+    /// Moves this keeper and its listeners onto the timeline of `leader`, running
+    /// `offset` behind it
+    pub fn join(&mut self, leader: &TimeKeeper, offset: Duration) {
+        if Arc::ptr_eq(&self.timeline, &leader.timeline) {
+            self.set_offset(leader.offset + offset);
+            return;
+        }
+
+        let previous = self.get_time();
+        let listeners = std::mem::take(&mut self.timeline().listeners);
+        self.timeline = leader.timeline.clone();
+        self.offset = leader.offset + offset;
+
+        let mut timeline = self.timeline();
+        timeline
+            .listeners
+            .extend(listeners.into_iter().map(|listener| Listener {
+                offset: self.offset,
+                ..listener
+            }));
+
+        // Joining at the same time is not a seek
+        let seeked = (timeline.current_state.get_time() - self.offset - previous).abs()
+            > Duration::milliseconds(10);
+        timeline.notify_listeners(seeked, Some(self.id));
+    }
+
+    /// Changes the time this keeper runs behind its timeline, which seeks its
+    /// listeners without moving the other keepers of the timeline
+    pub fn set_offset(&mut self, offset: Duration) {
+        self.offset = offset;
+
+        let id = self.id;
+        let mut timeline = self.timeline();
+        for listener in timeline.listeners.iter_mut() {
+            if listener.keeper == id {
+                listener.offset = offset;
+            }
+        }
+        timeline.notify_listeners(true, Some(id));
+    }
+}
+
+impl Drop for TimeKeeper {
+    fn drop(&mut self) {
+        // Dropping the senders stops the listeners, even while the timeline is still
+        // used by other keepers
+        let id = self.id;
+        if let Ok(mut timeline) = self.timeline.lock() {
+            timeline.listeners.retain(|listener| listener.keeper != id);
         }
-        self.notify_listeners(true);
     }
 }
 
@@ -0,0 +1,23 @@
+/// Shifts `key` by `semitones`, returning `None` if it is pushed out of the 0-255 key space
+pub fn transpose_key(key: u8, semitones: i32) -> Option<u8> {
+    u8::try_from(key as i32 + semitones).ok()
+}
+
+/// Like [`transpose_key`], but also returns `None` above key 127, the highest one
+/// which can be written into a MIDI file
+pub fn transpose_midi_key(key: u8, semitones: i32) -> Option<u8> {
+    transpose_key(key, semitones).filter(|key| *key <= 127)
+}
+
+/// Shifts the key of a note event in the `u32` format sent to the synths. Returns `None`
+/// if the note is pushed out of the key space and should be dropped.
+pub fn transpose_audio_event(event: u32, semitones: i32) -> Option<u32> {
+    match event & 0xF0 {
+        // Note off, note on and polyphonic key pressure
+        0x80 | 0x90 | 0xA0 => {
+            let key = transpose_key((event >> 8) as u8, semitones)?;
+            Some((event & !0xFF00) | ((key as u32) << 8))
+        }
+        _ => Some(event),
+    }
+}
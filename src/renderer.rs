@@ -1,3 +1,4 @@
+pub mod devices;
 pub mod swapchain;
 
 use std::sync::Arc;
@@ -6,8 +7,8 @@ use egui_winit_vulkano::{Gui, GuiConfig};
 use raw_window_handle::RawDisplayHandle;
 use vulkano::{
     device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures,
-        Queue, QueueCreateInfo, QueueFlags,
+        Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures, Queue, QueueCreateInfo,
+        QueueFlags,
     },
     format::Format,
     instance::{Instance, InstanceCreateInfo, InstanceExtensions},
@@ -30,7 +31,10 @@ use crate::{
     state::WasabiState,
 };
 
-use self::swapchain::ManagedSwapchain;
+use self::{
+    devices::{select_gpu, GpuInfo},
+    swapchain::ManagedSwapchain,
+};
 
 pub struct Renderer {
     _instance: Arc<Instance>,
@@ -74,7 +78,7 @@ impl Renderer {
         let surface = Surface::from_window(instance.clone(), window.clone())
             .expect("Failed to create surface");
 
-        // Get most performant physical device (device with most memory)
+        // Get the GPU picked in the settings, or the most performant one
         let device_extensions = DeviceExtensions {
             khr_swapchain: true,
             ..DeviceExtensions::empty()
@@ -84,10 +88,11 @@ impl Renderer {
             ..DeviceFeatures::empty()
         };
 
-        let (physical_device, queue_family_index) = instance
+        let candidates = instance
             .enumerate_physical_devices()
             .unwrap()
             .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .filter(|p| p.supported_features().contains(&features))
             .filter_map(|p| {
                 p.queue_family_properties()
                     .iter()
@@ -97,14 +102,10 @@ impl Renderer {
                             && p.surface_support(i as u32, &surface).unwrap_or(false)
                     })
                     .map(|i| (p, i as u32))
-            })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-                _ => 5,
+            });
+        let (physical_device, queue_family_index) =
+            select_gpu(candidates, settings.gui.render_device.as_ref(), |(p, _)| {
+                GpuInfo::from_device(p)
             })
             .unwrap();
 
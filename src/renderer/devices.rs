@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::physical::{PhysicalDevice, PhysicalDeviceType},
+    instance::Instance,
+};
+
+use crate::{gui::window::WasabiError, settings::GpuPreference};
+
+/// A GPU that can run the scene, as shown in the settings
+#[derive(Debug, Clone)]
+pub struct GpuInfo {
+    pub name: String,
+    /// Hex encoded UUID of the device, empty if the driver doesn't report one
+    pub uuid: String,
+    pub device_type: PhysicalDeviceType,
+}
+
+impl GpuInfo {
+    pub fn from_device(device: &PhysicalDevice) -> Self {
+        let properties = device.properties();
+        Self {
+            name: properties.device_name.clone(),
+            uuid: properties
+                .device_uuid
+                .map(|uuid| uuid.iter().map(|b| format!("{b:02x}")).collect())
+                .unwrap_or_default(),
+            device_type: properties.device_type,
+        }
+    }
+
+    pub fn preference(&self) -> GpuPreference {
+        GpuPreference {
+            name: self.name.clone(),
+            uuid: self.uuid.clone(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let kind = match self.device_type {
+            PhysicalDeviceType::DiscreteGpu => "Discrete",
+            PhysicalDeviceType::IntegratedGpu => "Integrated",
+            PhysicalDeviceType::VirtualGpu => "Virtual",
+            PhysicalDeviceType::Cpu => "CPU",
+            _ => "Other",
+        };
+        format!("{} ({kind})", self.name)
+    }
+
+    fn matches(&self, preference: &GpuPreference) -> bool {
+        if !self.uuid.is_empty() && !preference.uuid.is_empty() {
+            self.uuid == preference.uuid
+        } else {
+            self.name == preference.name
+        }
+    }
+
+    /// Discrete GPUs first, then integrated, virtual and software ones
+    fn rank(&self) -> u8 {
+        match self.device_type {
+            PhysicalDeviceType::DiscreteGpu => 0,
+            PhysicalDeviceType::IntegratedGpu => 1,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 3,
+            PhysicalDeviceType::Other => 4,
+            _ => 5,
+        }
+    }
+}
+
+/// Lists the GPUs of the instance which support the geometry shaders of the scene
+pub fn list_gpus(instance: &Arc<Instance>) -> Result<Vec<GpuInfo>, WasabiError> {
+    let devices = instance
+        .enumerate_physical_devices()
+        .map_err(|e| WasabiError::GraphicsError(e.to_string()))?;
+
+    Ok(devices
+        .filter(|p| p.supported_features().geometry_shader)
+        .map(|p| GpuInfo::from_device(&p))
+        .collect())
+}
+
+/// Picks the preferred GPU among the candidates, or the most capable one if there
+/// is no preference or the preferred GPU isn't available anymore
+pub fn select_gpu<T>(
+    candidates: impl IntoIterator<Item = T>,
+    preference: Option<&GpuPreference>,
+    info: impl Fn(&T) -> GpuInfo,
+) -> Option<T> {
+    let candidates: Vec<(GpuInfo, T)> = candidates.into_iter().map(|c| (info(&c), c)).collect();
+
+    let preferred = preference.and_then(|preference| {
+        candidates
+            .iter()
+            .position(|(gpu, _)| gpu.matches(preference))
+    });
+
+    match preferred {
+        Some(index) => candidates.into_iter().nth(index),
+        None => candidates.into_iter().min_by_key(|(gpu, _)| gpu.rank()),
+    }
+    .map(|(_, candidate)| candidate)
+}
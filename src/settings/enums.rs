@@ -11,6 +11,7 @@ pub enum MidiParsing {
     Live = 1,
     Cake = 2,
     Pie = 3,
+    Auto = 4,
 }
 
 impl MidiParsing {
@@ -20,6 +21,7 @@ impl MidiParsing {
             MidiParsing::Live => "Standard (Live)",
             MidiParsing::Cake => "Cake",
             MidiParsing::Pie => "Pie",
+            MidiParsing::Auto => "Auto",
         }
     }
 }
@@ -33,8 +35,9 @@ impl FromStr for MidiParsing {
             "live" => Ok(MidiParsing::Live),
             "cake" => Ok(MidiParsing::Cake),
             "pie" => Ok(MidiParsing::Pie),
+            "auto" => Ok(MidiParsing::Auto),
             s => Err(format!(
-                "{} was not expected. Expected one of `ram`, `live` or `cake`",
+                "{} was not expected. Expected one of `ram`, `live`, `cake`, `pie` or `auto`",
                 s
             )),
         }
@@ -156,6 +159,7 @@ pub enum Colors {
     Random = 1,
     Palette = 2,
     White = 3,
+    Gradient = 4,
 }
 
 impl Colors {
@@ -166,6 +170,7 @@ impl Colors {
             Colors::Random => "Random",
             Colors::Palette => "Palette",
             Colors::White => "White",
+            Colors::Gradient => "Gradient",
         }
     }
 }
@@ -179,8 +184,466 @@ impl FromStr for Colors {
             "random" => Ok(Colors::Random),
             "palette" => Ok(Colors::Palette),
             "white" => Ok(Colors::White),
+            "gradient" => Ok(Colors::Gradient),
             s => Err(format!(
-                "{} was not expected. Expected one of `rainbow`, `random`, `palette` or `white`",
+                "{} was not expected. Expected one of `rainbow`, `random`, `palette`, `white` or `gradient`",
+                s
+            )),
+        }
+    }
+}
+
+/// What the note colors are picked by
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorBy {
+    #[default]
+    TrackChannel = 0,
+    Track = 1,
+    Channel = 2,
+    PitchClass = 3,
+    Octave = 4,
+    Velocity = 5,
+}
+
+impl ColorBy {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ColorBy::TrackChannel => "Track & Channel",
+            ColorBy::Track => "Track",
+            ColorBy::Channel => "Channel",
+            ColorBy::PitchClass => "Pitch Class",
+            ColorBy::Octave => "Octave",
+            ColorBy::Velocity => "Velocity",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, ColorBy> {
+        static COLOR_BY: [ColorBy; 6] = [
+            ColorBy::TrackChannel,
+            ColorBy::Track,
+            ColorBy::Channel,
+            ColorBy::PitchClass,
+            ColorBy::Octave,
+            ColorBy::Velocity,
+        ];
+        COLOR_BY.iter()
+    }
+
+    /// Number of distinct colors needed for a MIDI with the given number of tracks
+    #[inline]
+    pub const fn color_count(self, tracks: usize) -> usize {
+        match self {
+            ColorBy::TrackChannel => tracks * 16,
+            ColorBy::Track => tracks,
+            ColorBy::Channel => 16,
+            ColorBy::PitchClass => 12,
+            ColorBy::Octave => 256usize.div_ceil(12),
+            ColorBy::Velocity => 128,
+        }
+    }
+}
+
+impl FromStr for ColorBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "trackchannel" => Ok(ColorBy::TrackChannel),
+            "track" => Ok(ColorBy::Track),
+            "channel" => Ok(ColorBy::Channel),
+            "pitchclass" => Ok(ColorBy::PitchClass),
+            "octave" => Ok(ColorBy::Octave),
+            "velocity" => Ok(ColorBy::Velocity),
+            s => Err(format!(
+                "{} was not expected. Expected one of `trackchannel`, `track`, `channel`, `pitchclass`, `octave` or `velocity`",
+                s
+            )),
+        }
+    }
+}
+
+/// How the colors of a palette image are assigned to the tracks and channels
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum PaletteOrder {
+    /// One row for each track, one column for each channel
+    #[default]
+    Track = 0,
+    /// One row for each channel, one column for each track
+    Channel = 1,
+}
+
+impl PaletteOrder {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            PaletteOrder::Track => "Per Track",
+            PaletteOrder::Channel => "Per Channel",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, PaletteOrder> {
+        static ORDERS: [PaletteOrder; 2] = [PaletteOrder::Track, PaletteOrder::Channel];
+        ORDERS.iter()
+    }
+}
+
+impl FromStr for PaletteOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "track" => Ok(PaletteOrder::Track),
+            "channel" => Ok(PaletteOrder::Channel),
+            s => Err(format!(
+                "{} was not expected. Expected one of `track` or `channel`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum VelocityMode {
+    #[default]
+    None = 0,
+    Brightness = 1,
+    Opacity = 2,
+}
+
+impl VelocityMode {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            VelocityMode::None => "None",
+            VelocityMode::Brightness => "Brightness",
+            VelocityMode::Opacity => "Opacity",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, VelocityMode> {
+        static MODES: [VelocityMode; 3] = [
+            VelocityMode::None,
+            VelocityMode::Brightness,
+            VelocityMode::Opacity,
+        ];
+        MODES.iter()
+    }
+}
+
+impl FromStr for VelocityMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(VelocityMode::None),
+            "brightness" => Ok(VelocityMode::Brightness),
+            "opacity" => Ok(VelocityMode::Opacity),
+            s => Err(format!(
+                "{} was not expected. Expected one of `none`, `brightness` or `opacity`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteStyle {
+    Flat = 0,
+    #[default]
+    Gradient = 1,
+    Rounded = 2,
+    Outlined = 3,
+    Glow = 4,
+}
+
+impl NoteStyle {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NoteStyle::Flat => "Flat",
+            NoteStyle::Gradient => "Classic Gradient",
+            NoteStyle::Rounded => "Rounded",
+            NoteStyle::Outlined => "Outlined",
+            NoteStyle::Glow => "Glow",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, NoteStyle> {
+        static STYLES: [NoteStyle; 5] = [
+            NoteStyle::Flat,
+            NoteStyle::Gradient,
+            NoteStyle::Rounded,
+            NoteStyle::Outlined,
+            NoteStyle::Glow,
+        ];
+        STYLES.iter()
+    }
+}
+
+impl FromStr for NoteStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flat" => Ok(NoteStyle::Flat),
+            "gradient" => Ok(NoteStyle::Gradient),
+            "rounded" => Ok(NoteStyle::Rounded),
+            "outlined" => Ok(NoteStyle::Outlined),
+            "glow" => Ok(NoteStyle::Glow),
+            s => Err(format!(
+                "{} was not expected. Expected one of `flat`, `gradient`, `rounded`, `outlined` or `glow`",
+                s
+            )),
+        }
+    }
+}
+
+/// How the widths of the keys are laid out
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardType {
+    #[default]
+    Classic = 0,
+    SameWidth = 1,
+}
+
+impl KeyboardType {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            KeyboardType::Classic => "Classic",
+            KeyboardType::SameWidth => "Equal Width",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, KeyboardType> {
+        static TYPES: [KeyboardType; 2] = [KeyboardType::Classic, KeyboardType::SameWidth];
+        TYPES.iter()
+    }
+}
+
+impl FromStr for KeyboardType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "classic" => Ok(KeyboardType::Classic),
+            "samewidth" => Ok(KeyboardType::SameWidth),
+            s => Err(format!(
+                "{} was not expected. Expected one of `classic` or `samewidth`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStyle {
+    #[default]
+    Shaded = 0,
+    Flat = 1,
+}
+
+impl KeyStyle {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            KeyStyle::Shaded => "Shaded",
+            KeyStyle::Flat => "Flat",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, KeyStyle> {
+        static STYLES: [KeyStyle; 2] = [KeyStyle::Shaded, KeyStyle::Flat];
+        STYLES.iter()
+    }
+}
+
+impl FromStr for KeyStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "shaded" => Ok(KeyStyle::Shaded),
+            "flat" => Ok(KeyStyle::Flat),
+            s => Err(format!(
+                "{} was not expected. Expected one of `shaded` or `flat`",
+                s
+            )),
+        }
+    }
+}
+
+/// The direction the notes move in, towards the keyboard
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollDirection {
+    #[default]
+    Down = 0,
+    Up = 1,
+    Horizontal = 2,
+}
+
+impl ScrollDirection {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ScrollDirection::Down => "Downward",
+            ScrollDirection::Up => "Upward",
+            ScrollDirection::Horizontal => "Horizontal",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, ScrollDirection> {
+        static DIRECTIONS: [ScrollDirection; 3] = [
+            ScrollDirection::Down,
+            ScrollDirection::Up,
+            ScrollDirection::Horizontal,
+        ];
+        DIRECTIONS.iter()
+    }
+
+    #[inline]
+    pub const fn is_horizontal(self) -> bool {
+        matches!(self, ScrollDirection::Horizontal)
+    }
+
+    /// Converts the size of the scene to its length along the keys and its length
+    /// along the time, which are swapped in the horizontal layout
+    #[inline]
+    pub fn orient<T: Copy>(self, size: [T; 2]) -> [T; 2] {
+        if self.is_horizontal() {
+            [size[1], size[0]]
+        } else {
+            size
+        }
+    }
+}
+
+impl FromStr for ScrollDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "down" => Ok(ScrollDirection::Down),
+            "up" => Ok(ScrollDirection::Up),
+            "horizontal" => Ok(ScrollDirection::Horizontal),
+            s => Err(format!(
+                "{} was not expected. Expected one of `down`, `up` or `horizontal`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteOffMatching {
+    #[default]
+    Fifo = 0,
+    Lifo = 1,
+    EndAll = 2,
+}
+
+impl NoteOffMatching {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NoteOffMatching::Fifo => "Oldest Note (FIFO)",
+            NoteOffMatching::Lifo => "Newest Note (LIFO)",
+            NoteOffMatching::EndAll => "All Notes",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, NoteOffMatching> {
+        static MATCHING: [NoteOffMatching; 3] = [
+            NoteOffMatching::Fifo,
+            NoteOffMatching::Lifo,
+            NoteOffMatching::EndAll,
+        ];
+        MATCHING.iter()
+    }
+}
+
+impl FromStr for NoteOffMatching {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fifo" => Ok(NoteOffMatching::Fifo),
+            "lifo" => Ok(NoteOffMatching::Lifo),
+            "endall" => Ok(NoteOffMatching::EndAll),
+            s => Err(format!(
+                "{} was not expected. Expected one of `fifo`, `lifo` or `endall`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundFit {
+    Fit = 0,
+    #[default]
+    Fill = 1,
+    Tile = 2,
+}
+
+impl BackgroundFit {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            BackgroundFit::Fit => "Fit",
+            BackgroundFit::Fill => "Fill",
+            BackgroundFit::Tile => "Tile",
+        }
+    }
+
+    pub fn iter() -> Iter<'static, BackgroundFit> {
+        static FITS: [BackgroundFit; 3] =
+            [BackgroundFit::Fit, BackgroundFit::Fill, BackgroundFit::Tile];
+        FITS.iter()
+    }
+
+    /// The size of an image scaled into the target area. Tiled images keep their size.
+    pub fn scaled_size(self, image: [f32; 2], target: [f32; 2]) -> [f32; 2] {
+        let scale_x = target[0] / image[0];
+        let scale_y = target[1] / image[1];
+        let scale = match self {
+            BackgroundFit::Fit => scale_x.min(scale_y),
+            BackgroundFit::Fill => scale_x.max(scale_y),
+            BackgroundFit::Tile => 1.0,
+        };
+        [image[0] * scale, image[1] * scale]
+    }
+}
+
+impl FromStr for BackgroundFit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fit" => Ok(BackgroundFit::Fit),
+            "fill" => Ok(BackgroundFit::Fill),
+            "tile" => Ok(BackgroundFit::Tile),
+            s => Err(format!(
+                "{} was not expected. Expected one of `fit`, `fill` or `tile`",
                 s
             )),
         }
@@ -69,6 +69,7 @@ impl WasabiConfigFileV1 {
                 statistics: Default::default(),
                 note_speed: cfg.midi.note_speed,
                 key_range: cfg.midi.key_range,
+                ..Default::default()
             },
             midi: MidiSettings {
                 parsing: cfg.midi.midi_loading,
@@ -1,4 +1,4 @@
-use directories::BaseDirs;
+use directories::{BaseDirs, UserDirs};
 use egui::Color32;
 use serde_derive::{Deserialize, Serialize};
 use std::{
@@ -13,13 +13,45 @@ use xsynth_realtime::XSynthRealtimeConfig;
 
 mod enums;
 mod migrations;
+mod profiles;
+mod zenith;
 
 pub use enums::*;
+pub use profiles::{FileProfile, FileProfiles};
 
 use crate::gui::window::WasabiError;
 
 // region: gui
 
+/// A GPU picked in the settings, found again by its UUID, or by its name if the
+/// driver doesn't report one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct GpuPreference {
+    pub name: String,
+    pub uuid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    /// Width and height of the screenshots, the window size if `None`
+    pub resolution: Option<[u32; 2]>,
+    pub statistics: bool,
+    /// Folder the screenshots are saved in, the pictures folder if `None`
+    pub folder: Option<PathBuf>,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            resolution: None,
+            statistics: true,
+            folder: None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct GuiSettings {
@@ -28,6 +60,11 @@ pub struct GuiSettings {
     pub skip_control: f64,
     pub speed_control: f64,
     pub ffmpeg_path: Option<PathBuf>,
+    /// GPU of the window, picked automatically if `None`
+    pub render_device: Option<GpuPreference>,
+    /// GPU of the video export, picked automatically if `None`
+    pub export_device: Option<GpuPreference>,
+    pub screenshot: ScreenshotSettings,
 }
 
 impl Default for GuiSettings {
@@ -38,6 +75,9 @@ impl Default for GuiSettings {
             skip_control: 1.0,
             speed_control: 0.05,
             ffmpeg_path: None,
+            render_device: None,
+            export_device: None,
+            screenshot: Default::default(),
         }
     }
 }
@@ -62,24 +102,221 @@ impl Default for StatisticsSettings {
     }
 }
 
+/// How the note velocity affects the rendered notes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct VelocitySettings {
+    pub mode: VelocityMode,
+    /// Notes with a lower velocity are hidden
+    pub min_velocity: u8,
+}
+
+impl Default for VelocitySettings {
+    fn default() -> Self {
+        Self {
+            mode: VelocityMode::None,
+            min_velocity: 0,
+        }
+    }
+}
+
+/// How the notes are drawn by all of the renderers
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct NoteStyleSettings {
+    pub style: NoteStyle,
+    /// Multiplier of the border width, which depends on the width of the keys
+    pub border_width: f32,
+    /// When unset, the border color is derived from the note color
+    pub border_color: Option<Color32>,
+    /// Strength of the shading of the style, from 0 to 1
+    pub shading: f32,
+}
+
+impl NoteStyleSettings {
+    /// The border color as packed RGB, or -1 when it is derived from the note color
+    pub fn packed_border_color(&self) -> i32 {
+        self.border_color.map_or(-1, |c| {
+            ((c.r() as i32) << 16) | ((c.g() as i32) << 8) | c.b() as i32
+        })
+    }
+}
+
+impl Default for NoteStyleSettings {
+    fn default() -> Self {
+        Self {
+            style: NoteStyle::Gradient,
+            border_width: 1.0,
+            border_color: None,
+            shading: 1.0,
+        }
+    }
+}
+
+/// The layout and the look of the keyboard, used by the GUI and the video export
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct KeyboardSettings {
+    pub keyboard_type: KeyboardType,
+    /// Offsets of the black keys of the classic layout, for the sets of 2 and 3
+    pub black_key_2_set_offset: f32,
+    pub black_key_3_set_offset: f32,
+    /// Width of the black keys of the classic layout, relative to the white keys
+    pub black_key_scale: f32,
+    /// Multiplier of the default keyboard height
+    pub height: f32,
+    /// Length of the black keys, relative to the keyboard height
+    pub black_key_length: f32,
+    pub key_style: KeyStyle,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            keyboard_type: KeyboardType::Classic,
+            black_key_2_set_offset: 0.35,
+            black_key_3_set_offset: 0.45,
+            black_key_scale: 0.74,
+            height: 1.0,
+            black_key_length: 0.66,
+            key_style: KeyStyle::Shaded,
+        }
+    }
+}
+
+/// An image or a video shown behind the notes instead of the flat background color
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BackgroundSettings {
+    pub image: Option<PathBuf>,
+    /// Only used by the video export, where it replaces the image
+    pub video: Option<PathBuf>,
+    pub fit: BackgroundFit,
+    /// How much the background is darkened, in the range `0.0..=1.0`
+    pub dim: f32,
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            image: None,
+            video: None,
+            fit: BackgroundFit::Fill,
+            dim: 0.5,
+        }
+    }
+}
+
+/// The bar and beat lines drawn under the notes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct GridSettings {
+    pub enabled: bool,
+    pub bar_color: Color32,
+    pub beat_color: Color32,
+    pub bar_numbers: bool,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bar_color: Color32::from_rgba_unmultiplied(255, 255, 255, 60),
+            beat_color: Color32::from_rgba_unmultiplied(255, 255, 255, 20),
+            bar_numbers: true,
+        }
+    }
+}
+
+/// The effects drawn where the notes hit the keyboard
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct KeyEffectsSettings {
+    pub key_glow: bool,
+    pub light_beams: bool,
+    pub particles: bool,
+    /// Length of the light beams, relative to the notes area
+    pub beam_length: f32,
+    /// Multiplier of the number of particles of each note
+    pub particle_amount: f32,
+}
+
+impl KeyEffectsSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.key_glow || self.light_beams || self.particles
+    }
+}
+
+impl Default for KeyEffectsSettings {
+    fn default() -> Self {
+        Self {
+            key_glow: false,
+            light_beams: false,
+            particles: false,
+            beam_length: 0.3,
+            particle_amount: 1.0,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct SceneSettings {
     pub bg_color: Color32,
+    pub background: BackgroundSettings,
     pub bar_color: Color32,
     pub statistics: StatisticsSettings,
     pub note_speed: f64,
+    pub scroll_direction: ScrollDirection,
     pub key_range: RangeInclusive<u8>,
+    /// Shows only the keys used by the loaded MIDI instead of `key_range`
+    pub auto_fit_keys: bool,
+    pub keyboard: KeyboardSettings,
+    pub velocity: VelocitySettings,
+    pub note_style: NoteStyleSettings,
+    pub grid: GridSettings,
+    pub effects: KeyEffectsSettings,
+    /// Semitones added to the audio and the keyboard view during playback, without
+    /// reloading the MIDI. Not saved, so every session starts untransposed.
+    #[serde(skip)]
+    pub live_transpose: i32,
 }
 
 impl Default for SceneSettings {
     fn default() -> Self {
         Self {
             bg_color: Color32::from_rgb(30, 30, 30),
+            background: Default::default(),
             bar_color: Color32::from_rgb(145, 0, 0),
             statistics: Default::default(),
             note_speed: 0.25,
+            scroll_direction: ScrollDirection::Down,
             key_range: 0..=127,
+            auto_fit_keys: false,
+            keyboard: Default::default(),
+            velocity: Default::default(),
+            note_style: Default::default(),
+            grid: Default::default(),
+            effects: Default::default(),
+            live_transpose: 0,
+        }
+    }
+}
+
+impl SceneSettings {
+    /// The range of keys to display, given the keys used by the loaded MIDI
+    pub fn visible_key_range(&self, used_keys: Option<RangeInclusive<u8>>) -> RangeInclusive<u8> {
+        match used_keys {
+            Some(used) if self.auto_fit_keys => {
+                // The keyboard needs at least two keys
+                if used.start() == used.end() {
+                    let key = (*used.start()).min(254);
+                    key..=key + 1
+                } else {
+                    used
+                }
+            }
+            _ => self.key_range.clone(),
         }
     }
 }
@@ -88,24 +325,98 @@ impl Default for SceneSettings {
 
 // region: midi
 
+/// Filters which remove notes while the MIDI is loading, from both the
+/// rendered notes and the audio
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct NoteFilterSettings {
+    /// Notes with a lower velocity are removed
+    pub min_velocity: u8,
+    /// Notes shorter than this, in milliseconds, are removed
+    pub min_length: f64,
+    /// Removes notes which start at the same time as another note of the same
+    /// key, track and channel, and end before it
+    pub remove_duplicates: bool,
+    /// Removes the notes outside of `key_range`
+    pub limit_key_range: bool,
+    pub key_range: RangeInclusive<u8>,
+}
+
+impl NoteFilterSettings {
+    /// Whether any of the filters can remove notes
+    pub fn is_active(&self) -> bool {
+        self.min_velocity > 1
+            || self.min_length > 0.0
+            || self.remove_duplicates
+            || self.limit_key_range
+    }
+}
+
+impl Default for NoteFilterSettings {
+    fn default() -> Self {
+        Self {
+            min_velocity: 1,
+            min_length: 0.0,
+            remove_duplicates: false,
+            limit_key_range: false,
+            key_range: 0..=127,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct MidiSettings {
     pub parsing: MidiParsing,
+    pub parallel_parsing: bool,
+    pub note_off_matching: NoteOffMatching,
+    pub filters: NoteFilterSettings,
+    /// Semitones added to every note while loading
+    pub transpose: i32,
     pub start_delay: f64,
     pub colors: Colors,
+    pub color_by: ColorBy,
+    /// The first and the last color of the gradient colors
+    pub gradient: [Color32; 2],
     pub randomize_palette: bool,
+    /// Start reading the palette at a random track, like Zenith does
+    pub palette_random_offset: bool,
+    pub palette_order: PaletteOrder,
     pub palette_path: PathBuf,
+    /// Saves the colors, the view and the playback position of each MIDI and
+    /// restores them when the MIDI is opened again
+    pub remember_files: bool,
+    /// Seed of the random colors, set from the profile of the loaded file
+    #[serde(skip)]
+    pub color_seed: Option<u64>,
+    /// Notes with a lower velocity are left out of the Cake and Pie note trees,
+    /// set from the scene velocity settings when the MIDI is loaded
+    #[serde(skip)]
+    pub hidden_velocity: u8,
 }
 
 impl Default for MidiSettings {
     fn default() -> Self {
         Self {
             parsing: MidiParsing::Cake,
+            parallel_parsing: false,
+            note_off_matching: NoteOffMatching::Fifo,
+            filters: Default::default(),
+            transpose: 0,
             start_delay: 2.0,
             colors: Colors::Rainbow,
+            color_by: ColorBy::TrackChannel,
+            gradient: [
+                Color32::from_rgb(0, 120, 255),
+                Color32::from_rgb(255, 40, 100),
+            ],
             randomize_palette: false,
+            palette_random_offset: false,
+            palette_order: PaletteOrder::Track,
             palette_path: PathBuf::new(),
+            remember_files: false,
+            color_seed: None,
+            hidden_velocity: 0,
         }
     }
 }
@@ -180,6 +491,10 @@ pub struct WasabiSettings {
     pub scene: SceneSettings,
     pub midi: MidiSettings,
     pub synth: SynthSettings,
+    /// The global values of the settings stored in the file profiles, kept while
+    /// the profile of the loaded MIDI replaces them
+    #[serde(skip)]
+    pub profile_defaults: Option<FileProfile>,
 }
 
 impl WasabiSettings {
@@ -233,8 +548,16 @@ impl WasabiSettings {
     }
 
     pub fn save_to_file(&self) -> Result<(), WasabiError> {
+        // The values of a file profile are only saved into the profile
+        let mut global = None;
+        if self.profile_defaults.is_some() {
+            let mut settings = self.clone();
+            FileProfile::restore_defaults(&mut settings);
+            global = Some(settings);
+        }
+
         let config_path = Self::get_config_path();
-        let cfg: String = serde_json::to_string_pretty(&self)
+        let cfg: String = serde_json::to_string_pretty(global.as_ref().unwrap_or(self))
             .map_err(|e| WasabiError::SettingsError(e.to_string()))?;
         if let Ok(mut file) = fs::File::create(&config_path) {
             file.write_all(Self::VERSION_TEXT.as_bytes())
@@ -278,6 +601,15 @@ impl WasabiSettings {
         path
     }
 
+    pub fn get_screenshots_dir() -> PathBuf {
+        let mut path = UserDirs::new()
+            .and_then(|dirs| dirs.picture_dir().map(|p| p.to_path_buf()))
+            .unwrap_or_else(Self::get_config_dir);
+        path.push("Wasabi");
+
+        path
+    }
+
     pub fn get_palettes_dir() -> PathBuf {
         let mut path = Self::get_config_dir();
         path.push("palettes");
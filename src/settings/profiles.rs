@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use egui::Color32;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::gui::window::WasabiError;
+
+use super::{ColorBy, Colors, WasabiSettings};
+
+/// The settings remembered for a single MIDI file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct FileProfile {
+    pub colors: Colors,
+    pub color_by: ColorBy,
+    pub palette_path: PathBuf,
+    pub gradient: [Color32; 2],
+    /// Seed of the random colors, so they are the same every time
+    pub color_seed: u64,
+    pub key_range: RangeInclusive<u8>,
+    pub auto_fit_keys: bool,
+    pub note_speed: f64,
+    pub start_delay: f64,
+    pub muted: bool,
+    /// Playback position in seconds when the file was closed
+    pub position: f64,
+}
+
+impl Default for FileProfile {
+    fn default() -> Self {
+        Self::from_settings(&WasabiSettings::default())
+    }
+}
+
+impl FileProfile {
+    /// A profile with the current settings, a new color seed and no playback state
+    pub fn from_settings(settings: &WasabiSettings) -> Self {
+        Self {
+            colors: settings.midi.colors,
+            color_by: settings.midi.color_by,
+            palette_path: settings.midi.palette_path.clone(),
+            gradient: settings.midi.gradient,
+            color_seed: settings.midi.color_seed.unwrap_or_else(rand::random::<u64>),
+            key_range: settings.scene.key_range.clone(),
+            auto_fit_keys: settings.scene.auto_fit_keys,
+            note_speed: settings.scene.note_speed,
+            start_delay: settings.midi.start_delay,
+            muted: false,
+            position: 0.0,
+        }
+    }
+
+    /// Replaces the settings stored in the profile. The global values are kept
+    /// aside, to be restored when a MIDI without a profile is loaded.
+    pub fn apply(&self, settings: &mut WasabiSettings) {
+        if settings.profile_defaults.is_none() {
+            settings.profile_defaults = Some(Self::from_settings(settings));
+        }
+        self.write_into(settings);
+    }
+
+    /// Puts back the global values replaced by the applied profile, if any
+    pub fn restore_defaults(settings: &mut WasabiSettings) {
+        if let Some(defaults) = settings.profile_defaults.take() {
+            defaults.write_into(settings);
+        }
+    }
+
+    fn write_into(&self, settings: &mut WasabiSettings) {
+        settings.midi.colors = self.colors;
+        settings.midi.color_by = self.color_by;
+        settings.midi.palette_path = self.palette_path.clone();
+        settings.midi.gradient = self.gradient;
+        settings.midi.color_seed = Some(self.color_seed);
+        settings.scene.key_range = self.key_range.clone();
+        settings.scene.auto_fit_keys = self.auto_fit_keys;
+        settings.scene.note_speed = self.note_speed;
+        settings.midi.start_delay = self.start_delay;
+    }
+}
+
+/// The profiles of all the remembered files, keyed by their path
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileProfiles {
+    profiles: HashMap<String, FileProfile>,
+}
+
+impl FileProfiles {
+    fn get_path() -> PathBuf {
+        let mut path = WasabiSettings::get_config_dir();
+        path.push("file-profiles.json");
+
+        path
+    }
+
+    fn key(midi_path: &Path) -> String {
+        let path = midi_path
+            .canonicalize()
+            .unwrap_or_else(|_| midi_path.to_path_buf());
+        path.to_string_lossy().into_owned()
+    }
+
+    pub fn load() -> Result<Self, WasabiError> {
+        let path = Self::get_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let profiles = fs::read_to_string(path).map_err(WasabiError::FilesystemError)?;
+        serde_json::from_str(&profiles).map_err(|e| WasabiError::SettingsError(e.to_string()))
+    }
+
+    pub fn save(&self) -> Result<(), WasabiError> {
+        let profiles = serde_json::to_string_pretty(self)
+            .map_err(|e| WasabiError::SettingsError(e.to_string()))?;
+        fs::write(Self::get_path(), profiles).map_err(WasabiError::FilesystemError)
+    }
+
+    pub fn get(&self, midi_path: &Path) -> Option<&FileProfile> {
+        self.profiles.get(&Self::key(midi_path))
+    }
+
+    pub fn insert(&mut self, midi_path: &Path, profile: FileProfile) {
+        self.profiles.insert(Self::key(midi_path), profile);
+    }
+}
@@ -0,0 +1,135 @@
+//! Importing the note color settings of Zenith
+//!
+//! Zenith saves the settings of a render module as a JSON object, in which the
+//! palette picker stores its state under `palette`:
+//!
+//! ```json
+//! {
+//!     "palette": {
+//!         "selected": "Rainbow.png",
+//!         "randomise": true,
+//!         "randomOffset": false,
+//!         "perChannel": false
+//!     }
+//! }
+//! ```
+//!
+//! The other settings of the module are ignored.
+
+use std::path::{Path, PathBuf};
+
+use serde_derive::Deserialize;
+
+use crate::gui::window::WasabiError;
+
+use super::{ColorBy, Colors, MidiSettings, PaletteOrder, WasabiSettings};
+
+#[derive(Deserialize)]
+struct ZenithModuleSettings {
+    palette: ZenithPalettePick,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZenithPalettePick {
+    /// File name of the palette image in the palettes directory of Zenith
+    selected: String,
+    randomise: Option<bool>,
+    random_offset: Option<bool>,
+    per_channel: Option<bool>,
+}
+
+/// Finds the palette image of the settings, copying it into the palettes
+/// directory when it's stored somewhere else
+fn resolve_palette(name: &str, settings_path: &Path) -> Result<PathBuf, WasabiError> {
+    let palettes_dir = WasabiSettings::get_palettes_dir();
+    let name = Path::new(name);
+    let file_name = name
+        .file_name()
+        .ok_or_else(|| WasabiError::PaletteError(format!("Invalid palette name: {name:?}")))?;
+
+    let mut candidates = vec![palettes_dir.join(file_name)];
+    if let Some(dir) = settings_path.parent() {
+        candidates.push(dir.join(name));
+    }
+    candidates.push(name.to_path_buf());
+
+    // Zenith palettes may be referenced without their extension
+    let found = candidates.into_iter().find_map(|path| {
+        let mut png = path.clone();
+        png.set_extension("png");
+        [path, png].into_iter().find(|path| path.is_file())
+    });
+
+    let Some(found) = found else {
+        return Err(WasabiError::PaletteError(format!(
+            "Palette of the Zenith settings not found: {name:?}"
+        )));
+    };
+
+    if found.parent() == Some(palettes_dir.as_path()) {
+        return Ok(found);
+    }
+
+    let target = palette_copy_target(&found, &palettes_dir)?;
+    if !target.exists() {
+        std::fs::copy(&found, &target).map_err(WasabiError::FilesystemError)?;
+    }
+    Ok(target)
+}
+
+/// The path the palette is copied to. A different palette with the same name is
+/// never overwritten, the copy gets a numbered name instead. An identical one is
+/// reused.
+fn palette_copy_target(palette: &Path, palettes_dir: &Path) -> Result<PathBuf, WasabiError> {
+    let content = std::fs::read(palette).map_err(WasabiError::FilesystemError)?;
+    let stem = palette.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = palette.extension().map_or(String::new(), |extension| {
+        format!(".{}", extension.to_string_lossy())
+    });
+
+    let mut i = 1;
+    loop {
+        let file_name = match i {
+            1 => format!("{stem}{extension}"),
+            _ => format!("{stem} ({i}){extension}"),
+        };
+        let target = palettes_dir.join(file_name);
+        if !target.exists() || std::fs::read(&target).is_ok_and(|existing| existing == content) {
+            return Ok(target);
+        }
+        i += 1;
+    }
+}
+
+impl MidiSettings {
+    /// Applies the palette and the palette options of a Zenith color settings file.
+    /// Options missing from the file are left unchanged.
+    pub fn import_zenith_colors(&mut self, path: &Path) -> Result<(), WasabiError> {
+        let text = std::fs::read_to_string(path).map_err(WasabiError::FilesystemError)?;
+        let settings: ZenithModuleSettings = serde_json::from_str(&text).map_err(|e| {
+            WasabiError::PaletteError(format!("Invalid Zenith color settings: {e}"))
+        })?;
+        let palette = settings.palette;
+
+        self.palette_path = resolve_palette(&palette.selected, path)?;
+        self.colors = Colors::Palette;
+        self.color_by = ColorBy::TrackChannel;
+
+        if let Some(randomize) = palette.randomise {
+            self.randomize_palette = randomize;
+        }
+        if let Some(offset) = palette.random_offset {
+            self.palette_random_offset = offset;
+        }
+        if let Some(per_channel) = palette.per_channel {
+            self.palette_order = if per_channel {
+                PaletteOrder::Channel
+            } else {
+                PaletteOrder::Track
+            };
+        }
+
+        Ok(())
+    }
+}
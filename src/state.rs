@@ -2,7 +2,11 @@ use std::{path::PathBuf, sync::Arc};
 
 use crate::{
     audio_playback::WasabiAudioPlayer,
-    gui::window::{render_state::RenderState, GuiMessageSystem, LoadingStatus},
+    gui::window::{
+        export::MidiExportState, palette_editor::PaletteEditorState, render_state::RenderState,
+        GuiMessageSystem, LoadingStatus,
+    },
+    settings::Colors,
 };
 
 #[derive(Default, PartialEq)]
@@ -31,8 +35,15 @@ pub struct WasabiState {
     pub show_shortcuts: bool,
     pub show_about: bool,
     pub show_render: bool,
+    pub show_export: bool,
+    pub show_layers: bool,
+    pub show_palette_editor: bool,
 
     pub render_state: RenderState,
+    pub export_state: MidiExportState,
+    pub palette_editor: PaletteEditorState,
+    /// The colors of the next MIDI added as a layer
+    pub layer_colors: Colors,
 
     pub settings_tab: SettingsTab,
 
@@ -62,8 +73,14 @@ impl WasabiState {
             show_shortcuts: false,
             show_about: false,
             show_render: false,
+            show_export: false,
+            show_layers: false,
+            show_palette_editor: false,
 
             render_state: RenderState::new(),
+            export_state: MidiExportState::default(),
+            palette_editor: PaletteEditorState::default(),
+            layer_colors: Colors::Random,
 
             settings_tab: SettingsTab::default(),
 
@@ -9,6 +9,12 @@ use crate::{gui::window::WasabiError, state::WasabiState};
 
 pub const WIN_MARGIN: egui::Margin = egui::Margin::same(12);
 pub const NOTE_SPEED_RANGE: RangeInclusive<f64> = 8.0..=0.05;
+pub const KEY_RANGE_PRESETS: [(&str, RangeInclusive<u8>); 3] = [
+    ("88 Keys", 21..=108),
+    ("128 Keys", 0..=127),
+    ("256 Keys", 0..=255),
+];
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "webp"];
 
 pub fn calculate_border_width(width_pixels: f32, keys_len: f32) -> f32 {
     ((width_pixels / keys_len) / 12.0).clamp(1.0, 5.0).round() * 2.0
@@ -32,6 +38,58 @@ pub fn create_window_frame(ctx: &egui::Context) -> egui::Frame {
     egui::Frame::inner_margin(egui::Frame::window(ctx.style().as_ref()), WIN_MARGIN)
 }
 
+/// Returns the amount of physical memory available to new allocations in bytes,
+/// or `None` if it can't be queried on this platform.
+pub fn available_memory() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+        let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kb * 1024)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        #[repr(C)]
+        struct MemoryStatusEx {
+            length: u32,
+            memory_load: u32,
+            total_phys: u64,
+            avail_phys: u64,
+            total_page_file: u64,
+            avail_page_file: u64,
+            total_virtual: u64,
+            avail_virtual: u64,
+            avail_extended_virtual: u64,
+        }
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GlobalMemoryStatusEx(buffer: *mut MemoryStatusEx) -> i32;
+        }
+
+        let mut status = MemoryStatusEx {
+            length: std::mem::size_of::<MemoryStatusEx>() as u32,
+            memory_load: 0,
+            total_phys: 0,
+            avail_phys: 0,
+            total_page_file: 0,
+            avail_page_file: 0,
+            total_virtual: 0,
+            avail_virtual: 0,
+            avail_extended_virtual: 0,
+        };
+        let ok = unsafe { GlobalMemoryStatusEx(&mut status) };
+        (ok != 0).then_some(status.avail_phys)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        None
+    }
+}
+
 fn get_latest_version() -> Result<String, WasabiError> {
     let api_url = "https://api.github.com/repos/BlackMIDIDevs/wasabi/releases/latest";
     let current = format!("v{}", env!("CARGO_PKG_VERSION"));
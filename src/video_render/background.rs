@@ -0,0 +1,298 @@
+//! Background image and video for video generation
+//!
+//! When a background is set, the notes are rendered over a transparent clear color
+//! and composited over the background on the CPU. Background videos are decoded by
+//! a separate FFmpeg process, which outputs one frame for each rendered frame.
+
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use image::{imageops, RgbaImage};
+
+use crate::gui::window::background::{dim_factor, load_background_image};
+use crate::settings::{BackgroundFit, SceneSettings};
+
+use super::ffmpeg_encoder::configure_command;
+
+/// A running FFmpeg process which decodes a video into raw BGRA frames
+struct VideoDecoder {
+    process: Child,
+    stdout: ChildStdout,
+}
+
+impl VideoDecoder {
+    fn spawn(
+        ffmpeg_path: &Path,
+        video_path: &Path,
+        filter: &str,
+        fps: u32,
+        start: f64,
+    ) -> std::io::Result<Self> {
+        let mut cmd = Command::new(ffmpeg_path);
+
+        cmd.args(["-hide_banner", "-loglevel", "error"])
+            .args(["-stream_loop", "-1"])
+            .args(["-ss", &format!("{start:.3}")])
+            .arg("-i")
+            .arg(video_path)
+            .args(["-vf", filter])
+            .args(["-r", &fps.to_string()])
+            .arg("-an")
+            .args(["-f", "rawvideo"])
+            .args(["-pix_fmt", "bgra"])
+            .arg("-")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+
+        configure_command(&mut cmd);
+
+        let mut process = cmd.spawn()?;
+        let stdout = process.stdout.take().ok_or_else(|| {
+            std::io::Error::new(ErrorKind::Other, "Failed to open stdout for FFmpeg process")
+        })?;
+
+        Ok(Self { process, stdout })
+    }
+
+    fn read_frame(&mut self, frame: &mut [u8]) -> std::io::Result<()> {
+        self.stdout.read_exact(frame)
+    }
+}
+
+impl Drop for VideoDecoder {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+enum BackgroundSource {
+    Image(RgbaImage),
+    Video {
+        path: PathBuf,
+        decoder: Option<VideoDecoder>,
+    },
+}
+
+/// The background behind the notes of the rendered frames
+pub struct VideoBackground {
+    source: BackgroundSource,
+    fit: BackgroundFit,
+    dim: f32,
+    bg_color: [u8; 3],
+    ffmpeg_path: PathBuf,
+    fps: u32,
+
+    /// The background of the current frame (BGRA), the size of the notes area
+    frame: Vec<u8>,
+    size: (u32, u32),
+    /// Number of frames composited, so a restarted video continues where it was
+    frames_rendered: u64,
+}
+
+impl VideoBackground {
+    /// Loads the background of the settings. Returns `None` when no background is set,
+    /// so the notes are drawn over the flat background color.
+    pub fn new(
+        settings: &SceneSettings,
+        ffmpeg_path: &Path,
+        fps: u32,
+    ) -> Result<Option<Self>, String> {
+        let background = &settings.background;
+
+        let source = if let Some(path) = &background.video {
+            BackgroundSource::Video {
+                path: path.clone(),
+                decoder: None,
+            }
+        } else if let Some(path) = &background.image {
+            BackgroundSource::Image(load_background_image(path).map_err(|e| e.to_string())?)
+        } else {
+            return Ok(None);
+        };
+
+        let bg = settings.bg_color;
+        Ok(Some(Self {
+            source,
+            fit: background.fit,
+            dim: dim_factor(background),
+            bg_color: [bg.r(), bg.g(), bg.b()],
+            ffmpeg_path: ffmpeg_path.to_path_buf(),
+            fps,
+            frame: Vec::new(),
+            size: (0, 0),
+            frames_rendered: 0,
+        }))
+    }
+
+    /// Starts the background video at the given time instead of at its beginning
+    pub fn start_at(&mut self, seconds: f64) {
+        self.frames_rendered = (seconds.max(0.0) * self.fps as f64).round() as u64;
+    }
+
+    /// Composites the notes in the given area of the frame (BGRA) over the background.
+    /// The notes must have been rendered over a transparent clear color.
+    pub fn composite(
+        &mut self,
+        target: &mut [u8],
+        target_width: u32,
+        offset: (u32, u32),
+        size: (u32, u32),
+    ) -> Result<(), String> {
+        if size.0 == 0 || size.1 == 0 {
+            return Ok(());
+        }
+
+        self.prepare_frame(size)?;
+        self.frames_rendered += 1;
+
+        let dim = (self.dim * 256.0) as u32;
+        let row_len = size.0 as usize * 4;
+
+        for (y, background) in self.frame.chunks_exact(row_len).enumerate() {
+            let start = ((offset.1 as usize + y) * target_width as usize + offset.0 as usize) * 4;
+            let Some(row) = target.get_mut(start..start + row_len) else {
+                break;
+            };
+
+            for (pixel, background) in row.chunks_exact_mut(4).zip(background.chunks_exact(4)) {
+                let transparency = 255 - pixel[3] as u32;
+                for c in 0..3 {
+                    let below = (background[c] as u32 * dim) >> 8;
+                    pixel[c] = (pixel[c] as u32 + below * transparency / 255).min(255) as u8;
+                }
+                pixel[3] = 255;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fills `self.frame` with the background of the current frame
+    fn prepare_frame(&mut self, size: (u32, u32)) -> Result<(), String> {
+        let resized = self.size != size;
+        if resized {
+            self.size = size;
+            self.frame.resize((size.0 * size.1 * 4) as usize, 0);
+        }
+
+        let filter = self.video_filter();
+        let start = self.frames_rendered as f64 / self.fps as f64;
+
+        match &mut self.source {
+            BackgroundSource::Image(image) => {
+                if resized {
+                    layout_image(&mut self.frame, size, image, self.fit, self.bg_color);
+                }
+            }
+            BackgroundSource::Video { path, decoder } => {
+                let spawn = |start| {
+                    VideoDecoder::spawn(&self.ffmpeg_path, path, &filter, self.fps, start)
+                        .map_err(|e| format!("Failed to start FFmpeg decoder: {}", e))
+                };
+
+                // The decoder outputs frames of a fixed size, so restart it when the
+                // notes area changes
+                let mut current = match decoder.take() {
+                    Some(current) if !resized => current,
+                    _ => spawn(start)?,
+                };
+
+                if let Err(e) = current.read_frame(&mut self.frame) {
+                    if e.kind() != ErrorKind::UnexpectedEof {
+                        return Err(format!("Failed to decode background video: {}", e));
+                    }
+                    // Seeking past the end of the video gives no frames, so start it over
+                    current = spawn(0.0)?;
+                    current
+                        .read_frame(&mut self.frame)
+                        .map_err(|e| format!("Failed to decode background video: {}", e))?;
+                }
+
+                *decoder = Some(current);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The FFmpeg filter which scales the video into the notes area
+    fn video_filter(&self) -> String {
+        let (w, h) = self.size;
+        match self.fit {
+            BackgroundFit::Fit => {
+                let [r, g, b] = self.bg_color;
+                format!(
+                    "scale={w}:{h}:force_original_aspect_ratio=decrease,\
+                    pad={w}:{h}:(ow-iw)/2:(oh-ih)/2:color=0x{r:02x}{g:02x}{b:02x}"
+                )
+            }
+            // Tiling is only supported for images
+            BackgroundFit::Fill | BackgroundFit::Tile => {
+                format!("scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h}")
+            }
+        }
+    }
+}
+
+/// Draws the image into a BGRA frame of the given size, over the background color
+fn layout_image(
+    frame: &mut [u8],
+    size: (u32, u32),
+    image: &RgbaImage,
+    fit: BackgroundFit,
+    bg_color: [u8; 3],
+) {
+    let scaled;
+    let (image, offset) = match fit {
+        BackgroundFit::Tile => (image, (0, 0)),
+        fit => {
+            let [w, h] = fit.scaled_size(
+                [image.width() as f32, image.height() as f32],
+                [size.0 as f32, size.1 as f32],
+            );
+            let (w, h) = ((w.round() as u32).max(1), (h.round() as u32).max(1));
+            scaled = imageops::resize(image, w, h, imageops::FilterType::Triangle);
+            let offset = (
+                (size.0 as i64 - w as i64) / 2,
+                (size.1 as i64 - h as i64) / 2,
+            );
+            (&scaled, offset)
+        }
+    };
+
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let (ix, iy) = (x as i64 - offset.0, y as i64 - offset.1);
+            let color = if fit == BackgroundFit::Tile {
+                Some(image.get_pixel(x % image.width(), y % image.height()))
+            } else if (0..image.width() as i64).contains(&ix)
+                && (0..image.height() as i64).contains(&iy)
+            {
+                Some(image.get_pixel(ix as u32, iy as u32))
+            } else {
+                None
+            };
+
+            let [r, g, b] = match color {
+                Some(color) => {
+                    let [r, g, b, a] = color.0;
+                    let blend = |c: u8, bg: u8| {
+                        ((c as u32 * a as u32 + bg as u32 * (255 - a as u32)) / 255) as u8
+                    };
+                    [
+                        blend(r, bg_color[0]),
+                        blend(g, bg_color[1]),
+                        blend(b, bg_color[2]),
+                    ]
+                }
+                None => bg_color,
+            };
+
+            let i = ((y * size.0 + x) * 4) as usize;
+            frame[i..i + 4].copy_from_slice(&[b, g, r, 255]);
+        }
+    }
+}
@@ -331,7 +331,7 @@ fn writer_thread_fn(
     Ok(())
 }
 
-fn configure_command(cmd: &mut Command) {
+pub(super) fn configure_command(cmd: &mut Command) {
     #[cfg(windows)]
     {
         use std::os::windows::process::CommandExt;
@@ -3,12 +3,53 @@
 //! This module provides a CPU-based keyboard renderer that draws directly to a pixel buffer.
 //! It replicates the exact visual style of the original egui-based keyboard renderer.
 
+use std::collections::HashSet;
+
 use super::utils::{
     calculate_border_width, darken_color, draw_gradient_rect, draw_solid_rect, lerp_u8,
     lighten_color,
 };
 use crate::gui::window::keyboard_layout::{KeyPosition, KeyboardView};
 use crate::midi::MIDIColor;
+use crate::settings::{KeyStyle, KeyboardSettings, ScrollDirection};
+
+// Black key lookup table for efficient key type checking
+// Pattern: C C# D D# E F F# G G# A A# B
+const BLACK_KEY_PATTERN: [bool; 12] = [
+    false, true, false, true, false, // C, C#, D, D#, E
+    false, true, false, true, false, true, false, // F, F#, G, G#, A, A#, B
+];
+
+/// Check if a MIDI key is black (inline for performance)
+#[inline(always)]
+fn is_black_key(key: usize) -> bool {
+    BLACK_KEY_PATTERN[key % 12]
+}
+
+/// The black keys to redraw over the static keyboard: the pressed ones and the ones
+/// next to a pressed white key, which covers part of them
+pub fn dirty_black_keys(key_colors: &[Option<MIDIColor>]) -> HashSet<usize> {
+    let mut dirty_keys = HashSet::new();
+
+    for (i, color) in key_colors.iter().enumerate() {
+        if color.is_some() {
+            if is_black_key(i) {
+                dirty_keys.insert(i);
+            } else {
+                // White key pressed: mark neighbors if they are black
+                if i > 0 && is_black_key(i - 1) {
+                    dirty_keys.insert(i - 1);
+                }
+                // Note: keys_len is usually 128, but strictly we check i < 127
+                if i < 127 && is_black_key(i + 1) {
+                    dirty_keys.insert(i + 1);
+                }
+            }
+        }
+    }
+
+    dirty_keys
+}
 
 /// Render the static part of the keyboard (background + all keys in unpressed state)
 pub fn render_static_keyboard(
@@ -18,6 +59,7 @@ pub fn render_static_keyboard(
     keyboard_height: u32,
     key_view: &KeyboardView,
     bar_color: [u8; 4], // BGRA
+    keyboard: &KeyboardSettings,
 ) {
     let rect_top = height - keyboard_height;
     let rect_bottom = height;
@@ -36,44 +78,65 @@ pub fn render_static_keyboard(
 
     // Helper to map x coordinate
     let map_x = |num: f32| (num * rect_width) as i32;
+    let flat = keyboard.key_style == KeyStyle::Flat;
 
     // Draw white keys (unpressed)
     for (_, key) in key_view.iter_visible_keys() {
         if !key.black {
-            draw_white_key_exact(
-                buffer,
-                width,
-                height,
-                &key,
-                None, // Force unpressed
-                top,
-                bottom,
-                black_key_overlap,
-                md_height,
-                key_border,
-                &map_x,
-            );
+            if flat {
+                draw_flat_key(
+                    buffer, width, height, &key, None, top, bottom, key_border, &map_x,
+                );
+            } else {
+                draw_white_key_exact(
+                    buffer,
+                    width,
+                    height,
+                    &key,
+                    None, // Force unpressed
+                    top,
+                    bottom,
+                    black_key_overlap,
+                    md_height,
+                    key_border,
+                    &map_x,
+                );
+            }
         }
     }
 
     // Draw black keys (unpressed) - NOW included in static buffer
-    let black_bottom = rect_bottom as f32 - rect_height * 0.34;
+    let black_bottom = rect_top as f32 + rect_height * keyboard.black_key_length;
     for (_, key) in key_view.iter_visible_keys() {
         if key.black {
-            draw_black_key_exact(
-                buffer,
-                width,
-                height,
-                &key,
-                None, // Unpressed color
-                top,
-                black_bottom,
-                black_key_overlap,
-                md_height,
-                key_border,
-                &map_x,
-                None, // No bar fix needed for unpressed keys
-            );
+            if flat {
+                draw_flat_key(
+                    buffer,
+                    width,
+                    height,
+                    &key,
+                    None,
+                    top,
+                    black_bottom,
+                    key_border,
+                    &map_x,
+                );
+            } else {
+                draw_black_key_exact(
+                    buffer,
+                    width,
+                    height,
+                    &key,
+                    None, // Unpressed color
+                    top,
+                    black_bottom,
+                    black_key_overlap,
+                    md_height,
+                    key_border,
+                    &map_x,
+                    None, // No bar fix needed for unpressed keys
+                );
+            }
         }
     }
 
@@ -90,6 +153,48 @@ pub fn render_static_keyboard(
     );
 }
 
+/// Copy a keyboard rendered for the downward direction (keys along the x axis, notes
+/// above it) into the frame, rotated to face the notes of the given direction.
+/// The upward keyboard is at the top, the horizontal one on the left with the lowest
+/// key at the bottom.
+pub fn blit_keyboard(
+    target: &mut [u8],
+    width: u32,
+    height: u32,
+    keyboard: &[u8],
+    keys_length: u32,
+    keyboard_size: u32,
+    direction: ScrollDirection,
+) {
+    let row_len = (keys_length * 4) as usize;
+    let rows = keyboard.chunks_exact(row_len).take(keyboard_size as usize);
+
+    match direction {
+        ScrollDirection::Down => {
+            let start = ((height - keyboard_size) * width * 4) as usize;
+            let len = (target.len() - start).min(keyboard.len());
+            target[start..start + len].copy_from_slice(&keyboard[..len]);
+        }
+        ScrollDirection::Up => {
+            for (y, row) in rows.enumerate() {
+                let start = (keyboard_size as usize - 1 - y) * row_len;
+                target[start..start + row_len].copy_from_slice(row);
+            }
+        }
+        ScrollDirection::Horizontal => {
+            let keys_length = keys_length.min(height) as usize;
+            for (y, row) in rows.enumerate() {
+                let target_x = keyboard_size as usize - 1 - y;
+                for (x, pixel) in row.chunks_exact(4).take(keys_length).enumerate() {
+                    let target_y = height as usize - 1 - x;
+                    let idx = (target_y * width as usize + target_x) * 4;
+                    target[idx..idx + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+}
+
 pub fn render_pressed_keys(
     buffer: &mut [u8],
     width: u32,
@@ -97,8 +202,9 @@ pub fn render_pressed_keys(
     keyboard_height: u32,
     key_view: &KeyboardView,
     key_colors: &[Option<MIDIColor>],
-    dirty_black_keys: &std::collections::HashSet<usize>,
+    dirty_black_keys: &HashSet<usize>,
     bar_color: [u8; 4], // BGRA, needed to fix black key gap
+    keyboard: &KeyboardSettings,
 ) {
     let rect_top = height - keyboard_height;
     let rect_bottom = height;
@@ -114,15 +220,30 @@ pub fn render_pressed_keys(
     let black_key_overlap = bar / 2.35;
     let top = rect_top as f32 + bar;
     let bottom = rect_bottom as f32;
-    let black_bottom = rect_bottom as f32 - rect_height * 0.34;
+    let black_bottom = rect_top as f32 + rect_height * keyboard.black_key_length;
 
     // Helper to map x coordinate
     let map_x = |num: f32| (num * rect_width) as i32;
+    let flat = keyboard.key_style == KeyStyle::Flat;
 
     // Draw PRESSED white keys only
     for (i, key) in key_view.iter_visible_keys() {
         if !key.black {
             if let Some(color) = key_colors.get(i).and_then(|c| *c) {
+                if flat {
+                    draw_flat_key(
+                        buffer,
+                        width,
+                        height,
+                        &key,
+                        Some(color),
+                        top,
+                        bottom,
+                        key_border,
+                        &map_x,
+                    );
+                    continue;
+                }
                 draw_white_key_exact(
                     buffer,
                     width,
@@ -145,6 +266,20 @@ pub fn render_pressed_keys(
         if key.black {
             if dirty_black_keys.contains(&i) {
                 let color = key_colors.get(i).and_then(|c| *c);
+                if flat {
+                    draw_flat_key(
+                        buffer,
+                        width,
+                        height,
+                        &key,
+                        color,
+                        top,
+                        black_bottom,
+                        key_border,
+                        &map_x,
+                    );
+                    continue;
+                }
                 let bar_fix = if color.is_some() {
                     Some(bar_color)
                 } else {
@@ -170,6 +305,46 @@ pub fn render_pressed_keys(
     }
 }
 
+/// Draw a key of the flat style as a single solid rectangle
+fn draw_flat_key<F: Fn(f32) -> i32>(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    key: &KeyPosition,
+    color: Option<MIDIColor>,
+    top: f32,
+    bottom: f32,
+    key_border: f32,
+    map_x: &F,
+) {
+    let left = map_x(key.left);
+    let right = map_x(key.right);
+    let top_i = top as i32;
+    let bottom_i = bottom as i32;
+
+    let base = match color {
+        Some(c) => (c.red(), c.green(), c.blue()),
+        None if key.black => (20, 20, 20),
+        None => (255, 255, 255),
+    };
+    draw_solid_rect(buffer, width, height, left, right, top_i, bottom_i, base);
+
+    if !key.black {
+        // White key right border
+        let border_left = right - key_border as i32;
+        draw_solid_rect(
+            buffer,
+            width,
+            height,
+            border_left,
+            right,
+            top_i,
+            bottom_i,
+            (40, 40, 40),
+        );
+    }
+}
+
 /// Draw white key with exact original styling
 fn draw_white_key_exact<F: Fn(f32) -> i32>(
     buffer: &mut [u8],
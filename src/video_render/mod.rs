@@ -3,11 +3,14 @@
 //! This module provides the core functionality for rendering MIDI playback
 //! to video files using FFmpeg.
 
+pub mod background;
 pub mod ffmpeg_encoder;
+pub mod grid;
 pub mod keyboard_renderer;
 pub mod offscreen_renderer;
 pub mod overlay_renderer;
 pub mod render_loop;
+pub mod screenshot;
 pub mod text_renderer;
 pub mod utils;
 
@@ -4,7 +4,7 @@
 //! allowing MIDI visualization to be rendered directly to buffers for video encoding.
 
 use crate::gui::window::stats::GuiMidiStats;
-use std::collections::{HashSet, VecDeque};
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 use vulkano::{
@@ -14,8 +14,8 @@ use vulkano::{
         CopyImageToBufferInfo,
     },
     device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures,
-        Queue, QueueCreateInfo, QueueFlags,
+        Device, DeviceCreateInfo, DeviceExtensions, DeviceFeatures, Queue, QueueCreateInfo,
+        QueueFlags,
     },
     format::Format,
     image::{view::ImageView, Image, ImageCreateInfo, ImageUsage},
@@ -26,22 +26,14 @@ use vulkano::{
 };
 
 use crate::gui::window::keyboard_layout::{KeyboardLayout, KeyboardParams};
+use crate::gui::window::scene::effects_system::EffectsRenderer;
 use crate::gui::window::scene::note_list_system::NoteRenderer;
 use crate::midi::MIDIFile;
-use crate::settings::WasabiSettings;
-
-// Black key lookup table for efficient key type checking
-// Pattern: C C# D D# E F F# G G# A A# B
-const BLACK_KEY_PATTERN: [bool; 12] = [
-    false, true, false, true, false,  // C, C#, D, D#, E
-    false, true, false, true, false, true, false,  // F, F#, G, G#, A, A#, B
-];
-
-/// Check if a MIDI key is black (inline for performance)
-#[inline(always)]
-fn is_black_key(key: usize) -> bool {
-    BLACK_KEY_PATTERN[key % 12]
-}
+use crate::renderer::devices::{select_gpu, GpuInfo};
+use crate::settings::{GpuPreference, KeyboardSettings, ScrollDirection, WasabiSettings};
+
+use super::background::VideoBackground;
+use super::grid::{draw_grid_under, fill_under, GridLinePosition};
 
 /// Offscreen renderer for generating video frames
 pub struct OffscreenRenderer {
@@ -55,9 +47,11 @@ pub struct OffscreenRenderer {
 
     // Note renderer
     note_renderer: NoteRenderer,
+    effects: EffectsRenderer,
 
     // Keyboard layout
     keyboard_layout: KeyboardLayout,
+    keyboard_params: KeyboardParams,
 
     // Dimensions
     width: u32,
@@ -67,14 +61,27 @@ pub struct OffscreenRenderer {
     nps_history: VecDeque<(f64, u64)>,
     // Keyboard Cache
     static_keyboard_buffer: Vec<u8>,
-    last_cache_params: Option<(usize, usize, u32, u32, [u8; 4])>, // start_key, end_key, width, height, bar_color
+    last_cache_params: Option<(
+        usize,
+        usize,
+        u32,
+        u32,
+        [u8; 4],
+        ScrollDirection,
+        KeyboardSettings,
+    )>, // start_key, end_key, width, height, bar_color, direction, keyboard
+    // The keyboard of the current frame, before it is rotated into the frame
+    keyboard_frame_buffer: Vec<u8>,
     // Overlay Cache
     overlay_cache: super::overlay_renderer::OverlayCache,
+    // Image or video drawn behind the notes
+    background: Option<VideoBackground>,
 }
 
 impl OffscreenRenderer {
-    /// Create a new offscreen renderer with the specified dimensions
-    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+    /// Create a new offscreen renderer with the specified dimensions, on the given GPU
+    /// or on the most performant one if it's `None`
+    pub fn new(width: u32, height: u32, gpu: Option<&GpuPreference>) -> Result<Self, String> {
         // Initialize Vulkan without a window
         let library =
             VulkanLibrary::new().map_err(|e| format!("Failed to load Vulkan library: {}", e))?;
@@ -94,7 +101,7 @@ impl OffscreenRenderer {
             ..DeviceFeatures::empty()
         };
 
-        let (physical_device, queue_family_index) = instance
+        let candidates = instance
             .enumerate_physical_devices()
             .map_err(|e| format!("Failed to enumerate physical devices: {}", e))?
             .filter(|p| p.supported_features().geometry_shader)
@@ -104,16 +111,10 @@ impl OffscreenRenderer {
                     .enumerate()
                     .position(|(_, q)| q.queue_flags.contains(QueueFlags::GRAPHICS))
                     .map(|i| (p, i as u32))
-            })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-                _ => 5,
-            })
-            .ok_or("No suitable GPU found with geometry shader support")?;
+            });
+        let (physical_device, queue_family_index) =
+            select_gpu(candidates, gpu, |(p, _)| GpuInfo::from_device(p))
+                .ok_or("No suitable GPU found with geometry shader support")?;
 
         println!(
             "[OffscreenRenderer] Using device: {} (type: {:?})",
@@ -178,9 +179,11 @@ impl OffscreenRenderer {
 
         // Create NoteRenderer using the new offscreen constructor
         let note_renderer = NoteRenderer::new_offscreen(device.clone(), queue.clone(), format);
+        let effects = EffectsRenderer::new(device.clone(), queue.clone(), format);
 
         // Create keyboard layout
-        let keyboard_layout = KeyboardLayout::new(&KeyboardParams::default());
+        let keyboard_params = KeyboardParams::default();
+        let keyboard_layout = KeyboardLayout::new(&keyboard_params);
 
         Ok(Self {
             device,
@@ -189,16 +192,25 @@ impl OffscreenRenderer {
             render_image,
             staging_buffer,
             note_renderer,
+            effects,
             keyboard_layout,
+            keyboard_params,
             width,
             height,
             nps_history: VecDeque::new(),
             static_keyboard_buffer: Vec::new(),
             last_cache_params: None,
+            keyboard_frame_buffer: Vec::new(),
             overlay_cache: super::overlay_renderer::OverlayCache::new(),
+            background: None,
         })
     }
 
+    /// Sets the image or video drawn behind the notes, instead of the background color
+    pub fn set_background(&mut self, background: Option<VideoBackground>) {
+        self.background = background;
+    }
+
     /// Render a frame into the provided buffer (BGRA format)
     /// The buffer is cleared and filled with new frame data
     pub fn render_frame_into(
@@ -209,9 +221,18 @@ impl OffscreenRenderer {
         settings: &WasabiSettings,
         current_time: f64,
     ) -> Result<(), String> {
+        // Rebuild the keyboard layout if its settings changed
+        let keyboard = settings.scene.keyboard;
+        let keyboard_params = KeyboardParams::from(&keyboard);
+        if keyboard_params != self.keyboard_params {
+            self.keyboard_layout = KeyboardLayout::new(&keyboard_params);
+            self.keyboard_params = keyboard_params;
+        }
+
         // Get keyboard view directly to avoid borrow conflict
-        let first_key = *settings.scene.key_range.start() as usize;
-        let last_key = *settings.scene.key_range.end() as usize;
+        let key_range = settings.scene.visible_key_range(midi_file.key_range());
+        let first_key = *key_range.start() as usize;
+        let last_key = *key_range.end() as usize;
         let key_view = self.keyboard_layout.get_view_for_keys(first_key, last_key);
 
         // Get bar color from settings
@@ -219,15 +240,28 @@ impl OffscreenRenderer {
         let bar_color = [bar.b(), bar.g(), bar.r(), bar.a()]; // BGRA
 
         // Update Static Keyboard Cache if needed
-        let cache_key = (first_key, last_key, self.width, self.height, bar_color);
+        let direction = settings.scene.scroll_direction;
+        let cache_key = (
+            first_key,
+            last_key,
+            self.width,
+            self.height,
+            bar_color,
+            direction,
+            keyboard,
+        );
         let cache_valid = self.last_cache_params.map_or(false, |p| p == cache_key);
 
+        // The keyboard is rendered along the keys and rotated into the frame afterwards
+        let [keys_length, time_length] = direction.orient([self.width, self.height]);
+
         // Calculate keyboard height
-        let keyboard_height = (11.6 / key_view.visible_range.len() as f32 * self.width as f32)
-            .min(self.height as f32 / 2.0);
+        let keyboard_height =
+            (11.6 / key_view.visible_range.len() as f32 * keys_length as f32 * keyboard.height)
+                .min(time_length as f32 / 2.0);
 
         // Calculate buffer size for keyboard area only
-        let keyboard_buffer_size = (self.width * (keyboard_height as u32) * 4) as usize;
+        let keyboard_buffer_size = (keys_length * (keyboard_height as u32) * 4) as usize;
 
         if !cache_valid {
             // Resize buffer to fit ONLY the keyboard area
@@ -240,11 +274,12 @@ impl OffscreenRenderer {
             // This aligns the drawing to the top of our cache buffer (which corresponds to rect_top in full frame)
             super::keyboard_renderer::render_static_keyboard(
                 &mut self.static_keyboard_buffer,
-                self.width,
+                keys_length,
                 keyboard_height as u32, // Treat height as just the keyboard height
                 keyboard_height as u32,
                 &key_view,
                 bar_color,
+                &keyboard,
             );
 
             self.last_cache_params = Some(cache_key);
@@ -254,24 +289,37 @@ impl OffscreenRenderer {
             );
         }
 
-        let notes_height = self.height as f32 - keyboard_height;
+        // Whole pixels, so the composited background meets the blitted keyboard
+        let notes_height = (time_length - keyboard_height as u32) as f32;
 
         // Adjust view_range to account for keyboard taking up part of the screen
-        let adjusted_view_range = view_range * (notes_height as f64 / self.height as f64);
+        let adjusted_view_range = view_range * (notes_height as f64 / time_length as f64);
 
         // Get background color from settings
+        // The notes are cleared to transparent when they are composited over a background
+        // or over the grid lines
+        let grid = &settings.scene.grid;
         let bg = settings.scene.bg_color;
-        let bg_color = Some([
-            (bg.r() as f32 / 255.0).powf(2.2),
-            (bg.g() as f32 / 255.0).powf(2.2),
-            (bg.b() as f32 / 255.0).powf(2.2),
-            bg.a() as f32 / 255.0,
-        ]);
+        let bg_color = (self.background.is_none() && !grid.enabled).then(|| {
+            [
+                (bg.r() as f32 / 255.0).powf(2.2),
+                (bg.g() as f32 / 255.0).powf(2.2),
+                (bg.b() as f32 / 255.0).powf(2.2),
+                bg.a() as f32 / 255.0,
+            ]
+        });
 
         // Create viewport for notes (excluding keyboard area)
+        let (notes_offset, notes_extent) = match direction {
+            ScrollDirection::Down => ([0.0, 0.0], [self.width as f32, notes_height]),
+            ScrollDirection::Up => ([0.0, keyboard_height], [self.width as f32, notes_height]),
+            ScrollDirection::Horizontal => {
+                ([keyboard_height, 0.0], [notes_height, self.height as f32])
+            }
+        };
         let viewport = vulkano::pipeline::graphics::viewport::Viewport {
-            offset: [0.0, 0.0],
-            extent: [self.width as f32, notes_height],
+            offset: notes_offset,
+            extent: notes_extent,
             depth_range: 0.0..=1.0,
         };
 
@@ -282,9 +330,22 @@ impl OffscreenRenderer {
             midi_file,
             adjusted_view_range,
             bg_color,
-            Some(viewport),
+            Some(viewport.clone()),
+            &settings.scene,
         );
 
+        // Key effects over the notes, driven by the frame time so the output is reproducible
+        if settings.scene.effects.is_enabled() {
+            self.effects.draw(
+                self.render_image.clone(),
+                &key_view,
+                current_time,
+                &result.key_hits,
+                Some(viewport),
+                &settings.scene,
+            );
+        }
+
         // Copy image to staging buffer
         let mut builder = AutoCommandBufferBuilder::primary(
             self.cb_allocator.clone(),
@@ -325,50 +386,74 @@ impl OffscreenRenderer {
         target_buffer.clear();
         target_buffer.extend_from_slice(&buffer_content);
 
-        // Blit static keyboard from cache
-        // The cache now ONLY contains the keyboard area.
-        let start_y = (self.height as f32 - keyboard_height) as u32;
-        let start_idx = (start_y * self.width * 4) as usize;
-
-        if start_idx < target_buffer.len() {
-            let target_slice = &mut target_buffer[start_idx..];
-            let source_slice = &self.static_keyboard_buffer;
-            // Ensure we don't overflow (shouldn't handle resizing race conditions, but safety first)
-            let len = target_slice.len().min(source_slice.len());
-            target_slice[..len].copy_from_slice(&source_slice[..len]);
-        }
+        let offset = (notes_offset[0] as u32, notes_offset[1] as u32);
+        let size = (notes_extent[0] as u32, notes_extent[1] as u32);
 
-        // Calculate dirty black keys (Optimization)
-        // We only redraw black keys if they are pressed OR if a neighbor white key is pressed
-        let mut dirty_keys = HashSet::new();
-
-        for (i, color) in result.key_colors.iter().enumerate() {
-            if color.is_some() {
-                if is_black_key(i) {
-                    dirty_keys.insert(i);
-                } else {
-                    // White key pressed: mark neighbors if they are black
-                    if i > 0 && is_black_key(i - 1) {
-                        dirty_keys.insert(i - 1);
-                    }
-                    // Note: keys_len is usually 128, but strictly we check i < 127
-                    if i < 127 && is_black_key(i + 1) {
-                        dirty_keys.insert(i + 1);
-                    }
-                }
+        if grid.enabled {
+            if let Some(beat_grid) = midi_file.beat_grid() {
+                let end = current_time + adjusted_view_range;
+                let lines: Vec<GridLinePosition> = beat_grid
+                    .lines_between(current_time, end)
+                    .iter()
+                    .map(|line| {
+                        let progress = (line.time - current_time) / adjusted_view_range;
+                        (progress as f32 * notes_height, line.bar)
+                    })
+                    .collect();
+                draw_grid_under(
+                    target_buffer,
+                    self.width,
+                    offset,
+                    size,
+                    direction,
+                    &lines,
+                    grid,
+                );
             }
         }
 
+        if let Some(background) = self.background.as_mut() {
+            background.composite(target_buffer, self.width, offset, size)?;
+        } else if grid.enabled {
+            fill_under(
+                target_buffer,
+                self.width,
+                offset,
+                size,
+                [bg.r(), bg.g(), bg.b()],
+            );
+        }
+
+        // Start the keyboard of this frame from the static keyboard cache
+        self.keyboard_frame_buffer.clear();
+        self.keyboard_frame_buffer
+            .extend_from_slice(&self.static_keyboard_buffer);
+
+        // Only the black keys which are pressed or next to a pressed white key are redrawn
+        let dirty_keys = super::keyboard_renderer::dirty_black_keys(&result.key_colors);
+
         // Render pressed keys on top
         super::keyboard_renderer::render_pressed_keys(
-            target_buffer,
-            self.width,
-            self.height,
+            &mut self.keyboard_frame_buffer,
+            keys_length,
+            keyboard_height as u32,
             keyboard_height as u32,
             &key_view,
             &result.key_colors,
             &dirty_keys,
             bar_color, // Pass bar color for black key gap fixing
+            &keyboard,
+        );
+
+        // Blit the keyboard into the frame, facing the notes
+        super::keyboard_renderer::blit_keyboard(
+            target_buffer,
+            self.width,
+            self.height,
+            &self.keyboard_frame_buffer,
+            keys_length,
+            keyboard_height as u32,
+            direction,
         );
 
         // Calculate NPS using history
@@ -410,7 +495,9 @@ impl OffscreenRenderer {
             target_buffer,
             self.width,
             self.height,
-            midi_file,
+            (notes_offset[0] as i32, notes_offset[1] as i32),
+            midi_file.midi_length().unwrap_or(0.0),
+            &file_stats,
             current_time,
             &stats,
             nps,
@@ -1,7 +1,7 @@
 use super::text_renderer;
 use super::utils::draw_solid_rect_alpha;
 use crate::gui::window::stats::GuiMidiStats;
-use crate::midi::MIDIFile;
+use crate::midi::MIDIFileStats;
 use crate::settings::{Statistics, WasabiSettings};
 use crate::utils::convert_seconds_to_time_string;
 
@@ -92,11 +92,14 @@ impl OverlayCache {
 
 
 /// Draw the overlay statistics
+/// `notes_origin` is the top left corner of the notes area, which the stats are kept in
 pub fn draw_overlay(
     buffer: &mut [u8],
     width: u32,
     height: u32,
-    midi_file: &mut impl MIDIFile,
+    notes_origin: (i32, i32),
+    midi_len: f64,
+    note_stats: &MIDIFileStats,
     current_time: f64,
     stats: &GuiMidiStats,
     nps: u64,
@@ -115,12 +118,10 @@ pub fn draw_overlay(
         0
     };
     let panel_height = 0;
-    let x = pad;
-    let y = panel_height + pad;
+    let x = notes_origin.0 + pad;
+    let y = notes_origin.1 + panel_height + pad;
 
-    let midi_len = midi_file.midi_length().unwrap_or(0.0);
     let time_passed = current_time.min(midi_len).max(0.0);
-    let note_stats = midi_file.stats();
     
     let passed_notes = note_stats.passed_notes.unwrap_or(0);
     let total_notes = note_stats.total_notes.unwrap_or(0);
@@ -9,8 +9,9 @@ use time::Duration;
 
 use crate::audio_playback::WasabiAudioPlayer;
 use crate::gui::window::render_state::RenderProgress;
-use crate::midi::{LiveLoadMIDIFile, MIDIFileBase};
+use crate::midi::{LiveLoadMIDIFile, LoadProgress, MIDIFileBase};
 
+use super::background::VideoBackground;
 use super::ffmpeg_encoder::FFmpegEncoder;
 use super::offscreen_renderer::OffscreenRenderer;
 use super::RenderConfig;
@@ -36,26 +37,39 @@ fn run_render_loop(config: RenderConfig, progress: RenderProgress) -> Result<(),
     );
 
     // Initialize offscreen renderer
-    let mut renderer = OffscreenRenderer::new(width, height)
+    let export_device = config.settings.gui.export_device.as_ref();
+    let mut renderer = OffscreenRenderer::new(width, height, export_device)
         .map_err(|e| format!("Failed to create offscreen renderer: {}", e))?;
 
     println!("[RenderLoop] Offscreen renderer initialized");
 
+    let background = VideoBackground::new(&config.settings.scene, &config.ffmpeg_path, fps)
+        .map_err(|e| format!("Failed to load background: {}", e))?;
+    renderer.set_background(background);
+
     // Create a silent audio player
     let silent_player = WasabiAudioPlayer::empty();
 
     // Load MIDI file
-    let mut midi_file =
-        LiveLoadMIDIFile::load_from_file(&config.midi_path, silent_player, &config.settings.midi)
-            .map_err(|e| format!("Failed to load MIDI: {:?}", e))?;
+    let mut midi_file = LiveLoadMIDIFile::load_from_file(
+        &config.midi_path,
+        silent_player,
+        &config.settings.midi,
+        LoadProgress::new(),
+    )
+    .map_err(|e| format!("Failed to load MIDI: {:?}", e))?;
 
     println!("[RenderLoop] MIDI file loaded");
 
     // Wait for MIDI length to be parsed (can take a long time for huge MIDIs)
+    // The grid lines are read right after it, so they are shown from the first frame
+    let grid_enabled = config.settings.scene.grid.enabled;
     let mut wait_count = 0;
     let midi_length = loop {
         if let Some(len) = midi_file.midi_length() {
-            break len;
+            if !grid_enabled || midi_file.beat_grid().is_some() {
+                break len;
+            }
         }
         thread::sleep(std::time::Duration::from_millis(100));
         wait_count += 1;
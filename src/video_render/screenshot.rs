@@ -0,0 +1,461 @@
+//! Still frames of the scene, at the window size or at any other resolution
+//!
+//! The notes of the loaded layers are drawn on the GPU of the window with the key
+//! view of the window, so the screenshot shows what is on the screen. They are
+//! drawn by renderers of their own, which don't take part in the hit tracking of
+//! the key effects in the window. The background, the keyboard and the statistics
+//! are then composited in a background thread, like in the video export.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use time::OffsetDateTime;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyImageToBufferInfo,
+    },
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{self, GpuFuture},
+};
+
+use crate::gui::window::keyboard_layout::KeyboardView;
+use crate::gui::window::scene::{GuiRenderScene, RenderResultData};
+use crate::gui::window::stats::GuiMidiStats;
+use crate::gui::window::{GuiMessageSystem, WasabiError};
+use crate::gui::GuiRenderer;
+use crate::midi::{MIDIFileBase, MIDIFileGroup, MIDIFileStats};
+use crate::settings::{ScrollDirection, WasabiSettings};
+
+use super::background::VideoBackground;
+use super::grid::{draw_grid_under, fill_under, GridLinePosition};
+use super::keyboard_renderer;
+use super::overlay_renderer::{draw_overlay, OverlayCache};
+
+pub struct ScreenshotConfig {
+    pub output_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub settings: WasabiSettings,
+}
+
+impl ScreenshotConfig {
+    /// A screenshot saved in the screenshots folder, named after the MIDI and the
+    /// current date
+    pub fn new(midi_path: &Path, [width, height]: [u32; 2], settings: WasabiSettings) -> Self {
+        let folder = settings
+            .gui
+            .screenshot
+            .folder
+            .clone()
+            .unwrap_or_else(WasabiSettings::get_screenshots_dir);
+        let output_path = folder.join(screenshot_name(midi_path));
+
+        Self {
+            output_path,
+            width,
+            height,
+            settings,
+        }
+    }
+}
+
+fn screenshot_name(midi_path: &Path) -> String {
+    let stem = midi_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "wasabi".to_owned());
+    let now = OffsetDateTime::now_utc();
+
+    format!(
+        "{stem} {}-{:02}-{:02} {:02}-{:02}-{:02}.png",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+    )
+}
+
+/// Draws the notes of the current frame of `midi` with the key view of the window,
+/// then composites and saves the screenshot in a background thread, reporting
+/// errors to the GUI. `nps` is the value shown by the statistics of the window.
+pub fn take_screenshot(
+    renderer: &GuiRenderer,
+    midi: &mut MIDIFileGroup,
+    key_view: &KeyboardView,
+    nps: u64,
+    config: ScreenshotConfig,
+    errors: Arc<GuiMessageSystem>,
+) {
+    let screenshot = match RenderedScreenshot::render(renderer, midi, key_view, nps, config) {
+        Ok(screenshot) => screenshot,
+        Err(e) => {
+            errors.error(&WasabiError::ScreenshotError(e));
+            return;
+        }
+    };
+
+    thread::spawn(move || match screenshot.save() {
+        Ok(path) => println!("[Screenshot] Saved {:?}", path),
+        Err(e) => errors.error(&WasabiError::ScreenshotError(e)),
+    });
+}
+
+/// The parts of a screenshot which need the window: the notes of each layer, read
+/// back from the GPU, and the keyboard with the keys they press
+struct RenderedScreenshot {
+    config: ScreenshotConfig,
+    /// The notes of each layer (BGRA or RGBA), the first one at the bottom
+    layers: Vec<Subbuffer<[u8]>>,
+    rgba: bool,
+    notes_offset: (u32, u32),
+    notes_size: (u32, u32),
+    grid_lines: Vec<GridLinePosition>,
+    keyboard: Vec<u8>,
+    keys_length: u32,
+    keyboard_height: u32,
+    time: f64,
+    midi_length: f64,
+    file_stats: MIDIFileStats,
+    stats: GuiMidiStats,
+    nps: u64,
+}
+
+impl RenderedScreenshot {
+    fn render(
+        renderer: &GuiRenderer,
+        midi: &mut MIDIFileGroup,
+        key_view: &KeyboardView,
+        nps: u64,
+        config: ScreenshotConfig,
+    ) -> Result<Self, String> {
+        let settings = &config.settings;
+        let (width, height) = (config.width, config.height);
+        let time = midi.timer().get_time().as_seconds_f64();
+
+        // The same layout as the window, at the size of the screenshot
+        let direction = settings.scene.scroll_direction;
+        let keyboard = settings.scene.keyboard;
+        let [keys_length, time_length] = direction.orient([width, height]);
+        let keyboard_height =
+            (11.6 / key_view.visible_range.len() as f32 * keys_length as f32 * keyboard.height)
+                .min(time_length as f32 / 2.0) as u32;
+        let notes_length = time_length - keyboard_height;
+        let (notes_offset, notes_size) = match direction {
+            ScrollDirection::Down => ((0, 0), (width, notes_length)),
+            ScrollDirection::Up => ((0, keyboard_height), (width, notes_length)),
+            ScrollDirection::Horizontal => ((keyboard_height, 0), (notes_length, height)),
+        };
+
+        let allocator = Arc::new(StandardMemoryAllocator::new_default(
+            renderer.device.clone(),
+        ));
+        let mut layers = Vec::new();
+        let mut result: Option<RenderResultData> = None;
+
+        // The layers are drawn on top of each other, the first one at the bottom
+        for layer in midi.layers_mut() {
+            let target = LayerTarget::new(renderer, &allocator, notes_size)?;
+            let layer_result = GuiRenderScene::new(renderer).draw_into(
+                renderer,
+                target.image.clone(),
+                key_view,
+                &mut layer.file,
+                settings.scene.note_speed,
+                &settings.scene,
+            );
+            layers.push(target);
+
+            result = Some(match result.take() {
+                Some(below) => below.merge_under(layer_result),
+                None => layer_result,
+            });
+        }
+        let result = result.ok_or("The MIDI has no layers")?;
+        let layers = read_back(renderer, layers)?;
+
+        let grid_lines = match midi.beat_grid() {
+            Some(beat_grid) if settings.scene.grid.enabled => {
+                let view_range = settings.scene.note_speed;
+                beat_grid
+                    .lines_between(time, time + view_range)
+                    .iter()
+                    .map(|line| {
+                        let progress = (line.time - time) / view_range;
+                        (progress as f32 * notes_length as f32, line.bar)
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        // The keyboard is rendered along the keys, and rotated into the frame later
+        let bar = settings.scene.bar_color;
+        let bar_color = [bar.b(), bar.g(), bar.r(), bar.a()];
+        let mut keyboard_frame = vec![0; (keys_length * keyboard_height * 4) as usize];
+        keyboard_renderer::render_static_keyboard(
+            &mut keyboard_frame,
+            keys_length,
+            keyboard_height,
+            keyboard_height,
+            key_view,
+            bar_color,
+            &keyboard,
+        );
+        keyboard_renderer::render_pressed_keys(
+            &mut keyboard_frame,
+            keys_length,
+            keyboard_height,
+            keyboard_height,
+            key_view,
+            &result.key_colors,
+            &keyboard_renderer::dirty_black_keys(&result.key_colors),
+            bar_color,
+            &keyboard,
+        );
+
+        let mut stats = GuiMidiStats::empty();
+        stats.set_rendered_note_count(result.notes_rendered);
+        stats.set_polyphony(result.polyphony);
+
+        Ok(Self {
+            rgba: matches!(
+                renderer.format,
+                Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM
+            ),
+            layers,
+            notes_offset,
+            notes_size,
+            grid_lines,
+            keyboard: keyboard_frame,
+            keys_length,
+            keyboard_height,
+            time,
+            midi_length: midi.midi_length().unwrap_or(0.0),
+            file_stats: midi.stats(),
+            stats,
+            nps,
+            config,
+        })
+    }
+
+    /// Composites the frame and saves it, returning the path it was saved at
+    fn save(self) -> Result<PathBuf, String> {
+        let frame = self.composite()?;
+        let config = self.config;
+
+        // The frame is BGRA, and saved without transparency
+        let rgb = frame
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+            .collect();
+        let image = image::RgbImage::from_raw(config.width, config.height, rgb)
+            .ok_or("The rendered frame doesn't match the screenshot size")?;
+
+        if let Some(folder) = config.output_path.parent() {
+            std::fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+        }
+        image.save(&config.output_path).map_err(|e| e.to_string())?;
+        Ok(config.output_path)
+    }
+
+    fn composite(&self) -> Result<Vec<u8>, String> {
+        let settings = &self.config.settings;
+        let (width, height) = (self.config.width, self.config.height);
+        let (offset, size) = (self.notes_offset, self.notes_size);
+        let mut frame = vec![0; (width * height * 4) as usize];
+
+        for layer in self.layers.iter() {
+            let notes = layer
+                .read()
+                .map_err(|e| format!("Failed to read screenshot buffer: {}", e))?;
+            blend_notes(&mut frame, width, &notes, offset, size, self.rgba);
+        }
+
+        let grid = &settings.scene.grid;
+        if grid.enabled {
+            let direction = settings.scene.scroll_direction;
+            draw_grid_under(
+                &mut frame,
+                width,
+                offset,
+                size,
+                direction,
+                &self.grid_lines,
+                grid,
+            );
+        }
+
+        // A background video is at the frame it would be at in an exported video,
+        // which starts it with the start delay
+        let ffmpeg_path = settings.gui.ffmpeg_path.clone().unwrap_or("ffmpeg".into());
+        let background = VideoBackground::new(&settings.scene, &ffmpeg_path, 60)
+            .map_err(|e| format!("Failed to load background: {}", e))?;
+        let bg = settings.scene.bg_color;
+        match background {
+            Some(mut background) => {
+                background.start_at(self.time + settings.midi.start_delay);
+                background.composite(&mut frame, width, offset, size)?;
+            }
+            None => fill_under(&mut frame, width, offset, size, [bg.r(), bg.g(), bg.b()]),
+        }
+
+        keyboard_renderer::blit_keyboard(
+            &mut frame,
+            width,
+            height,
+            &self.keyboard,
+            self.keys_length,
+            self.keyboard_height,
+            settings.scene.scroll_direction,
+        );
+
+        if settings.gui.screenshot.statistics {
+            draw_overlay(
+                &mut frame,
+                width,
+                height,
+                (offset.0 as i32, offset.1 as i32),
+                self.midi_length,
+                &self.file_stats,
+                self.time,
+                &self.stats,
+                self.nps,
+                settings,
+                &mut OverlayCache::new(),
+            );
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Blends the notes of a layer over the area of the frame (BGRA) at `offset`, which
+/// holds the layers below
+fn blend_notes(
+    frame: &mut [u8],
+    frame_width: u32,
+    notes: &[u8],
+    offset: (u32, u32),
+    size: (u32, u32),
+    rgba: bool,
+) {
+    let row_len = size.0 as usize * 4;
+    for (y, row) in notes
+        .chunks_exact(row_len)
+        .take(size.1 as usize)
+        .enumerate()
+    {
+        let start = ((offset.1 as usize + y) * frame_width as usize + offset.0 as usize) * 4;
+        let Some(target) = frame.get_mut(start..start + row_len) else {
+            break;
+        };
+
+        for (pixel, note) in target.chunks_exact_mut(4).zip(row.chunks_exact(4)) {
+            let note = if rgba {
+                [note[2], note[1], note[0], note[3]]
+            } else {
+                [note[0], note[1], note[2], note[3]]
+            };
+            // The notes are drawn over a transparent clear color, so their color is
+            // already multiplied by their alpha
+            let transparency = 255 - note[3] as u32;
+            for c in 0..4 {
+                pixel[c] = (note[c] as u32 + pixel[c] as u32 * transparency / 255).min(255) as u8;
+            }
+        }
+    }
+}
+
+/// The image the notes of a layer are drawn into, in the format of the window, and
+/// the buffer they are read back through
+struct LayerTarget {
+    image: Arc<ImageView>,
+    buffer: Subbuffer<[u8]>,
+}
+
+impl LayerTarget {
+    fn new(
+        renderer: &GuiRenderer,
+        allocator: &Arc<StandardMemoryAllocator>,
+        size: (u32, u32),
+    ) -> Result<Self, String> {
+        let image = ImageView::new_default(
+            Image::new(
+                allocator.clone(),
+                ImageCreateInfo {
+                    extent: [size.0.max(1), size.1.max(1), 1],
+                    format: renderer.format,
+                    usage: ImageUsage::COLOR_ATTACHMENT
+                        | ImageUsage::SAMPLED
+                        | ImageUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                Default::default(),
+            )
+            .map_err(|e| format!("Failed to create screenshot image: {}", e))?,
+        )
+        .map_err(|e| format!("Failed to create screenshot image view: {}", e))?;
+
+        let buffer = Buffer::new_slice::<u8>(
+            allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (size.0.max(1) * size.1.max(1) * 4) as u64,
+        )
+        .map_err(|e| format!("Failed to create screenshot buffer: {}", e))?;
+
+        Ok(Self { image, buffer })
+    }
+}
+
+/// Copies the images of all the layers into their buffers, in a single submission
+fn read_back(
+    renderer: &GuiRenderer,
+    layers: Vec<LayerTarget>,
+) -> Result<Vec<Subbuffer<[u8]>>, String> {
+    let cb_allocator = Arc::new(StandardCommandBufferAllocator::new(
+        renderer.device.clone(),
+        Default::default(),
+    ));
+    let mut builder = AutoCommandBufferBuilder::primary(
+        cb_allocator,
+        renderer.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .map_err(|e| format!("Failed to create command buffer: {}", e))?;
+
+    for layer in layers.iter() {
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                layer.image.image().clone(),
+                layer.buffer.clone(),
+            ))
+            .map_err(|e| format!("Failed to copy image to buffer: {}", e))?;
+    }
+    let command_buffer = builder
+        .build()
+        .map_err(|e| format!("Failed to build command buffer: {}", e))?;
+
+    sync::now(renderer.device.clone())
+        .then_execute(renderer.queue.clone(), command_buffer)
+        .map_err(|e| format!("Failed to execute command buffer: {}", e))?
+        .then_signal_fence_and_flush()
+        .map_err(|e| format!("Failed to signal fence: {}", e))?
+        .wait(None)
+        .map_err(|e| format!("Failed to wait for fence: {}", e))?;
+
+    Ok(layers.into_iter().map(|layer| layer.buffer).collect())
+}